@@ -1,4 +1,5 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
@@ -6,8 +7,22 @@ const CPU_CLOCK_HZ: u32 = 4_194_304;
 // 512 Hz frame sequencer tick (not doubled in CGB mode)
 const FRAME_SEQUENCER_PERIOD: u32 = 8192;
 const VOLUME_FACTOR: i16 = 64;
+/// Fraction of its charge the DMG output capacitor keeps per CPU cycle.
+const DMG_CHARGE_FACTOR: f64 = 0.999958;
+/// The CGB's capacitor discharges faster.
+const CGB_CHARGE_FACTOR: f64 = 0.998943;
+/// Wave RAM contents at power-on. The DMG's are effectively random; this is
+/// the pattern commonly observed. The CGB's alternate 0x00 and 0xFF.
+pub const DMG_WAVE_RAM: [u8; 0x10] = [
+    0x84, 0x40, 0x43, 0xAA, 0x2D, 0x78, 0x92, 0x3C, 0x60, 0x59, 0x59, 0xB0, 0x34, 0xB8, 0x2E, 0xDA,
+];
+pub const CGB_WAVE_RAM: [u8; 0x10] = [
+    0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF,
+];
+/// Number of recent raw outputs kept per channel for scope views.
+pub const SCOPE_LEN: usize = 512;
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 struct Envelope {
     initial: u8,
     period: u8,
@@ -40,7 +55,7 @@ impl Envelope {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 // Handles Channel 1 frequency sweep logic. See TODO.md #257.
 struct Sweep {
     period: u8,
@@ -49,32 +64,40 @@ struct Sweep {
     timer: u8,
     shadow: u16,
     enabled: bool,
+    /// A frequency was calculated in negate mode since the last trigger.
+    negate_used: bool,
 }
 
 impl Sweep {
-    fn calculate(&self) -> u16 {
+    fn calculate(&mut self) -> u16 {
         let delta = self.shadow >> self.shift;
         if self.negate {
+            self.negate_used = true;
             self.shadow.wrapping_sub(delta)
         } else {
             self.shadow.wrapping_add(delta)
         }
     }
 
-    fn set_params(&mut self, val: u8) {
+    /// Write NR10. Returns false if the write disables the channel:
+    /// clearing negate mode after a negated calculation since the last
+    /// trigger turns channel 1 off.
+    fn set_params(&mut self, val: u8) -> bool {
         self.period = (val >> 4) & 0x07;
         self.negate = val & 0x08 != 0;
         self.shift = val & 0x07;
+        self.negate || !self.negate_used
     }
 
     fn reload(&mut self, freq: u16) {
         self.shadow = freq;
         self.timer = if self.period == 0 { 8 } else { self.period };
         self.enabled = self.period != 0 || self.shift != 0;
+        self.negate_used = false;
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 struct SquareChannel {
     enabled: bool,
     dac_enabled: bool,
@@ -168,7 +191,7 @@ impl SquareChannel {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 struct WaveChannel {
     enabled: bool,
     dac_enabled: bool,
@@ -179,6 +202,8 @@ struct WaveChannel {
     last_sample: u8,
     frequency: u16,
     timer: i32,
+    /// T-cycles since the channel last fetched from wave RAM.
+    since_fetch: u32,
 }
 
 impl WaveChannel {
@@ -191,6 +216,7 @@ impl WaveChannel {
             return;
         }
         let mut cycles = cycles as i32;
+        let mut fetched = false;
         while self.timer <= cycles {
             cycles -= self.timer;
             self.timer = self.period();
@@ -201,8 +227,14 @@ impl WaveChannel {
             } else {
                 byte & 0x0F
             };
+            fetched = true;
         }
         self.timer -= cycles;
+        self.since_fetch = if fetched {
+            cycles as u32
+        } else {
+            self.since_fetch.saturating_add(cycles as u32)
+        };
     }
 
     fn clock_length(&mut self) {
@@ -228,7 +260,7 @@ impl WaveChannel {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 struct NoiseChannel {
     enabled: bool,
     dac_enabled: bool,
@@ -306,7 +338,27 @@ impl FrameSequencer {
     }
 }
 
+/// Channel, register and filter state of the APU, used by savestates.
+/// Buffered output samples and the output sample rate are not included.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApuState {
+    ch1: SquareChannel,
+    ch2: SquareChannel,
+    ch3: WaveChannel,
+    ch4: NoiseChannel,
+    wave_ram: [u8; 0x10],
+    nr50: u8,
+    nr51: u8,
+    nr52: u8,
+    sequencer_step: u8,
+    seq_counter: u32,
+    sample_timer: u32,
+    hp_capacitor: [f32; 2],
+}
+
 pub struct Apu {
+    /// Emulate the CGB APU rather than the DMG's.
+    cgb: bool,
     ch1: SquareChannel,
     ch2: SquareChannel,
     ch3: WaveChannel,
@@ -317,13 +369,24 @@ pub struct Apu {
     nr52: u8,
     sequencer: FrameSequencer,
     seq_counter: u32,
+    /// Progress towards the next output sample, in 1/sample_rate CPU cycles.
     sample_timer: u32,
     sample_rate: u32,
     samples: VecDeque<i16>,
-    hp_prev_input_left: f32,
-    hp_prev_output_left: f32,
-    hp_prev_input_right: f32,
-    hp_prev_output_right: f32,
+    /// Recent raw 0-15 output of each channel, one entry per output sample.
+    scopes: [Vec<u8>; 4],
+    /// While paused no samples are generated and the stream plays silence.
+    paused: bool,
+    /// Apply the output capacitor's high-pass filter to the mix.
+    high_pass: bool,
+    /// Charge kept by the capacitor per output sample, derived from the
+    /// model's charge factor and the sample rate.
+    hp_charge: f32,
+    /// Charge of the left and right output capacitors.
+    hp_capacitor: [f32; 2],
+    /// Channels 1-4 included in the mix. Muting a channel does not affect
+    /// its emulated state.
+    audible: [bool; 4],
 }
 
 impl Apu {
@@ -364,18 +427,58 @@ impl Apu {
         self.nr50 = 0;
         self.nr51 = 0;
         self.samples.clear();
-        self.hp_prev_input_left = 0.0;
-        self.hp_prev_output_left = 0.0;
-        self.hp_prev_input_right = 0.0;
-        self.hp_prev_output_right = 0.0;
+        self.hp_capacitor = [0.0; 2];
     }
+
+    /// Capture the channel and register state for a savestate.
+    pub fn save_state(&self) -> ApuState {
+        ApuState {
+            ch1: self.ch1.clone(),
+            ch2: self.ch2.clone(),
+            ch3: self.ch3.clone(),
+            ch4: self.ch4.clone(),
+            wave_ram: self.wave_ram,
+            nr50: self.nr50,
+            nr51: self.nr51,
+            nr52: self.nr52,
+            sequencer_step: self.sequencer.step,
+            seq_counter: self.seq_counter,
+            sample_timer: self.sample_timer,
+            hp_capacitor: self.hp_capacitor,
+        }
+    }
+
+    /// Restore state captured by `save_state`. Samples not yet played are
+    /// dropped.
+    pub fn load_state(&mut self, state: &ApuState) {
+        self.ch1 = state.ch1.clone();
+        self.ch2 = state.ch2.clone();
+        self.ch3 = state.ch3.clone();
+        self.ch4 = state.ch4.clone();
+        self.wave_ram = state.wave_ram;
+        self.nr50 = state.nr50;
+        self.nr51 = state.nr51;
+        self.nr52 = state.nr52;
+        self.sequencer.step = state.sequencer_step;
+        self.seq_counter = state.seq_counter;
+        self.sample_timer = state.sample_timer;
+        self.hp_capacitor = state.hp_capacitor;
+        self.samples.clear();
+    }
+
     pub fn new() -> Self {
+        Self::new_with_mode(false)
+    }
+
+    /// Create an APU in the post-boot state of the selected hardware model.
+    pub fn new_with_mode(cgb: bool) -> Self {
         let mut apu = Self {
+            cgb,
             ch1: SquareChannel::new(true),
             ch2: SquareChannel::new(false),
             ch3: WaveChannel::default(),
             ch4: NoiseChannel::default(),
-            wave_ram: [0; 0x10],
+            wave_ram: if cgb { CGB_WAVE_RAM } else { DMG_WAVE_RAM },
             nr50: 0x77,
             nr51: 0xF3,
             nr52: 0xF1,
@@ -384,11 +487,14 @@ impl Apu {
             sample_timer: 0,
             sample_rate: 44100,
             samples: VecDeque::with_capacity(4096),
-            hp_prev_input_left: 0.0,
-            hp_prev_output_left: 0.0,
-            hp_prev_input_right: 0.0,
-            hp_prev_output_right: 0.0,
+            scopes: std::array::from_fn(|_| Vec::with_capacity(SCOPE_LEN * 2)),
+            paused: false,
+            high_pass: true,
+            hp_charge: 0.0,
+            hp_capacitor: [0.0; 2],
+            audible: [true; 4],
         };
+        apu.update_hp_charge();
 
         // Initialize channels to power-on register defaults
         apu.ch1.duty = 2;
@@ -490,13 +596,10 @@ impl Apu {
             0xFF23 => (self.ch4.length_enable as u8) << 6,
             0xFF24 => self.nr50,
             0xFF25 => self.nr51,
-            0xFF30..=0xFF3F => {
-                if self.ch3.enabled && self.ch3.dac_enabled {
-                    0xFF
-                } else {
-                    self.wave_ram[(addr - 0xFF30) as usize]
-                }
-            }
+            0xFF30..=0xFF3F => match self.wave_ram_index(addr) {
+                Some(i) => self.wave_ram[i],
+                None => 0xFF,
+            },
             _ => 0xFF,
         };
 
@@ -509,8 +612,10 @@ impl Apu {
         }
         match addr {
             0xFF10 => {
-                if let Some(s) = self.ch1.sweep.as_mut() {
-                    s.set_params(val);
+                if let Some(s) = self.ch1.sweep.as_mut()
+                    && !s.set_params(val)
+                {
+                    self.ch1.enabled = false;
                 }
             }
             0xFF11 => {
@@ -526,7 +631,11 @@ impl Apu {
             }
             0xFF13 => self.ch1.frequency = (self.ch1.frequency & 0x700) | val as u16,
             0xFF14 => {
+                let was_enabled = self.ch1.length_enable;
                 self.ch1.length_enable = val & 0x40 != 0;
+                if !was_enabled && self.ch1.length_enable && self.length_clock_skipped() {
+                    self.ch1.clock_length();
+                }
                 self.ch1.frequency = (self.ch1.frequency & 0xFF) | (((val & 0x07) as u16) << 8);
                 if val & 0x80 != 0 {
                     self.trigger_square(1);
@@ -545,7 +654,11 @@ impl Apu {
             }
             0xFF18 => self.ch2.frequency = (self.ch2.frequency & 0x700) | val as u16,
             0xFF19 => {
+                let was_enabled = self.ch2.length_enable;
                 self.ch2.length_enable = val & 0x40 != 0;
+                if !was_enabled && self.ch2.length_enable && self.length_clock_skipped() {
+                    self.ch2.clock_length();
+                }
                 self.ch2.frequency = (self.ch2.frequency & 0xFF) | (((val & 0x07) as u16) << 8);
                 if val & 0x80 != 0 {
                     self.trigger_square(2);
@@ -556,7 +669,11 @@ impl Apu {
             0xFF1C => self.ch3.volume = (val >> 5) & 0x03,
             0xFF1D => self.ch3.frequency = (self.ch3.frequency & 0x700) | val as u16,
             0xFF1E => {
+                let was_enabled = self.ch3.length_enable;
                 self.ch3.length_enable = val & 0x40 != 0;
+                if !was_enabled && self.ch3.length_enable && self.length_clock_skipped() {
+                    self.ch3.clock_length();
+                }
                 self.ch3.frequency = (self.ch3.frequency & 0xFF) | (((val & 0x07) as u16) << 8);
                 if val & 0x80 != 0 {
                     self.trigger_wave();
@@ -580,7 +697,11 @@ impl Apu {
                 self.ch4.divisor = val & 0x07;
             }
             0xFF23 => {
+                let was_enabled = self.ch4.length_enable;
                 self.ch4.length_enable = val & 0x40 != 0;
+                if !was_enabled && self.ch4.length_enable && self.length_clock_skipped() {
+                    self.ch4.clock_length();
+                }
                 if val & 0x80 != 0 {
                     self.trigger_noise();
                 }
@@ -596,60 +717,97 @@ impl Apu {
                 }
             }
             0xFF30..=0xFF3F => {
-                if !(self.ch3.enabled && self.ch3.dac_enabled) {
-                    self.wave_ram[(addr - 0xFF30) as usize] = val;
+                if let Some(i) = self.wave_ram_index(addr) {
+                    self.wave_ram[i] = val;
                 }
             }
             _ => {}
         }
     }
 
+    /// Wave RAM byte accessed through `addr`. While channel 3 plays, the CPU
+    /// reaches the byte the channel is playing instead; the DMG only allows
+    /// that in the cycle the channel fetches it and blocks access otherwise.
+    fn wave_ram_index(&self, addr: u16) -> Option<usize> {
+        if !(self.ch3.enabled && self.ch3.dac_enabled) {
+            return Some((addr - 0xFF30) as usize);
+        }
+        (self.cgb || self.ch3.since_fetch == 0).then_some(self.ch3.position as usize / 2)
+    }
+
+    /// Returns true if the next frame sequencer step does not clock the
+    /// length counters. Enabling length during such a step clocks it once
+    /// immediately, and a trigger reloading an empty counter loads one less.
+    fn length_clock_skipped(&self) -> bool {
+        self.sequencer.step & 1 == 1
+    }
+
     fn trigger_square(&mut self, idx: u8) {
+        let skipped = self.length_clock_skipped();
         let ch = if idx == 1 {
             &mut self.ch1
         } else {
             &mut self.ch2
         };
-        ch.enabled = true;
+        // A channel whose DAC is off cannot be enabled by a trigger.
+        ch.enabled = ch.dac_enabled;
         ch.duty_pos = 0;
         ch.timer = ch.period();
         ch.envelope.volume = ch.envelope.initial;
-        if idx == 1 {
-            if let Some(s) = ch.sweep.as_mut() {
-                s.reload(ch.frequency);
-                if s.shift != 0 {
-                    let new_freq = s.calculate();
-                    if new_freq > 2047 {
-                        ch.enabled = false;
-                        s.enabled = false;
-                    } else {
-                        s.shadow = new_freq;
-                        ch.frequency = new_freq;
-                    }
-                }
+        if idx == 1
+            && let Some(s) = ch.sweep.as_mut()
+        {
+            s.reload(ch.frequency);
+            // With a non-zero shift the trigger runs the overflow check, but
+            // the result is not written back until the sweep is clocked.
+            if s.shift != 0 && s.calculate() > 2047 {
+                ch.enabled = false;
+                s.enabled = false;
             }
         }
         if ch.length == 0 {
-            ch.length = 64;
+            ch.length = if ch.length_enable && skipped { 63 } else { 64 };
         }
     }
 
     fn trigger_wave(&mut self) {
-        self.ch3.enabled = true;
+        let skipped = self.length_clock_skipped();
+        // Retriggering a playing DMG channel just as it fetches corrupts the
+        // start of wave RAM with the bytes around the one being fetched.
+        if !self.cgb && self.ch3.enabled && self.ch3.dac_enabled && self.ch3.timer <= 2 {
+            let next = ((self.ch3.position as usize + 1) & 0x1F) / 2;
+            if next < 4 {
+                self.wave_ram[0] = self.wave_ram[next];
+            } else {
+                let block = next & !3;
+                self.wave_ram.copy_within(block..block + 4, 0);
+            }
+        }
+        self.ch3.enabled = self.ch3.dac_enabled;
         self.ch3.position = 0;
         self.ch3.timer = self.ch3.period();
+        self.ch3.since_fetch = u32::MAX;
         if self.ch3.length == 0 {
-            self.ch3.length = 256;
+            self.ch3.length = if self.ch3.length_enable && skipped {
+                255
+            } else {
+                256
+            };
         }
     }
 
     fn trigger_noise(&mut self) {
-        self.ch4.enabled = true;
+        let skipped = self.length_clock_skipped();
+        self.ch4.enabled = self.ch4.dac_enabled;
         self.ch4.lfsr = 0x7FFF;
         self.ch4.timer = self.ch4.period();
         self.ch4.envelope.volume = self.ch4.envelope.initial;
         if self.ch4.length == 0 {
-            self.ch4.length = 64;
+            self.ch4.length = if self.ch4.length_enable && skipped {
+                63
+            } else {
+                64
+            };
         }
     }
 
@@ -670,7 +828,14 @@ impl Apu {
         }
     }
 
+    #[cfg_attr(
+        feature = "profiling",
+        tracing::instrument(name = "Apu::step", skip_all)
+    )]
     pub fn step(&mut self, cycles: u16) {
+        if self.paused {
+            return;
+        }
         let cycles = cycles as u32;
         self.seq_counter += cycles;
         while self.seq_counter >= FRAME_SEQUENCER_PERIOD {
@@ -682,21 +847,58 @@ impl Apu {
         self.ch2.step(cycles);
         self.ch3.step(cycles, &self.wave_ram);
         self.ch4.step(cycles);
-        self.sample_timer += cycles;
-        let cps = CPU_CLOCK_HZ / self.sample_rate;
-        while self.sample_timer >= cps {
-            self.sample_timer -= cps;
+        // Count in units of 1/sample_rate CPU cycles so the average rate is
+        // exact even when it does not divide the CPU clock.
+        let mut timer = self.sample_timer as u64 + cycles as u64 * self.sample_rate as u64;
+        while timer >= CPU_CLOCK_HZ as u64 {
+            timer -= CPU_CLOCK_HZ as u64;
+            self.record_scopes();
             let (left, right) = self.mix_output();
             self.samples.push_back(left);
             self.samples.push_back(right);
         }
+        self.sample_timer = timer as u32;
+        // Without a consumer (or one that fell behind) keep only the most
+        // recent second of audio.
+        let max = self.sample_rate as usize * 2;
+        if self.samples.len() > max {
+            self.samples.drain(..self.samples.len() - max);
+        }
+    }
+
+    fn record_scopes(&mut self) {
+        let outputs = [
+            self.ch1.output(),
+            self.ch2.output(),
+            self.ch3.output(),
+            self.ch4.output(),
+        ];
+        for (scope, out) in self.scopes.iter_mut().zip(outputs) {
+            // Trim in batches so the buffer stays contiguous and cheap to append.
+            if scope.len() == SCOPE_LEN * 2 {
+                scope.drain(..SCOPE_LEN);
+            }
+            scope.push(out);
+        }
+    }
+
+    /// Recent raw outputs (0-15) of channel `idx` (1-4), oldest first, at
+    /// most `SCOPE_LEN` entries. Useful for drawing oscilloscope views.
+    pub fn channel_scope(&self, idx: u8) -> &[u8] {
+        match self.scopes.get((idx as usize).wrapping_sub(1)) {
+            Some(scope) => &scope[scope.len().saturating_sub(SCOPE_LEN)..],
+            None => &[],
+        }
     }
 
     fn mix_output(&mut self) -> (i16, i16) {
-        let ch1 = self.ch1.output() as i16 - 8;
-        let ch2 = self.ch2.output() as i16 - 8;
-        let ch3 = self.ch3.output() as i16 - 8;
-        let ch4 = self.ch4.output() as i16 - 8;
+        let level = |out: u8, idx: usize| {
+            if self.audible[idx] { out as i16 - 8 } else { 0 }
+        };
+        let ch1 = level(self.ch1.output(), 0);
+        let ch2 = level(self.ch2.output(), 1);
+        let ch3 = level(self.ch3.output(), 2);
+        let ch4 = level(self.ch4.output(), 3);
 
         let mut left = 0i16;
         let mut right = 0i16;
@@ -732,110 +934,239 @@ impl Apu {
         let left_sample = left * left_vol as i16 * VOLUME_FACTOR;
         let right_sample = right * right_vol as i16 * VOLUME_FACTOR;
 
-        self.dc_block(left_sample, right_sample)
+        if !self.high_pass {
+            return (left_sample, right_sample);
+        }
+        (
+            self.high_pass_filter(0, left_sample),
+            self.high_pass_filter(1, right_sample),
+        )
+    }
+
+    /// Model the capacitor between the mixer and the amplifier: the output
+    /// is the input minus the capacitor's charge, which slowly follows the
+    /// input, so any constant offset decays to zero.
+    fn high_pass_filter(&mut self, side: usize, input: i16) -> i16 {
+        let input = input as f32;
+        let out = input - self.hp_capacitor[side];
+        self.hp_capacitor[side] = input - out * self.hp_charge;
+        out.round() as i16
+    }
+
+    fn update_hp_charge(&mut self) {
+        let factor = if self.cgb {
+            CGB_CHARGE_FACTOR
+        } else {
+            DMG_CHARGE_FACTOR
+        };
+        let cycles_per_sample = CPU_CLOCK_HZ as f64 / self.sample_rate as f64;
+        self.hp_charge = factor.powf(cycles_per_sample) as f32;
     }
 
-    fn dc_block(&mut self, left: i16, right: i16) -> (i16, i16) {
-        const DC_FILTER_R: f32 = 0.999;
-        let left_in = left as f32;
-        let right_in = right as f32;
-        let left_out = left_in - self.hp_prev_input_left + DC_FILTER_R * self.hp_prev_output_left;
-        let right_out =
-            right_in - self.hp_prev_input_right + DC_FILTER_R * self.hp_prev_output_right;
-        self.hp_prev_input_left = left_in;
-        self.hp_prev_output_left = left_out;
-        self.hp_prev_input_right = right_in;
-        self.hp_prev_output_right = right_out;
-        (left_out.round() as i16, right_out.round() as i16)
+    /// Enable or disable the high-pass filter that removes the DC offset
+    /// from the output, as the capacitor on real hardware does. On by
+    /// default.
+    pub fn set_high_pass(&mut self, enabled: bool) {
+        self.high_pass = enabled;
+        self.hp_capacitor = [0.0; 2];
+    }
+
+    pub fn high_pass_enabled(&self) -> bool {
+        self.high_pass
+    }
+
+    /// Include or mute channel `channel` (1-4) in the output mix. The
+    /// channel keeps running, so NR52 and its length counter are unaffected.
+    /// Other channel numbers are ignored.
+    pub fn set_channel_enabled(&mut self, channel: usize, on: bool) {
+        if let Some(audible) = self.audible.get_mut(channel.wrapping_sub(1)) {
+            *audible = on;
+        }
+    }
+
+    /// Whether channel `channel` (1-4) is included in the output mix.
+    pub fn channel_enabled(&self, channel: usize) -> bool {
+        self.audible
+            .get(channel.wrapping_sub(1))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// CGB PCM12 register: the current digital outputs of channel 2 (high
+    /// nibble) and channel 1 (low nibble).
+    pub fn pcm12(&self) -> u8 {
+        (self.ch2.output() << 4) | self.ch1.output()
+    }
+
+    /// CGB PCM34 register: the current digital outputs of channel 4 (high
+    /// nibble) and channel 3 (low nibble).
+    pub fn pcm34(&self) -> u8 {
+        (self.ch4.output() << 4) | self.ch3.output()
     }
 
     pub fn ch1_frequency(&self) -> u16 {
         self.ch1.frequency
     }
 
+    /// Pop the next buffered sample. Returns silence while paused so the
+    /// output stream does not underrun; buffered samples are kept for resume.
     pub fn pop_sample(&mut self) -> Option<i16> {
+        if self.paused {
+            return Some(0);
+        }
         self.samples.pop_front()
     }
 
+    /// Take every buffered sample, interleaved left/right, for consumers
+    /// other than the output stream such as a WAV writer.
+    pub fn drain_samples(&mut self) -> Vec<i16> {
+        self.samples.drain(..).collect()
+    }
+
+    /// Drop the oldest buffered samples so at most `max` remain, e.g. to
+    /// keep latency low while running faster than real time.
+    pub fn trim_samples(&mut self, max: usize) {
+        let excess = self.samples.len().saturating_sub(max);
+        self.samples.drain(..excess);
+    }
+
+    /// Pause or resume sample generation.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Return to the power-on state, keeping the output sample rate, the
+    /// pause setting, the high-pass filter setting and muted channels.
+    pub fn reset(&mut self) {
+        *self = Self {
+            sample_rate: self.sample_rate,
+            paused: self.paused,
+            high_pass: self.high_pass,
+            hp_charge: self.hp_charge,
+            audible: self.audible,
+            ..Self::new_with_mode(self.cgb)
+        };
+    }
+
+    /// Output sample rate in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Generate samples at `rate` Hz from now on. Samples buffered at the
+    /// old rate are dropped.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = rate.max(1);
+        self.sample_timer = 0;
+        self.samples.clear();
+        self.update_hp_charge();
+    }
+
+    /// Number of samples currently buffered for the output stream.
+    pub fn buffered_samples(&self) -> usize {
+        self.samples.len()
+    }
+
     pub fn sequencer_step(&self) -> u8 {
         self.sequencer.step
     }
 
-    pub fn start_stream(apu: Arc<Mutex<Self>>) -> cpal::Stream {
+    /// Open the default output device and stream samples from `apu` to it.
+    /// Returns `None` with a warning if no usable audio device is available,
+    /// so emulation can continue silently.
+    pub fn start_stream(apu: Arc<Mutex<Self>>) -> Option<cpal::Stream> {
         let host = cpal::default_host();
-        let device = host.default_output_device().expect("no output device");
-        let supported = device
-            .default_output_config()
-            .expect("no supported output config");
+        let Some(device) = host.default_output_device() else {
+            eprintln!("No audio output device, continuing without sound");
+            return None;
+        };
+        let supported = match device.default_output_config() {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("No supported audio output config ({e}), continuing without sound");
+                return None;
+            }
+        };
         let sample_format = supported.sample_format();
         let config: cpal::StreamConfig = supported.into();
         {
             let mut a = apu.lock().unwrap();
-            a.sample_rate = config.sample_rate.0;
+            a.set_sample_rate(config.sample_rate.0);
         }
         let channels = config.channels as usize;
         let err_fn = |err| eprintln!("cpal stream error: {err}");
 
         let stream = match sample_format {
-            cpal::SampleFormat::I16 => device
-                .build_output_stream(
-                    &config,
-                    move |data: &mut [i16], _| {
-                        let mut apu = apu.lock().unwrap();
-                        for frame in data.chunks_mut(channels) {
-                            let left = apu.pop_sample().unwrap_or(0);
-                            let right = apu.pop_sample().unwrap_or(0);
-                            frame[0] = left;
-                            if channels > 1 {
-                                frame[1] = right;
-                            }
+            cpal::SampleFormat::I16 => device.build_output_stream(
+                &config,
+                move |data: &mut [i16], _| {
+                    let mut apu = apu.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let left = apu.pop_sample().unwrap_or(0);
+                        let right = apu.pop_sample().unwrap_or(0);
+                        frame[0] = left;
+                        if channels > 1 {
+                            frame[1] = right;
                         }
-                    },
-                    err_fn,
-                    None,
-                )
-                .unwrap(),
-            cpal::SampleFormat::U16 => device
-                .build_output_stream(
-                    &config,
-                    move |data: &mut [u16], _| {
-                        let mut apu = apu.lock().unwrap();
-                        for frame in data.chunks_mut(channels) {
-                            let left = apu.pop_sample().unwrap_or(0);
-                            let right = apu.pop_sample().unwrap_or(0);
-                            frame[0] = (left as i32 + 32768) as u16;
-                            if channels > 1 {
-                                frame[1] = (right as i32 + 32768) as u16;
-                            }
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::U16 => device.build_output_stream(
+                &config,
+                move |data: &mut [u16], _| {
+                    let mut apu = apu.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let left = apu.pop_sample().unwrap_or(0);
+                        let right = apu.pop_sample().unwrap_or(0);
+                        frame[0] = (left as i32 + 32768) as u16;
+                        if channels > 1 {
+                            frame[1] = (right as i32 + 32768) as u16;
                         }
-                    },
-                    err_fn,
-                    None,
-                )
-                .unwrap(),
-            cpal::SampleFormat::F32 => device
-                .build_output_stream(
-                    &config,
-                    move |data: &mut [f32], _| {
-                        let mut apu = apu.lock().unwrap();
-                        for frame in data.chunks_mut(channels) {
-                            let left = apu.pop_sample().unwrap_or(0) as f32 / 32768.0;
-                            let right = apu.pop_sample().unwrap_or(0) as f32 / 32768.0;
-                            frame[0] = left;
-                            if channels > 1 {
-                                frame[1] = right;
-                            }
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::F32 => device.build_output_stream(
+                &config,
+                move |data: &mut [f32], _| {
+                    let mut apu = apu.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let left = apu.pop_sample().unwrap_or(0) as f32 / 32768.0;
+                        let right = apu.pop_sample().unwrap_or(0) as f32 / 32768.0;
+                        frame[0] = left;
+                        if channels > 1 {
+                            frame[1] = right;
                         }
-                    },
-                    err_fn,
-                    None,
-                )
-                .unwrap(),
-            _ => panic!("Unsupported sample format"),
+                    }
+                },
+                err_fn,
+                None,
+            ),
+            format => {
+                eprintln!("Unsupported audio sample format {format}, continuing without sound");
+                return None;
+            }
         };
 
-        stream.play().expect("failed to play stream");
-        stream
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Failed to open audio stream ({e}), continuing without sound");
+                return None;
+            }
+        };
+        if let Err(e) = stream.play() {
+            eprintln!("Failed to start audio stream ({e}), continuing without sound");
+            return None;
+        }
+        Some(stream)
     }
 }
 
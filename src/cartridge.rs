@@ -1,8 +1,48 @@
+use crate::{
+    huc3::{Huc3, IR_IDLE},
+    mbc7::{EEPROM_SIZE, Mbc7},
+    rtc::Rtc,
+};
+use serde::{Deserialize, Serialize};
 use std::{
     fs, io,
     path::{Path, PathBuf},
 };
 
+/// Nintendo logo bitmap stored in every cartridge header at 0x0104-0x0133
+/// and in the boot ROMs that check it.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+/// Number of camera registers mapped at 0xA000 when RAM bank bit 4 is set.
+const CAMERA_REG_COUNT: usize = 0x36;
+/// Offset in RAM bank 0 where the camera stores the captured image.
+const CAMERA_IMAGE_OFFSET: usize = 0x0100;
+/// Size of the captured 128x112 image in 2bpp tile format.
+const CAMERA_IMAGE_SIZE: usize = 16 * 14 * 16;
+
+/// Emulation status of each mapper family, as printed by `--list-mbc`.
+pub const MBC_SUPPORT: [(&str, &str); 12] = [
+    ("ROM only", "full"),
+    ("MBC1", "full"),
+    ("MBC2", "unsupported"),
+    ("MMM01", "unsupported"),
+    ("MBC3", "full"),
+    ("MBC30", "full"),
+    ("MBC5", "full"),
+    ("MBC6", "unsupported"),
+    ("MBC7", "full"),
+    (
+        "Pocket Camera",
+        "partial: no image sensor, captures a test pattern",
+    ),
+    ("TAMA5", "unsupported"),
+    ("HuC1/HuC3", "partial: no infrared link"),
+];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MbcType {
     NoMbc,
@@ -10,9 +50,44 @@ pub enum MbcType {
     Mbc3,
     Mbc30,
     Mbc5,
+    Mbc7,
+    PocketCamera,
+    HuC1,
+    HuC3,
     Unknown(u8),
 }
 
+/// Fields of the cartridge header at 0x0134-0x014F.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    /// 0x80 when the game supports CGB features, 0xC0 when it requires them.
+    pub cgb_flag: u8,
+    pub sgb_flag: bool,
+    /// Raw cartridge type byte at 0x0147.
+    pub cartridge_type: u8,
+    /// Mapper chosen for `cartridge_type`.
+    pub mbc: MbcType,
+    /// ROM size declared at 0x0148, in bytes.
+    pub rom_size: usize,
+    /// RAM size declared at 0x0149, in bytes.
+    pub ram_size: usize,
+    /// Two-character new licensee code, or the old licensee byte in hex.
+    pub licensee: String,
+    pub header_checksum_valid: bool,
+    /// Big-endian checksum at 0x014E. Hardware never checks it.
+    pub global_checksum: u16,
+}
+
+/// Mapper registers, RAM and clock of a cartridge, used by savestates. The
+/// ROM itself is not included.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CartridgeState {
+    ram: Vec<u8>,
+    mbc_state: MbcState,
+    rtc: Option<Rtc>,
+}
+
 #[derive(Debug)]
 pub struct Cartridge {
     pub rom: Vec<u8>,
@@ -23,9 +98,33 @@ pub struct Cartridge {
     cart_type: u8,
     save_path: Option<PathBuf>,
     mbc_state: MbcState,
+    /// MBC3 real-time clock, present on MBC3+TIMER cartridges.
+    rtc: Option<Rtc>,
 }
 
-#[derive(Debug)]
+impl std::str::FromStr for MbcType {
+    type Err = String;
+
+    /// Parse a mapper name as accepted by `--force-mbc`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" | "rom" => Ok(MbcType::NoMbc),
+            "mbc1" => Ok(MbcType::Mbc1),
+            "mbc3" => Ok(MbcType::Mbc3),
+            "mbc30" => Ok(MbcType::Mbc30),
+            "mbc5" => Ok(MbcType::Mbc5),
+            "mbc7" => Ok(MbcType::Mbc7),
+            "camera" => Ok(MbcType::PocketCamera),
+            "huc1" => Ok(MbcType::HuC1),
+            "huc3" => Ok(MbcType::HuC3),
+            _ => Err(format!(
+                "unknown mapper '{s}', expected one of none, mbc1, mbc3, mbc30, mbc5, mbc7, camera, huc1, huc3"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum MbcState {
     NoMbc,
     Mbc1 {
@@ -48,10 +147,73 @@ enum MbcState {
         rom_bank: u16,
         ram_bank: u8,
         ram_enable: bool,
+        /// Rumble motor state, driven by RAM bank bit 3 on rumble carts.
+        motor: bool,
     },
+    Mbc7(Mbc7),
+    PocketCamera {
+        rom_bank: u8,
+        ram_bank: u8,
+        ram_enable: bool,
+        regs: Vec<u8>,
+    },
+    HuC1 {
+        rom_bank: u8,
+        ram_bank: u8,
+        /// 0xA000-0xBFFF maps the infrared port instead of RAM.
+        ir_mode: bool,
+        ir_led: bool,
+    },
+    HuC3(Huc3),
     Unknown,
 }
 
+impl MbcState {
+    /// Power-on register state for `mbc`.
+    fn initial(mbc: MbcType) -> Self {
+        match mbc {
+            MbcType::NoMbc => MbcState::NoMbc,
+            MbcType::Mbc1 => MbcState::Mbc1 {
+                rom_bank: 1,
+                ram_bank: 0,
+                mode: 0,
+                ram_enable: false,
+            },
+            MbcType::Mbc3 => MbcState::Mbc3 {
+                rom_bank: 1,
+                ram_bank: 0,
+                ram_enable: false,
+            },
+            MbcType::Mbc30 => MbcState::Mbc30 {
+                rom_bank: 1,
+                ram_bank: 0,
+                ram_enable: false,
+            },
+            MbcType::Mbc5 => MbcState::Mbc5 {
+                rom_bank: 1,
+                ram_bank: 0,
+                ram_enable: false,
+                motor: false,
+            },
+            MbcType::Mbc7 => MbcState::Mbc7(Mbc7::default()),
+            MbcType::PocketCamera => MbcState::PocketCamera {
+                rom_bank: 1,
+                ram_bank: 0,
+                ram_enable: false,
+                regs: vec![0; CAMERA_REG_COUNT],
+            },
+            MbcType::HuC1 => MbcState::HuC1 {
+                rom_bank: 1,
+                ram_bank: 0,
+                ir_mode: false,
+                ir_led: false,
+            },
+            MbcType::HuC3 => MbcState::HuC3(Huc3::default()),
+            MbcType::Unknown(_) => MbcState::Unknown,
+        }
+    }
+}
+
 impl Cartridge {
     pub fn from_bytes_with_ram(data: Vec<u8>, ram_size: usize) -> Self {
         let mut c = Self::load(data);
@@ -72,6 +234,23 @@ impl Cartridge {
                     *d = *s;
                 }
             }
+            if cart.rtc.is_some()
+                && let Ok(bytes) = fs::read(save.with_extension("rtc"))
+            {
+                match Rtc::from_save_bytes(&bytes, unix_now()) {
+                    Some(rtc) => cart.rtc = Some(rtc),
+                    None => log::warn!("Ignoring RTC save of unexpected size {}", bytes.len()),
+                }
+            }
+            if let MbcState::HuC3(huc3) = &mut cart.mbc_state
+                && let Ok(bytes) = fs::read(save.with_extension("rtc"))
+                && !huc3.load_save_bytes(&bytes, unix_now())
+            {
+                log::warn!(
+                    "Ignoring HuC3 clock save of unexpected size {}",
+                    bytes.len()
+                );
+            }
         }
 
         println!(
@@ -92,30 +271,12 @@ impl Cartridge {
         let cgb = header.cgb_supported();
         let title = header.title();
 
-        let mbc_state = match mbc {
-            MbcType::NoMbc => MbcState::NoMbc,
-            MbcType::Mbc1 => MbcState::Mbc1 {
-                rom_bank: 1,
-                ram_bank: 0,
-                mode: 0,
-                ram_enable: false,
-            },
-            MbcType::Mbc3 => MbcState::Mbc3 {
-                rom_bank: 1,
-                ram_bank: 0,
-                ram_enable: false,
-            },
-            MbcType::Mbc30 => MbcState::Mbc30 {
-                rom_bank: 1,
-                ram_bank: 0,
-                ram_enable: false,
-            },
-            MbcType::Mbc5 => MbcState::Mbc5 {
-                rom_bank: 1,
-                ram_bank: 0,
-                ram_enable: false,
-            },
-            MbcType::Unknown(_) => MbcState::Unknown,
+        let mbc_state = MbcState::initial(mbc);
+        // MBC7 has no RAM; its EEPROM is kept in `ram` so it is saved.
+        let ram_size = if mbc == MbcType::Mbc7 {
+            EEPROM_SIZE
+        } else {
+            ram_size
         };
 
         Self {
@@ -127,6 +288,7 @@ impl Cartridge {
             cart_type,
             save_path: None,
             mbc_state,
+            rtc: matches!(cart_type, 0x0F | 0x10).then(Rtc::new),
         }
     }
 
@@ -182,10 +344,66 @@ impl Cartridge {
                 let offset = (*rom_bank as usize) * 0x4000 + (addr as usize - 0x4000);
                 self.rom.get(offset).copied().unwrap_or(0xFF)
             }
+            (MbcState::Mbc7(_), 0x0000..=0x3FFF) => {
+                self.rom.get(addr as usize).copied().unwrap_or(0xFF)
+            }
+            (MbcState::Mbc7(mbc7), 0x4000..=0x7FFF) => {
+                let offset = (mbc7.rom_bank as usize) * 0x4000 + (addr as usize - 0x4000);
+                self.rom.get(offset).copied().unwrap_or(0xFF)
+            }
+            (MbcState::Mbc7(mbc7), 0xA000..=0xBFFF) => mbc7.read(addr),
+            (MbcState::HuC1 { .. } | MbcState::HuC3(_), 0x0000..=0x3FFF) => {
+                self.rom.get(addr as usize).copied().unwrap_or(0xFF)
+            }
+            (MbcState::HuC1 { rom_bank, .. }, 0x4000..=0x7FFF)
+            | (MbcState::HuC3(Huc3 { rom_bank, .. }), 0x4000..=0x7FFF) => {
+                let offset = (*rom_bank as usize) * 0x4000 + (addr as usize - 0x4000);
+                self.rom.get(offset).copied().unwrap_or(0xFF)
+            }
+            (MbcState::HuC1 { ir_mode: true, .. }, 0xA000..=0xBFFF) => IR_IDLE,
+            (MbcState::HuC1 { .. }, 0xA000..=0xBFFF) => {
+                let idx = self.ram_index(addr);
+                self.ram.get(idx).copied().unwrap_or(0xFF)
+            }
+            (MbcState::HuC3(huc3), 0xA000..=0xBFFF) => huc3.read(addr, &self.ram),
+            (MbcState::PocketCamera { .. }, 0x0000..=0x3FFF) => {
+                self.rom.get(addr as usize).copied().unwrap_or(0xFF)
+            }
+            (MbcState::PocketCamera { rom_bank, .. }, 0x4000..=0x7FFF) => {
+                let offset = (*rom_bank as usize) * 0x4000 + (addr as usize - 0x4000);
+                self.rom.get(offset).copied().unwrap_or(0xFF)
+            }
             (MbcState::NoMbc, 0xA000..=0xBFFF) => {
                 let idx = self.ram_index(addr);
                 self.ram.get(idx).copied().unwrap_or(0xFF)
             }
+            (MbcState::PocketCamera { ram_bank, regs, .. }, 0xA000..=0xBFFF) => {
+                if *ram_bank & 0x10 != 0 {
+                    // Only the capture/status register is readable.
+                    if addr & 0x7F == 0 {
+                        regs[0] & 0x07
+                    } else {
+                        0x00
+                    }
+                } else {
+                    // Camera RAM stays readable even when writes are disabled.
+                    let idx = self.ram_index(addr);
+                    self.ram.get(idx).copied().unwrap_or(0xFF)
+                }
+            }
+            (
+                MbcState::Mbc3 {
+                    ram_enable: true,
+                    ram_bank: reg @ 0x08..=0x0C,
+                    ..
+                }
+                | MbcState::Mbc30 {
+                    ram_enable: true,
+                    ram_bank: reg @ 0x08..=0x0C,
+                    ..
+                },
+                0xA000..=0xBFFF,
+            ) => self.rtc.as_ref().map_or(0xFF, |rtc| rtc.read(*reg)),
             (MbcState::Mbc1 { ram_enable, .. }, 0xA000..=0xBFFF)
             | (MbcState::Mbc3 { ram_enable, .. }, 0xA000..=0xBFFF)
             | (MbcState::Mbc30 { ram_enable, .. }, 0xA000..=0xBFFF)
@@ -232,16 +450,14 @@ impl Cartridge {
                     ..
                 },
                 0xA000..=0xBFFF,
-            ) => {
-                if *ram_enable {
-                    let idx = if *mode == 0 {
-                        addr as usize - 0xA000
-                    } else {
-                        (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000
-                    };
-                    if let Some(b) = self.ram.get_mut(idx) {
-                        *b = val;
-                    }
+            ) if *ram_enable => {
+                let idx = if *mode == 0 {
+                    addr as usize - 0xA000
+                } else {
+                    (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000
+                };
+                if let Some(b) = self.ram.get_mut(idx) {
+                    *b = val;
                 }
             }
             (MbcState::Mbc3 { ram_enable, .. }, 0x0000..=0x1FFF)
@@ -260,11 +476,34 @@ impl Cartridge {
                     *rom_bank = 1;
                 }
             }
-            (MbcState::Mbc3 { ram_bank, .. }, 0x4000..=0x5FFF) => {
-                *ram_bank = val & 0x03;
+            // Values 0x08-0x0C select an RTC register instead of a RAM bank.
+            (
+                MbcState::Mbc3 { ram_bank, .. } | MbcState::Mbc30 { ram_bank, .. },
+                0x4000..=0x5FFF,
+            ) => {
+                *ram_bank = val & 0x0F;
+            }
+            (MbcState::Mbc3 { .. } | MbcState::Mbc30 { .. }, 0x6000..=0x7FFF) => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(val);
+                }
             }
-            (MbcState::Mbc30 { ram_bank, .. }, 0x4000..=0x5FFF) => {
-                *ram_bank = val & 0x07;
+            (
+                MbcState::Mbc3 {
+                    ram_enable: true,
+                    ram_bank: reg @ 0x08..=0x0C,
+                    ..
+                }
+                | MbcState::Mbc30 {
+                    ram_enable: true,
+                    ram_bank: reg @ 0x08..=0x0C,
+                    ..
+                },
+                0xA000..=0xBFFF,
+            ) => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write(*reg, val);
+                }
             }
             (
                 MbcState::Mbc3 {
@@ -273,12 +512,10 @@ impl Cartridge {
                     ..
                 },
                 0xA000..=0xBFFF,
-            ) => {
-                if *ram_enable {
-                    let idx = (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000;
-                    if let Some(b) = self.ram.get_mut(idx) {
-                        *b = val;
-                    }
+            ) if *ram_enable => {
+                let idx = (*ram_bank as usize & 0x03) * 0x2000 + addr as usize - 0xA000;
+                if let Some(b) = self.ram.get_mut(idx) {
+                    *b = val;
                 }
             }
             (
@@ -288,9 +525,75 @@ impl Cartridge {
                     ..
                 },
                 0xA000..=0xBFFF,
+            ) if *ram_enable => {
+                let idx = (*ram_bank as usize & 0x07) * 0x2000 + addr as usize - 0xA000;
+                if let Some(b) = self.ram.get_mut(idx) {
+                    *b = val;
+                }
+            }
+            (MbcState::Mbc7(mbc7), 0x0000..=0x7FFF) => mbc7.write_control(addr, val),
+            (MbcState::Mbc7(mbc7), 0xA000..=0xBFFF) => mbc7.write(addr, val, &mut self.ram),
+            (MbcState::HuC1 { ir_mode, .. }, 0x0000..=0x1FFF) => {
+                *ir_mode = val & 0x0F == 0x0E;
+            }
+            (MbcState::HuC1 { rom_bank, .. }, 0x2000..=0x3FFF) => {
+                *rom_bank = (val & 0x3F).max(1);
+            }
+            (MbcState::HuC1 { ram_bank, .. }, 0x4000..=0x5FFF) => {
+                *ram_bank = val & 0x03;
+            }
+            (
+                MbcState::HuC1 {
+                    ir_mode: true,
+                    ir_led,
+                    ..
+                },
+                0xA000..=0xBFFF,
             ) => {
-                if *ram_enable {
-                    let idx = (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000;
+                *ir_led = val & 0x01 != 0;
+            }
+            (MbcState::HuC1 { ram_bank, .. }, 0xA000..=0xBFFF) => {
+                let idx = (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000;
+                if let Some(b) = self.ram.get_mut(idx) {
+                    *b = val;
+                }
+            }
+            (MbcState::HuC3(huc3), 0x0000..=0x7FFF) => huc3.write_control(addr, val),
+            (MbcState::HuC3(huc3), 0xA000..=0xBFFF) => huc3.write(addr, val, &mut self.ram),
+            (MbcState::PocketCamera { ram_enable, .. }, 0x0000..=0x1FFF) => {
+                *ram_enable = val & 0x0F == 0x0A;
+            }
+            (MbcState::PocketCamera { rom_bank, .. }, 0x2000..=0x3FFF) => {
+                *rom_bank = val & 0x3F;
+                if *rom_bank == 0 {
+                    *rom_bank = 1;
+                }
+            }
+            (MbcState::PocketCamera { ram_bank, .. }, 0x4000..=0x5FFF) => {
+                *ram_bank = val & 0x1F;
+            }
+            (
+                MbcState::PocketCamera {
+                    ram_enable,
+                    ram_bank,
+                    regs,
+                    ..
+                },
+                0xA000..=0xBFFF,
+            ) => {
+                if *ram_bank & 0x10 != 0 {
+                    let reg = (addr & 0x7F) as usize;
+                    if reg < CAMERA_REG_COUNT {
+                        regs[reg] = val;
+                    }
+                    if reg == 0 && val & 0x01 != 0 {
+                        // No sensor is emulated: the capture completes
+                        // instantly with a test pattern.
+                        Self::write_camera_test_pattern(&mut self.ram);
+                        regs[0] &= !0x01;
+                    }
+                } else if *ram_enable {
+                    let idx = ((*ram_bank & 0x0F) as usize) * 0x2000 + addr as usize - 0xA000;
                     if let Some(b) = self.ram.get_mut(idx) {
                         *b = val;
                     }
@@ -305,8 +608,20 @@ impl Cartridge {
             (MbcState::Mbc5 { rom_bank, .. }, 0x3000..=0x3FFF) => {
                 *rom_bank = (*rom_bank & 0xFF) | (((val & 0x01) as u16) << 8);
             }
-            (MbcState::Mbc5 { ram_bank, .. }, 0x4000..=0x5FFF) => {
-                *ram_bank = val & 0x0F;
+            (
+                MbcState::Mbc5 {
+                    ram_bank, motor, ..
+                },
+                0x4000..=0x5FFF,
+            ) => {
+                // On rumble carts bit 3 drives the motor instead of
+                // selecting RAM.
+                if Self::type_has_rumble(self.cart_type) {
+                    *motor = val & 0x08 != 0;
+                    *ram_bank = val & 0x07;
+                } else {
+                    *ram_bank = val & 0x0F;
+                }
             }
             (
                 MbcState::Mbc5 {
@@ -315,12 +630,10 @@ impl Cartridge {
                     ..
                 },
                 0xA000..=0xBFFF,
-            ) => {
-                if *ram_enable {
-                    let idx = (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000;
-                    if let Some(b) = self.ram.get_mut(idx) {
-                        *b = val;
-                    }
+            ) if *ram_enable => {
+                let idx = (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000;
+                if let Some(b) = self.ram.get_mut(idx) {
+                    *b = val;
                 }
             }
             _ => {}
@@ -338,35 +651,275 @@ impl Cartridge {
                 }
             }
             MbcState::Mbc3 { ram_bank, .. } => {
-                (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000
+                (*ram_bank as usize & 0x03) * 0x2000 + addr as usize - 0xA000
             }
             MbcState::Mbc30 { ram_bank, .. } => {
-                (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000
+                (*ram_bank as usize & 0x07) * 0x2000 + addr as usize - 0xA000
             }
             MbcState::Mbc5 { ram_bank, .. } => {
                 (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000
             }
-            MbcState::Unknown => addr as usize - 0xA000,
+            MbcState::PocketCamera { ram_bank, .. } => {
+                ((*ram_bank & 0x0F) as usize) * 0x2000 + addr as usize - 0xA000
+            }
+            MbcState::HuC1 { ram_bank, .. } | MbcState::HuC3(Huc3 { ram_bank, .. }) => {
+                (*ram_bank as usize) * 0x2000 + addr as usize - 0xA000
+            }
+            MbcState::Mbc7(_) | MbcState::Unknown => addr as usize - 0xA000,
         }
     }
 
+    /// Guess the mapper the ROM actually uses by cross-checking the header
+    /// type byte against the ROM size, RAM size and logo. A mapper that
+    /// differs from the header is returned for the caller to report; the
+    /// other inconsistencies are logged as warnings. The cartridge itself is
+    /// left unchanged.
+    pub fn detect_mbc(&self) -> MbcType {
+        /// Largest ROM addressable by MBC1 and MBC3.
+        const MAX_MBC1_ROM: usize = 0x20_0000;
+        let rom_len = self.rom.len();
+        let detected = match self.mbc {
+            // Only a real ROM-only header; unknown types also map to NoMbc.
+            MbcType::NoMbc if self.cart_type == 0x00 && rom_len > 0x8000 => {
+                if rom_len > MAX_MBC1_ROM {
+                    MbcType::Mbc5
+                } else {
+                    MbcType::Mbc1
+                }
+            }
+            MbcType::Mbc1 | MbcType::Mbc3 if rom_len > MAX_MBC1_ROM => MbcType::Mbc5,
+            mbc => mbc,
+        };
+        let has_ram_type = matches!(
+            self.cart_type,
+            0x02 | 0x03
+                | 0x08
+                | 0x09
+                | 0x10
+                | 0x12
+                | 0x13
+                | 0x1A
+                | 0x1B
+                | 0x1D
+                | 0x1E
+                | 0x22
+                | 0xFC
+                | 0xFE
+                | 0xFF
+        );
+        if !self.ram.is_empty() && !has_ram_type && self.rom.len() >= 0x150 {
+            log::warn!(
+                "Header declares {} KB of RAM for a cartridge type without RAM",
+                self.ram.len() / 1024
+            );
+        }
+        if !self.logo_matches() {
+            log::warn!("Nintendo logo mismatch, the header may be corrupt");
+        }
+        detected
+    }
+
+    /// Replace the mapper chosen from the header, resetting its registers.
+    pub fn force_mbc(&mut self, mbc: MbcType) {
+        self.mbc = mbc;
+        self.mbc_state = MbcState::initial(mbc);
+    }
+
+    /// One-line description of what this cartridge's mapper is missing, or
+    /// `None` when it is fully emulated.
+    pub fn mbc_warning(&self) -> Option<String> {
+        let Some(family) = Self::mbc_family(self.cart_type) else {
+            return Some(format!(
+                "Unknown cartridge type {:02X}, running without a mapper",
+                self.cart_type
+            ));
+        };
+        let (_, status) = MBC_SUPPORT.iter().find(|(name, _)| *name == family)?;
+        match *status {
+            "full" => None,
+            "unsupported" => Some(format!(
+                "{family} is not emulated, running without a mapper; banking will not work"
+            )),
+            partial => Some(format!("{family} support is {partial}")),
+        }
+    }
+
+    /// The `MBC_SUPPORT` entry covering header cartridge type `cart_type`.
+    fn mbc_family(cart_type: u8) -> Option<&'static str> {
+        Some(match cart_type {
+            0x00 | 0x08 | 0x09 => "ROM only",
+            0x01..=0x03 => "MBC1",
+            0x05 | 0x06 => "MBC2",
+            0x0B..=0x0D => "MMM01",
+            0x0F..=0x13 => "MBC3",
+            0x19..=0x1E => "MBC5",
+            0x20 => "MBC6",
+            0x22 => "MBC7",
+            0xFC => "Pocket Camera",
+            0xFD => "TAMA5",
+            0xFE | 0xFF => "HuC1/HuC3",
+            _ => return None,
+        })
+    }
+
+    /// Advance the real-time clock, if the cartridge has one, by `cycles`
+    /// normal-speed system clock cycles.
+    pub fn tick_rtc(&mut self, cycles: u32) {
+        if let Some(rtc) = self.rtc.as_mut() {
+            rtc.tick(cycles);
+        }
+        if let MbcState::HuC3(huc3) = &mut self.mbc_state {
+            huc3.tick(cycles);
+        }
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    /// Whether the cartridge has a rumble motor.
+    pub fn has_rumble(&self) -> bool {
+        Self::type_has_rumble(self.cart_type)
+    }
+
+    fn type_has_rumble(cart_type: u8) -> bool {
+        matches!(cart_type, 0x1C..=0x1E)
+    }
+
+    /// Whether the rumble motor is currently switched on.
+    pub fn rumble_state(&self) -> bool {
+        matches!(self.mbc_state, MbcState::Mbc5 { motor: true, .. })
+    }
+
+    /// Set the accelerometer tilt of an MBC7 cartridge as signed offsets
+    /// from level on the X and Y axes. Ignored for other mappers.
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        if let MbcState::Mbc7(mbc7) = &mut self.mbc_state {
+            mbc7.set_tilt(x, y);
+        }
+    }
+
+    /// Return the mapper registers to their power-on state. RAM and the
+    /// clock are battery-backed and keep their contents.
+    pub fn reset(&mut self) {
+        match &mut self.mbc_state {
+            MbcState::HuC3(huc3) => huc3.reset(),
+            state => *state = MbcState::initial(self.mbc),
+        }
+    }
+
+    /// Capture the mapper registers, RAM and clock for a savestate.
+    pub fn save_state(&self) -> CartridgeState {
+        CartridgeState {
+            ram: self.ram.clone(),
+            mbc_state: self.mbc_state.clone(),
+            rtc: self.rtc.clone(),
+        }
+    }
+
+    /// Check that `state` fits this cartridge before `load_state` restores
+    /// it, so a corrupt savestate cannot leave out-of-range buffers behind.
+    pub fn check_state(&self, state: &CartridgeState) -> Result<(), String> {
+        if state.ram.len() != self.ram.len() {
+            return Err(format!(
+                "cartridge RAM is {} bytes, expected {}",
+                state.ram.len(),
+                self.ram.len()
+            ));
+        }
+        if std::mem::discriminant(&state.mbc_state) != std::mem::discriminant(&self.mbc_state) {
+            return Err("mapper state is for a different MBC".to_string());
+        }
+        match &state.mbc_state {
+            MbcState::PocketCamera { regs, .. } if regs.len() != CAMERA_REG_COUNT => Err(format!(
+                "camera has {} registers, expected {CAMERA_REG_COUNT}",
+                regs.len()
+            )),
+            MbcState::HuC3(huc3) if !huc3.is_valid() => {
+                Err("HuC3 scratch memory has the wrong size".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Restore state captured by `save_state` from the same ROM.
+    pub fn load_state(&mut self, state: &CartridgeState) {
+        self.ram.clone_from(&state.ram);
+        self.mbc_state = state.mbc_state.clone();
+        self.rtc.clone_from(&state.rtc);
+    }
+
+    /// Parse the header fields of the loaded ROM.
+    pub fn header(&self) -> CartridgeHeader {
+        let header = Header::parse(&self.rom);
+        CartridgeHeader {
+            title: header.title(),
+            cgb_flag: header.byte(0x0143),
+            sgb_flag: header.byte(0x0146) == 0x03,
+            cartridge_type: header.cart_type(),
+            mbc: header.mbc_type(),
+            rom_size: 0x8000 << header.byte(0x0148).min(8),
+            ram_size: header.ram_size(),
+            licensee: header.licensee(),
+            header_checksum_valid: header.checksum() == header.byte(0x014D),
+            global_checksum: u16::from_be_bytes([header.byte(0x014E), header.byte(0x014F)]),
+        }
+    }
+
+    /// Whether the header logo at 0x0104-0x0133 matches the Nintendo logo.
+    /// Real hardware refuses to boot cartridges where it doesn't.
+    pub fn logo_matches(&self) -> bool {
+        self.rom.get(0x0104..0x0134) == Some(&NINTENDO_LOGO[..])
+    }
+
     fn has_battery(&self) -> bool {
         matches!(
             self.cart_type,
-            0x03 | 0x06 | 0x09 | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E
+            0x03 | 0x06 | 0x09 | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFE | 0xFF
         )
     }
 
+    /// Fill the camera capture buffer with a checkerboard of 8x8 tiles
+    /// cycling through all four shades.
+    fn write_camera_test_pattern(ram: &mut [u8]) {
+        let Some(image) = ram.get_mut(CAMERA_IMAGE_OFFSET..CAMERA_IMAGE_OFFSET + CAMERA_IMAGE_SIZE)
+        else {
+            return;
+        };
+        for (tile_idx, tile) in image.chunks_exact_mut(16).enumerate() {
+            let shade = ((tile_idx % 16) + (tile_idx / 16)) % 4;
+            let lo = if shade & 1 != 0 { 0xFF } else { 0x00 };
+            let hi = if shade & 2 != 0 { 0xFF } else { 0x00 };
+            for row in tile.chunks_exact_mut(2) {
+                row[0] = lo;
+                row[1] = hi;
+            }
+        }
+    }
+
     pub fn save_ram(&self) -> io::Result<()> {
         if let (true, Some(path)) = (self.has_battery(), &self.save_path) {
             if !self.ram.is_empty() {
                 fs::write(path, &self.ram)?;
             }
+            if let Some(rtc) = &self.rtc {
+                fs::write(path.with_extension("rtc"), rtc.to_save_bytes(unix_now()))?;
+            }
+            if let MbcState::HuC3(huc3) = &self.mbc_state {
+                fs::write(path.with_extension("rtc"), huc3.to_save_bytes(unix_now()))?;
+            }
         }
         Ok(())
     }
 }
 
+/// Current time in seconds since the Unix epoch.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
 struct Header<'a> {
     data: &'a [u8],
 }
@@ -385,6 +938,26 @@ impl<'a> Header<'a> {
         String::from_utf8_lossy(slice).trim().to_string()
     }
 
+    fn byte(&self, addr: usize) -> u8 {
+        self.data.get(addr).copied().unwrap_or(0)
+    }
+
+    fn licensee(&self) -> String {
+        match self.byte(0x014B) {
+            // 0x33 defers to the two ASCII characters at 0x0144.
+            0x33 => String::from_utf8_lossy(self.data.get(0x0144..0x0146).unwrap_or_default())
+                .into_owned(),
+            code => format!("{code:02X}"),
+        }
+    }
+
+    /// Header checksum over 0x0134-0x014C, as verified by the boot ROM.
+    fn checksum(&self) -> u8 {
+        (0x0134..=0x014C).fold(0u8, |x, addr| {
+            x.wrapping_sub(self.byte(addr)).wrapping_sub(1)
+        })
+    }
+
     fn cgb_supported(&self) -> bool {
         self.data.get(0x0143).copied().unwrap_or(0) & 0x80 != 0
     }
@@ -406,6 +979,10 @@ impl<'a> Header<'a> {
                 }
             }
             0x19..=0x1E => MbcType::Mbc5,
+            0x22 => MbcType::Mbc7,
+            0xFC => MbcType::PocketCamera,
+            0xFE => MbcType::HuC3,
+            0xFF => MbcType::HuC1,
             _ => MbcType::NoMbc,
         }
     }
@@ -421,6 +998,7 @@ impl<'a> Header<'a> {
         if self.data.len() < 0x150 {
             return 0x2000;
         }
+        // Note that 0x04 (128KB) and 0x05 (64KB) are not in size order.
         match self.data.get(0x0149).copied().unwrap_or(0) {
             0x00 => 0,
             0x01 => 0x800,   // 2KB
@@ -428,7 +1006,10 @@ impl<'a> Header<'a> {
             0x03 => 0x8000,  // 32KB (4 banks)
             0x04 => 0x20000, // 128KB (16 banks)
             0x05 => 0x10000, // 64KB (8 banks)
-            _ => 0x2000,
+            code => {
+                log::warn!("Unknown RAM size code {code:02X}, assuming 8KB");
+                0x2000
+            }
         }
     }
 }
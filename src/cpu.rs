@@ -1,3 +1,7 @@
+use crate::interrupts::Interrupts;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
 const fn opcode_cycles() -> [u8; 256] {
     let mut arr = [0u8; 256];
     arr[0x00] = 4; // NOP
@@ -79,7 +83,7 @@ const fn opcode_cycles() -> [u8; 256] {
     arr[0xCA] = 12; // JP Z,a16 (not taken)
     // CB prefix is handled separately; base cycles are accounted for in the
     // prefixed opcode itself.
-    arr[0xCB] = 0; // PREFIX CB
+    arr[0xCB] = 0; // PREFIX CB, whole instruction timed by CB_CYCLES
     arr[0xCC] = 12; // CALL Z,a16 (not taken)
     arr[0xCD] = 24; // CALL a16
     arr[0xCE] = 8; // ADC A,d8
@@ -225,9 +229,58 @@ pub struct Cpu {
     pub cycles: u64,
     pub ime: bool,
     pub halted: bool,
+    /// Set by STOP; cleared when a selected joypad line goes low.
+    pub stopped: bool,
     pub double_speed: bool,
+    /// Execute illegal opcodes as 4-cycle NOPs instead of locking up.
+    pub lenient: bool,
+    /// Set after an illegal opcode; the CPU stops executing until reset.
+    locked_up: bool,
     halt_bug: bool,
     ime_delay: bool,
+    /// CPU cycles already ticked during the current step.
+    step_cycles: u16,
+    /// When set, `tick` only counts cycles and leaves the timer, serial, PPU
+    /// and APU for the caller to advance.
+    external_ticks: bool,
+    /// Addresses at which `step_checked` stops before fetching.
+    breakpoints: HashSet<u16>,
+    /// Breakpoint already reported by `step_checked`, so the next call
+    /// executes the instruction instead of reporting it again.
+    resume_pc: Option<u16>,
+}
+
+/// Outcome of `Cpu::step_checked`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// An instruction (or an idle cycle while halted) was executed.
+    Normal,
+    /// PC reached a breakpoint; nothing was executed.
+    BreakpointHit(u16),
+}
+
+/// Snapshot of every CPU register and internal flag, used by savestates.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub pc: u16,
+    pub sp: u16,
+    pub cycles: u64,
+    pub ime: bool,
+    pub halted: bool,
+    pub stopped: bool,
+    pub double_speed: bool,
+    pub locked_up: bool,
+    pub halt_bug: bool,
+    /// EI was executed and IME turns on after the next instruction.
+    pub ime_delay: bool,
 }
 
 impl Cpu {
@@ -253,9 +306,16 @@ impl Cpu {
                 cycles: 0,
                 ime: false,
                 halted: false,
+                stopped: false,
                 double_speed: false,
+                lenient: false,
+                locked_up: false,
                 halt_bug: false,
                 ime_delay: false,
+                step_cycles: 0,
+                external_ticks: false,
+                breakpoints: HashSet::new(),
+                resume_pc: None,
             }
         } else {
             Self {
@@ -272,9 +332,16 @@ impl Cpu {
                 cycles: 0,
                 ime: false,
                 halted: false,
+                stopped: false,
                 double_speed: false,
+                lenient: false,
+                locked_up: false,
                 halt_bug: false,
                 ime_delay: false,
+                step_cycles: 0,
+                external_ticks: false,
+                breakpoints: HashSet::new(),
+                resume_pc: None,
             }
         }
     }
@@ -306,6 +373,12 @@ impl Cpu {
         self.l = val as u8;
     }
 
+    /// Build F from the individual Z, N, H and C flags. The low nibble of F
+    /// is always zero.
+    fn set_flags(&mut self, z: bool, n: bool, h: bool, c: bool) {
+        self.f = ((z as u8) << 7) | ((n as u8) << 6) | ((h as u8) << 5) | ((c as u8) << 4);
+    }
+
     /// Return a formatted string of the current CPU state for debugging.
     pub fn debug_state(&self) -> String {
         format!(
@@ -320,22 +393,140 @@ impl Cpu {
         )
     }
 
+    /// Advance the timer, PPU and APU by `cpu_cycles` CPU clocks. In double
+    /// speed mode the hardware only sees half as many cycles.
+    fn tick(&mut self, mmu: &mut crate::mmu::Mmu, cpu_cycles: u16) {
+        self.cycles += cpu_cycles as u64;
+        self.step_cycles += cpu_cycles;
+        if self.external_ticks {
+            return;
+        }
+        let hw_cycles = if self.double_speed {
+            cpu_cycles / 2
+        } else {
+            cpu_cycles
+        };
+        let if_before = mmu.if_reg;
+        mmu.timer.step(hw_cycles, &mut mmu.if_reg);
+        if let Some(cart) = mmu.cart.as_mut() {
+            cart.tick_rtc(hw_cycles as u32);
+        }
+        // The serial clock speeds up with the CPU in double speed mode.
+        mmu.serial.step(cpu_cycles, &mut mmu.if_reg);
+        mmu.ppu.step(hw_cycles, &mut mmu.if_reg);
+        mmu.hdma_step();
+        mmu.apu.lock().unwrap().step(hw_cycles);
+        mmu.note_interrupts(if_before);
+    }
+
+    /// Return to the post-boot register state, keeping frontend settings
+    /// and breakpoints. With `boot_rom` the registers are cleared instead
+    /// and PC starts at 0x0000, leaving their setup to the boot ROM.
+    pub fn reset(&mut self, cgb: bool, boot_rom: bool) {
+        *self = Self {
+            lenient: self.lenient,
+            external_ticks: self.external_ticks,
+            breakpoints: std::mem::take(&mut self.breakpoints),
+            ..Self::new_with_mode(cgb)
+        };
+        if boot_rom {
+            [
+                self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+            ] = [0; 8];
+            self.sp = 0;
+            self.pc = 0;
+        }
+    }
+
+    /// Hang the CPU as an illegal opcode would, e.g. to mimic the boot ROM
+    /// refusing a cartridge.
+    pub fn lock_up(&mut self) {
+        self.locked_up = true;
+    }
+
+    /// True once an illegal opcode has hung the CPU. PC is left pointing
+    /// at the offending opcode.
+    pub fn is_locked_up(&self) -> bool {
+        self.locked_up
+    }
+
+    /// Capture the register file and internal state for a savestate.
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            f: self.f,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            pc: self.pc,
+            sp: self.sp,
+            cycles: self.cycles,
+            ime: self.ime,
+            halted: self.halted,
+            stopped: self.stopped,
+            double_speed: self.double_speed,
+            locked_up: self.locked_up,
+            halt_bug: self.halt_bug,
+            ime_delay: self.ime_delay,
+        }
+    }
+
+    /// Restore state captured by `save_state`. Frontend options such as
+    /// `lenient` are left untouched.
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.a = state.a;
+        self.f = state.f;
+        self.b = state.b;
+        self.c = state.c;
+        self.d = state.d;
+        self.e = state.e;
+        self.h = state.h;
+        self.l = state.l;
+        self.pc = state.pc;
+        self.sp = state.sp;
+        self.cycles = state.cycles;
+        self.ime = state.ime;
+        self.halted = state.halted;
+        self.stopped = state.stopped;
+        self.double_speed = state.double_speed;
+        self.locked_up = state.locked_up;
+        self.halt_bug = state.halt_bug;
+        self.ime_delay = state.ime_delay;
+        self.step_cycles = 0;
+    }
+
+    /// Read a byte at the end of a 4-cycle memory access so that the
+    /// hardware state observed matches the sub-instruction timing.
+    fn read8(&mut self, mmu: &mut crate::mmu::Mmu, addr: u16) -> u8 {
+        self.tick(mmu, 4);
+        mmu.read_byte(addr)
+    }
+
+    /// Write a byte at the end of a 4-cycle memory access.
+    fn write8(&mut self, mmu: &mut crate::mmu::Mmu, addr: u16, val: u8) {
+        self.tick(mmu, 4);
+        mmu.write_byte(addr, val);
+    }
+
     fn push_stack(&mut self, mmu: &mut crate::mmu::Mmu, val: u16) {
         self.sp = self.sp.wrapping_sub(1);
-        mmu.write_byte(self.sp, (val >> 8) as u8);
+        self.write8(mmu, self.sp, (val >> 8) as u8);
         self.sp = self.sp.wrapping_sub(1);
-        mmu.write_byte(self.sp, val as u8);
+        self.write8(mmu, self.sp, val as u8);
     }
 
     fn pop_stack(&mut self, mmu: &mut crate::mmu::Mmu) -> u16 {
-        let lo = mmu.read_byte(self.sp) as u16;
+        let lo = self.read8(mmu, self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
-        let hi = mmu.read_byte(self.sp) as u16;
+        let hi = self.read8(mmu, self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
         (hi << 8) | lo
     }
 
-    fn read_reg(&self, mmu: &mut crate::mmu::Mmu, index: u8) -> u8 {
+    fn read_reg(&mut self, mmu: &mut crate::mmu::Mmu, index: u8) -> u8 {
         match index {
             0 => self.b,
             1 => self.c,
@@ -343,7 +534,7 @@ impl Cpu {
             3 => self.e,
             4 => self.h,
             5 => self.l,
-            6 => mmu.read_byte(self.get_hl()),
+            6 => self.read8(mmu, self.get_hl()),
             7 => self.a,
             _ => unreachable!(),
         }
@@ -359,13 +550,16 @@ impl Cpu {
             5 => self.l = val,
             6 => {
                 let addr = self.get_hl();
-                mmu.write_byte(addr, val);
+                self.write8(mmu, addr, val);
             }
             7 => self.a = val,
             _ => unreachable!(),
         }
     }
 
+    /// Execute a CB-prefixed instruction. Operations on (HL) read the operand
+    /// on the third M-cycle and write the result back on the fourth, since
+    /// `read_reg` and `write_reg` each tick the hardware for their access.
     fn handle_cb(&mut self, opcode: u8, mmu: &mut crate::mmu::Mmu) {
         match opcode {
             0x00..=0x07 => {
@@ -373,14 +567,14 @@ impl Cpu {
                 let val = self.read_reg(mmu, r);
                 let res = val.rotate_left(1);
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x80 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x80 != 0);
             }
             0x08..=0x0F => {
                 let r = opcode & 0x07;
                 let val = self.read_reg(mmu, r);
                 let res = val.rotate_right(1);
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x01 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x01 != 0);
             }
             0x10..=0x17 => {
                 let r = opcode & 0x07;
@@ -388,7 +582,7 @@ impl Cpu {
                 let carry_in = if self.f & 0x10 != 0 { 1 } else { 0 };
                 let res = (val << 1) | carry_in;
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x80 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x80 != 0);
             }
             0x18..=0x1F => {
                 let r = opcode & 0x07;
@@ -396,41 +590,41 @@ impl Cpu {
                 let carry_in = if self.f & 0x10 != 0 { 1 } else { 0 };
                 let res = (val >> 1) | ((carry_in as u8) << 7);
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x01 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x01 != 0);
             }
             0x20..=0x27 => {
                 let r = opcode & 0x07;
                 let val = self.read_reg(mmu, r);
                 let res = val << 1;
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x80 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x80 != 0);
             }
             0x28..=0x2F => {
                 let r = opcode & 0x07;
                 let val = self.read_reg(mmu, r);
                 let res = (val >> 1) | (val & 0x80);
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x01 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x01 != 0);
             }
             0x30..=0x37 => {
                 let r = opcode & 0x07;
                 let val = self.read_reg(mmu, r);
                 let res = val.rotate_left(4);
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 };
+                self.set_flags(res == 0, false, false, false);
             }
             0x38..=0x3F => {
                 let r = opcode & 0x07;
                 let val = self.read_reg(mmu, r);
                 let res = val >> 1;
                 self.write_reg(mmu, r, res);
-                self.f = if res == 0 { 0x80 } else { 0 } | if val & 0x01 != 0 { 0x10 } else { 0 };
+                self.set_flags(res == 0, false, false, val & 0x01 != 0);
             }
             0x40..=0x7F => {
                 let bit = (opcode - 0x40) >> 3;
                 let r = opcode & 0x07;
                 let val = self.read_reg(mmu, r);
-                self.f = (self.f & 0x10) | 0x20 | if val & (1 << bit) == 0 { 0x80 } else { 0 };
+                self.set_flags(val & (1 << bit) == 0, false, true, self.f & 0x10 != 0);
             }
             0x80..=0xBF => {
                 let bit = (opcode - 0x80) >> 3;
@@ -450,74 +644,130 @@ impl Cpu {
     }
 
     fn handle_interrupts(&mut self, mmu: &mut crate::mmu::Mmu) {
-        let pending = mmu.if_reg & mmu.ie_reg;
-        if pending == 0 {
+        let Some(interrupt) = Interrupts::pending(mmu.if_reg, mmu.ie_reg) else {
             return;
-        }
+        };
 
         if self.ime {
             self.halted = false;
+            Interrupts::acknowledge(&mut mmu.if_reg, interrupt);
 
-            let vector = if pending & 0x01 != 0 {
-                mmu.if_reg &= !0x01;
-                0x40
-            } else if pending & 0x02 != 0 {
-                mmu.if_reg &= !0x02;
-                0x48
-            } else if pending & 0x04 != 0 {
-                mmu.if_reg &= !0x04;
-                0x50
-            } else if pending & 0x08 != 0 {
-                mmu.if_reg &= !0x08;
-                0x58
-            } else {
-                mmu.if_reg &= !0x10;
-                0x60
-            };
-
+            // Two internal cycles, the PC push, then one more to load the vector.
+            self.tick(mmu, 8);
             let pc = self.pc;
             self.push_stack(mmu, pc);
-            self.pc = vector;
+            self.tick(mmu, 4);
+            self.pc = interrupt.vector();
             self.ime = false;
-            self.cycles += 20;
         } else if self.halted {
             self.halted = false;
         }
     }
 
-    pub fn step(&mut self, mmu: &mut crate::mmu::Mmu) {
+    /// Execute one instruction and return the CPU cycles it consumed without
+    /// advancing the timer, PPU or APU, for frontends that schedule those
+    /// themselves. In double speed mode the caller should advance them by half
+    /// the returned cycles.
+    pub fn step_instruction(&mut self, mmu: &mut crate::mmu::Mmu) -> u32 {
+        self.external_ticks = true;
+        let cycles = self.step(mmu);
+        self.external_ticks = false;
+        cycles
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    /// Breakpoint addresses in no particular order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Breakpoint check done by `step_checked`, for callers that step the
+    /// CPU some other way, such as a `GameBoy::run_frame_with` hook. Returns
+    /// the address when PC is on a breakpoint that was not just reported;
+    /// otherwise the caller is expected to execute the next step.
+    pub fn check_breakpoint(&mut self, mmu: &crate::mmu::Mmu) -> Option<u16> {
+        if self.breakpoints.contains(&self.pc) && self.resume_pc != Some(self.pc) {
+            self.resume_pc = Some(self.pc);
+            return Some(self.pc);
+        }
+        // Idle steps (halt, DMA) do not consume the resume.
+        if !self.halted && !self.locked_up && !mmu.cpu_stalled() {
+            self.resume_pc = None;
+        }
+        None
+    }
+
+    /// Like `step`, but stops when PC is on a breakpoint. The hit is
+    /// reported once; calling again executes the instruction so execution
+    /// can resume.
+    pub fn step_checked(&mut self, mmu: &mut crate::mmu::Mmu) -> StepResult {
+        if let Some(pc) = self.check_breakpoint(mmu) {
+            return StepResult::BreakpointHit(pc);
+        }
+        self.step(mmu);
+        StepResult::Normal
+    }
+
+    /// Execute one instruction, or one idle cycle while halted, stalled by
+    /// OAM or VRAM DMA or locked up, and return the T-cycles it took. This includes
+    /// taken-branch extras and the 20-cycle interrupt dispatch.
+    #[cfg_attr(
+        feature = "profiling",
+        tracing::instrument(name = "Cpu::step", skip_all)
+    )]
+    pub fn step(&mut self, mmu: &mut crate::mmu::Mmu) -> u32 {
+        self.execute(mmu);
+        self.step_cycles as u32
+    }
+
+    fn execute(&mut self, mmu: &mut crate::mmu::Mmu) {
+        self.step_cycles = 0;
         if mmu.dma_active() {
-            let cpu_cycles = 4u16;
-            mmu.dma_step(cpu_cycles);
-            let hw_cycles = if self.double_speed {
-                cpu_cycles / 2
-            } else {
-                cpu_cycles
-            };
-            self.cycles += cpu_cycles as u64;
-            mmu.timer.step(hw_cycles, &mut mmu.if_reg);
-            mmu.ppu.step(hw_cycles, &mut mmu.if_reg);
-            mmu.apu.lock().unwrap().step(hw_cycles);
+            mmu.dma_step(4);
+            self.tick(mmu, 4);
+            return;
+        }
+
+        if mmu.vram_dma_stall > 0 {
+            mmu.vram_dma_stall = mmu.vram_dma_stall.saturating_sub(4);
+            self.tick(mmu, 4);
+            return;
+        }
+
+        if self.locked_up {
+            // Nothing wakes a locked CPU, but the rest of the system runs on.
+            self.tick(mmu, 4);
             return;
         }
 
         if self.halted {
-            let cpu_cycles = 4u16;
-            let hw_cycles = if self.double_speed {
-                cpu_cycles / 2
-            } else {
-                cpu_cycles
-            };
-            self.cycles += cpu_cycles as u64;
-            mmu.timer.step(hw_cycles, &mut mmu.if_reg);
-            mmu.ppu.step(hw_cycles, &mut mmu.if_reg);
-            mmu.apu.lock().unwrap().step(hw_cycles);
+            self.tick(mmu, 4);
             self.handle_interrupts(mmu);
             return;
         }
 
+        if self.stopped {
+            self.tick(mmu, 4);
+            if mmu.input.read() & 0x0F != 0x0F {
+                self.stopped = false;
+            }
+            return;
+        }
+
         let enable_after = self.ime_delay;
-        let opcode = mmu.read_byte(self.pc);
+        mmu.set_current_pc(self.pc);
+        let opcode = self.read8(mmu, self.pc);
         if self.halt_bug {
             self.halt_bug = false;
         } else {
@@ -528,14 +778,14 @@ impl Cpu {
         match opcode {
             0x00 => {}
             0x01 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 self.set_bc((hi << 8) | lo);
             }
             0x02 => {
                 let addr = self.get_bc();
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
             }
             0x03 => {
                 let val = self.get_bc().wrapping_add(1);
@@ -543,57 +793,47 @@ impl Cpu {
             }
             0x04 => {
                 let res = self.b.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.b & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.b & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.b = res;
             }
             0x05 => {
                 let res = self.b.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.b & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.b & 0x0F == 0, self.f & 0x10 != 0);
                 self.b = res;
             }
             0x06 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.b = val;
             }
             0x07 => {
                 let carry = (self.a & 0x80) != 0;
                 self.a = self.a.rotate_left(1);
-                self.f = if carry { 0x10 } else { 0 };
+                self.set_flags(false, false, false, carry);
             }
             0x08 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 let addr = (hi << 8) | lo;
-                mmu.write_byte(addr, (self.sp & 0xFF) as u8);
-                mmu.write_byte(addr.wrapping_add(1), (self.sp >> 8) as u8);
+                self.write8(mmu, addr, (self.sp & 0xFF) as u8);
+                self.write8(mmu, addr.wrapping_add(1), (self.sp >> 8) as u8);
             }
             0x09 => {
                 let hl = self.get_hl();
                 let bc = self.get_bc();
                 let res = hl.wrapping_add(bc);
-                self.f = (self.f & 0x80)
-                    | if ((hl & 0x0FFF) + (bc & 0x0FFF)) & 0x1000 != 0 {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if (hl as u32 + bc as u32) > 0xFFFF {
-                        0x10
-                    } else {
-                        0
-                    };
+                self.set_flags(
+                    self.f & 0x80 != 0,
+                    false,
+                    (hl & 0x0FFF) + (bc & 0x0FFF) > 0x0FFF,
+                    hl as u32 + bc as u32 > 0xFFFF,
+                );
                 self.set_hl(res);
             }
             0x0A => {
                 let addr = self.get_bc();
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
             }
             0x0B => {
                 let val = self.get_bc().wrapping_sub(1);
@@ -601,48 +841,45 @@ impl Cpu {
             }
             0x0C => {
                 let res = self.c.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.c & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.c & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.c = res;
             }
             0x0D => {
                 let res = self.c.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.c & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.c & 0x0F == 0, self.f & 0x10 != 0);
                 self.c = res;
             }
             0x0E => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.c = val;
             }
             0x0F => {
                 let carry = (self.a & 0x01) != 0;
                 self.a = self.a.rotate_right(1);
-                self.f = if carry { 0x10 } else { 0 };
+                self.set_flags(false, false, false, carry);
             }
             0x10 => {
                 // STOP
-                let _ = mmu.read_byte(self.pc);
+                let _ = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 if mmu.key1 & 0x01 != 0 {
                     mmu.key1 &= !0x01;
                     mmu.key1 ^= 0x80;
                     self.double_speed = mmu.key1 & 0x80 != 0;
+                } else {
+                    self.stopped = true;
                 }
             }
             0x11 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 self.set_de((hi << 8) | lo);
             }
             0x12 => {
                 let addr = self.get_de();
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
             }
             0x13 => {
                 let val = self.get_de().wrapping_add(1);
@@ -650,31 +887,26 @@ impl Cpu {
             }
             0x14 => {
                 let res = self.d.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.d & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.d & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.d = res;
             }
             0x15 => {
                 let res = self.d.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.d & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.d & 0x0F == 0, self.f & 0x10 != 0);
                 self.d = res;
             }
             0x16 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.d = val;
             }
             0x17 => {
                 let carry = (self.a & 0x80) != 0;
                 self.a = (self.a << 1) | if self.f & 0x10 != 0 { 1 } else { 0 };
-                self.f = if carry { 0x10 } else { 0 };
+                self.set_flags(false, false, false, carry);
             }
             0x18 => {
-                let offset = mmu.read_byte(self.pc) as i8;
+                let offset = self.read8(mmu, self.pc) as i8;
                 self.pc = self.pc.wrapping_add(1);
                 self.pc = self.pc.wrapping_add(offset as u16);
             }
@@ -682,22 +914,17 @@ impl Cpu {
                 let hl = self.get_hl();
                 let de = self.get_de();
                 let res = hl.wrapping_add(de);
-                self.f = (self.f & 0x80)
-                    | if ((hl & 0x0FFF) + (de & 0x0FFF)) & 0x1000 != 0 {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if (hl as u32 + de as u32) > 0xFFFF {
-                        0x10
-                    } else {
-                        0
-                    };
+                self.set_flags(
+                    self.f & 0x80 != 0,
+                    false,
+                    (hl & 0x0FFF) + (de & 0x0FFF) > 0x0FFF,
+                    hl as u32 + de as u32 > 0xFFFF,
+                );
                 self.set_hl(res);
             }
             0x1A => {
                 let addr = self.get_de();
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
             }
             0x1B => {
                 let val = self.get_de().wrapping_sub(1);
@@ -705,31 +932,26 @@ impl Cpu {
             }
             0x1C => {
                 let res = self.e.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.e & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.e & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.e = res;
             }
             0x1D => {
                 let res = self.e.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.e & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.e & 0x0F == 0, self.f & 0x10 != 0);
                 self.e = res;
             }
             0x1E => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.e = val;
             }
             0x1F => {
                 let carry = (self.a & 0x01) != 0;
                 self.a = (self.a >> 1) | if self.f & 0x10 != 0 { 0x80 } else { 0 };
-                self.f = if carry { 0x10 } else { 0 };
+                self.set_flags(false, false, false, carry);
             }
             0x20 => {
-                let offset = mmu.read_byte(self.pc) as i8;
+                let offset = self.read8(mmu, self.pc) as i8;
                 self.pc = self.pc.wrapping_add(1);
                 if self.f & 0x80 == 0 {
                     self.pc = self.pc.wrapping_add(offset as u16);
@@ -737,14 +959,14 @@ impl Cpu {
                 }
             }
             0x21 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 self.set_hl((hi << 8) | lo);
             }
             0x22 => {
                 let addr = self.get_hl();
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
                 self.set_hl(addr.wrapping_add(1));
             }
             0x23 => {
@@ -753,26 +975,21 @@ impl Cpu {
             }
             0x24 => {
                 let res = self.h.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.h & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.h & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.h = res;
             }
             0x25 => {
                 let res = self.h.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.h & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.h & 0x0F == 0, self.f & 0x10 != 0);
                 self.h = res;
             }
             0x26 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.h = val;
             }
             0x28 => {
-                let offset = mmu.read_byte(self.pc) as i8;
+                let offset = self.read8(mmu, self.pc) as i8;
                 self.pc = self.pc.wrapping_add(1);
                 if self.f & 0x80 != 0 {
                     self.pc = self.pc.wrapping_add(offset as u16);
@@ -782,18 +999,17 @@ impl Cpu {
             0x29 => {
                 let hl = self.get_hl();
                 let res = hl.wrapping_add(hl);
-                self.f = (self.f & 0x80)
-                    | if ((hl & 0x0FFF) << 1) & 0x1000 != 0 {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if (hl as u32 * 2) > 0xFFFF { 0x10 } else { 0 };
+                self.set_flags(
+                    self.f & 0x80 != 0,
+                    false,
+                    hl & 0x0800 != 0,
+                    hl & 0x8000 != 0,
+                );
                 self.set_hl(res);
             }
             0x2A => {
                 let addr = self.get_hl();
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
                 self.set_hl(addr.wrapping_add(1));
             }
             0x2B => {
@@ -802,27 +1018,22 @@ impl Cpu {
             }
             0x2C => {
                 let res = self.l.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.l & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.l & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.l = res;
             }
             0x2D => {
                 let res = self.l.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.l & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.l & 0x0F == 0, self.f & 0x10 != 0);
                 self.l = res;
             }
             0x2E => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.l = val;
             }
             0x2F => {
                 self.a ^= 0xFF;
-                self.f = (self.f & 0x90) | 0x60;
+                self.set_flags(self.f & 0x80 != 0, true, true, self.f & 0x10 != 0);
             }
             0x27 => {
                 let mut correction = 0u8;
@@ -839,12 +1050,10 @@ impl Cpu {
                 } else {
                     self.a = self.a.wrapping_sub(correction);
                 }
-                self.f = if self.a == 0 { 0x80 } else { 0 }
-                    | (self.f & 0x40)
-                    | if carry { 0x10 } else { 0 };
+                self.set_flags(self.a == 0, self.f & 0x40 != 0, false, carry);
             }
             0x30 => {
-                let offset = mmu.read_byte(self.pc) as i8;
+                let offset = self.read8(mmu, self.pc) as i8;
                 self.pc = self.pc.wrapping_add(1);
                 if self.f & 0x10 == 0 {
                     self.pc = self.pc.wrapping_add(offset as u16);
@@ -852,14 +1061,14 @@ impl Cpu {
                 }
             }
             0x31 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 self.sp = (hi << 8) | lo;
             }
             0x32 => {
                 let addr = self.get_hl();
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
                 self.set_hl(addr.wrapping_sub(1));
             }
             0x33 => {
@@ -867,34 +1076,29 @@ impl Cpu {
             }
             0x34 => {
                 let addr = self.get_hl();
-                let val = mmu.read_byte(addr).wrapping_add(1);
-                let old = mmu.read_byte(addr);
-                mmu.write_byte(addr, val);
-                self.f = (self.f & 0x10)
-                    | if val == 0 { 0x80 } else { 0 }
-                    | if (old & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                let old = self.read8(mmu, addr);
+                let val = old.wrapping_add(1);
+                self.write8(mmu, addr, val);
+                self.set_flags(val == 0, false, old & 0x0F == 0x0F, self.f & 0x10 != 0);
             }
             0x35 => {
                 let addr = self.get_hl();
-                let old = mmu.read_byte(addr);
+                let old = self.read8(mmu, addr);
                 let val = old.wrapping_sub(1);
-                mmu.write_byte(addr, val);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if val == 0 { 0x80 } else { 0 }
-                    | if old & 0x0F == 0 { 0x20 } else { 0 };
+                self.write8(mmu, addr, val);
+                self.set_flags(val == 0, true, old & 0x0F == 0, self.f & 0x10 != 0);
             }
             0x36 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let addr = self.get_hl();
-                mmu.write_byte(addr, val);
+                self.write8(mmu, addr, val);
             }
             0x37 => {
-                self.f = (self.f & 0x80) | 0x10;
+                self.set_flags(self.f & 0x80 != 0, false, false, true);
             }
             0x38 => {
-                let offset = mmu.read_byte(self.pc) as i8;
+                let offset = self.read8(mmu, self.pc) as i8;
                 self.pc = self.pc.wrapping_add(1);
                 if self.f & 0x10 != 0 {
                     self.pc = self.pc.wrapping_add(offset as u16);
@@ -905,22 +1109,17 @@ impl Cpu {
                 let hl = self.get_hl();
                 let sp = self.sp;
                 let res = hl.wrapping_add(sp);
-                self.f = (self.f & 0x80)
-                    | if ((hl & 0x0FFF) + (sp & 0x0FFF)) & 0x1000 != 0 {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if (hl as u32 + sp as u32) > 0xFFFF {
-                        0x10
-                    } else {
-                        0
-                    };
+                self.set_flags(
+                    self.f & 0x80 != 0,
+                    false,
+                    (hl & 0x0FFF) + (sp & 0x0FFF) > 0x0FFF,
+                    hl as u32 + sp as u32 > 0xFFFF,
+                );
                 self.set_hl(res);
             }
             0x3A => {
                 let addr = self.get_hl();
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
                 self.set_hl(addr.wrapping_sub(1));
             }
             0x3B => {
@@ -928,28 +1127,37 @@ impl Cpu {
             }
             0x3C => {
                 let res = self.a.wrapping_add(1);
-                self.f = (self.f & 0x10)
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) + 1 > 0x0F { 0x20 } else { 0 };
+                self.set_flags(res == 0, false, self.a & 0x0F == 0x0F, self.f & 0x10 != 0);
                 self.a = res;
             }
             0x3D => {
                 let res = self.a.wrapping_sub(1);
-                self.f = (self.f & 0x10)
-                    | 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if self.a & 0x0F == 0 { 0x20 } else { 0 };
+                self.set_flags(res == 0, true, self.a & 0x0F == 0, self.f & 0x10 != 0);
                 self.a = res;
             }
             0x3E => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.a = val;
             }
             0x3F => {
-                self.f = (self.f & 0x80) | if self.f & 0x10 != 0 { 0 } else { 0x10 };
+                self.set_flags(self.f & 0x80 != 0, false, false, self.f & 0x10 == 0);
             }
-            opcode @ 0x40..=0x7F if opcode != 0x76 => {
+            0x76 => {
+                let pending = mmu.if_reg & mmu.ie_reg;
+                // IME from an EI just before HALT is set by the end of this
+                // step, in time to service the pending interrupt, so the
+                // HALT bug does not apply. The HALT's own address is pushed,
+                // so it runs again after the handler returns.
+                if enable_after && !self.ime && pending != 0 {
+                    self.pc = self.pc.wrapping_sub(1);
+                } else if self.ime || enable_after || pending == 0 {
+                    self.halted = true;
+                } else {
+                    self.halt_bug = true;
+                }
+            }
+            opcode @ 0x40..=0x7F => {
                 let dest = (opcode >> 3) & 0x07;
                 let src = opcode & 0x07;
                 let val = match src {
@@ -959,7 +1167,7 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
@@ -972,24 +1180,12 @@ impl Cpu {
                     5 => self.l = val,
                     6 => {
                         let addr = self.get_hl();
-                        mmu.write_byte(addr, val);
+                        self.write8(mmu, addr, val);
                     }
                     7 => self.a = val,
                     _ => unreachable!(),
                 }
             }
-            0x76 => {
-                let pending = mmu.if_reg & mmu.ie_reg;
-                if self.ime || pending == 0 {
-                    self.halted = true;
-                } else {
-                    self.halt_bug = true;
-                }
-            }
-            0x77 => {
-                let addr = self.get_hl();
-                mmu.write_byte(addr, self.a);
-            }
             opcode @ 0x80..=0x87 => {
                 let src = opcode & 0x07;
                 let val = match src {
@@ -999,18 +1195,17 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 let (res, carry) = self.a.overflowing_add(val);
-                self.f = if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) + (val & 0x0F) > 0x0F {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if carry { 0x10 } else { 0 };
+                self.set_flags(
+                    res == 0,
+                    false,
+                    (self.a & 0x0F) + (val & 0x0F) > 0x0F,
+                    carry,
+                );
                 self.a = res;
             }
             opcode @ 0x88..=0x8F => {
@@ -1022,20 +1217,19 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 let carry_in = if self.f & 0x10 != 0 { 1 } else { 0 };
                 let (res1, carry1) = self.a.overflowing_add(val);
                 let (res2, carry2) = res1.overflowing_add(carry_in);
-                self.f = if res2 == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) + (val & 0x0F) + carry_in > 0x0F {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if carry1 || carry2 { 0x10 } else { 0 };
+                self.set_flags(
+                    res2 == 0,
+                    false,
+                    (self.a & 0x0F) + (val & 0x0F) + carry_in > 0x0F,
+                    carry1 || carry2,
+                );
                 self.a = res2;
             }
             opcode @ 0x90..=0x97 => {
@@ -1047,19 +1241,12 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 let (res, borrow) = self.a.overflowing_sub(val);
-                self.f = 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) < (val & 0x0F) {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if borrow { 0x10 } else { 0 };
+                self.set_flags(res == 0, true, (self.a & 0x0F) < (val & 0x0F), borrow);
                 self.a = res;
             }
             opcode @ 0x98..=0x9F => {
@@ -1071,21 +1258,19 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 let carry_in = if self.f & 0x10 != 0 { 1 } else { 0 };
                 let (res1, borrow1) = self.a.overflowing_sub(val);
                 let (res2, borrow2) = res1.overflowing_sub(carry_in);
-                self.f = 0x40
-                    | if res2 == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) < ((val & 0x0F) + carry_in) {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if borrow1 || borrow2 { 0x10 } else { 0 };
+                self.set_flags(
+                    res2 == 0,
+                    true,
+                    (self.a & 0x0F) < (val & 0x0F) + carry_in,
+                    borrow1 || borrow2,
+                );
                 self.a = res2;
             }
             opcode @ 0xA0..=0xA7 => {
@@ -1097,12 +1282,12 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 self.a &= val;
-                self.f = if self.a == 0 { 0x80 } else { 0 } | 0x20;
+                self.set_flags(self.a == 0, false, true, false);
             }
             opcode @ 0xA8..=0xAE => {
                 let src = opcode & 0x07;
@@ -1113,12 +1298,12 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 self.a ^= val;
-                self.f = if self.a == 0 { 0x80 } else { 0 };
+                self.set_flags(self.a == 0, false, false, false);
             }
             opcode @ 0xB0..=0xB7 => {
                 let src = opcode & 0x07;
@@ -1129,12 +1314,12 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 self.a |= val;
-                self.f = if self.a == 0 { 0x80 } else { 0 };
+                self.set_flags(self.a == 0, false, false, false);
             }
             opcode @ 0xB8..=0xBF => {
                 let src = opcode & 0x07;
@@ -1145,24 +1330,17 @@ impl Cpu {
                     3 => self.e,
                     4 => self.h,
                     5 => self.l,
-                    6 => mmu.read_byte(self.get_hl()),
+                    6 => self.read8(mmu, self.get_hl()),
                     7 => self.a,
                     _ => unreachable!(),
                 };
                 let res = self.a.wrapping_sub(val);
-                self.f = 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) < (val & 0x0F) {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if self.a < val { 0x10 } else { 0 };
+                self.set_flags(res == 0, true, (self.a & 0x0F) < (val & 0x0F), self.a < val);
             }
             0xAF => {
                 self.a ^= self.a;
                 // XOR A resets NF, HF, CF and sets Z
-                self.f = 0x80;
+                self.set_flags(true, false, false, false);
             }
             0xC0 => {
                 if self.f & 0x80 == 0 {
@@ -1179,8 +1357,8 @@ impl Cpu {
                 self.push_stack(mmu, val);
             }
             0xC2 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x80 == 0 {
                     self.pc = (hi << 8) | lo;
@@ -1188,13 +1366,13 @@ impl Cpu {
                 }
             }
             0xC3 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = (hi << 8) | lo;
             }
             0xCA => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x80 != 0 {
                     self.pc = (hi << 8) | lo;
@@ -1202,8 +1380,8 @@ impl Cpu {
                 }
             }
             0xC4 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x80 == 0 {
                     self.push_stack(mmu, self.pc);
@@ -1218,16 +1396,15 @@ impl Cpu {
                 }
             }
             0xC6 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let (res, carry) = self.a.overflowing_add(val);
-                self.f = if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) + (val & 0x0F) > 0x0F {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if carry { 0x10 } else { 0 };
+                self.set_flags(
+                    res == 0,
+                    false,
+                    (self.a & 0x0F) + (val & 0x0F) > 0x0F,
+                    carry,
+                );
                 self.a = res;
             }
             0xC9 => {
@@ -1249,16 +1426,16 @@ impl Cpu {
                 self.pc = target;
             }
             0xCD => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 let addr = (hi << 8) | lo;
                 self.push_stack(mmu, self.pc);
                 self.pc = addr;
             }
             0xCC => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x80 != 0 {
                     self.push_stack(mmu, self.pc);
@@ -1267,23 +1444,22 @@ impl Cpu {
                 }
             }
             0xCE => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let carry_in = if self.f & 0x10 != 0 { 1 } else { 0 };
                 let (res1, carry1) = self.a.overflowing_add(val);
                 let (res2, carry2) = res1.overflowing_add(carry_in);
-                self.f = if res2 == 0 { 0x80 } else { 0 }
-                    | if ((self.a & 0x0F) + (val & 0x0F) + carry_in) > 0x0F {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if carry1 || carry2 { 0x10 } else { 0 };
+                self.set_flags(
+                    res2 == 0,
+                    false,
+                    (self.a & 0x0F) + (val & 0x0F) + carry_in > 0x0F,
+                    carry1 || carry2,
+                );
                 self.a = res2;
             }
             0xD2 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x10 == 0 {
                     self.pc = (hi << 8) | lo;
@@ -1301,8 +1477,8 @@ impl Cpu {
                 self.set_de(val);
             }
             0xD4 => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x10 == 0 {
                     self.push_stack(mmu, self.pc);
@@ -1315,17 +1491,10 @@ impl Cpu {
                 self.push_stack(mmu, val);
             }
             0xD6 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let (res, borrow) = self.a.overflowing_sub(val);
-                self.f = 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) < (val & 0x0F) {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if borrow { 0x10 } else { 0 };
+                self.set_flags(res == 0, true, (self.a & 0x0F) < (val & 0x0F), borrow);
                 self.a = res;
             }
             0xD8 => {
@@ -1339,8 +1508,8 @@ impl Cpu {
                 self.ime = true;
             }
             0xDA => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x10 != 0 {
                     self.pc = (hi << 8) | lo;
@@ -1348,8 +1517,8 @@ impl Cpu {
                 }
             }
             0xDC => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 if self.f & 0x10 != 0 {
                     self.push_stack(mmu, self.pc);
@@ -1358,30 +1527,28 @@ impl Cpu {
                 }
             }
             0xDE => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let carry_in = if self.f & 0x10 != 0 { 1 } else { 0 };
                 let (res1, borrow1) = self.a.overflowing_sub(val);
                 let (res2, borrow2) = res1.overflowing_sub(carry_in);
-                self.f = 0x40
-                    | if res2 == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) < (val & 0x0F) + carry_in {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if borrow1 || borrow2 { 0x10 } else { 0 };
+                self.set_flags(
+                    res2 == 0,
+                    true,
+                    (self.a & 0x0F) < (val & 0x0F) + carry_in,
+                    borrow1 || borrow2,
+                );
                 self.a = res2;
             }
             0xE0 => {
-                let offset = mmu.read_byte(self.pc);
+                let offset = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let addr = 0xFF00u16 | offset as u16;
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
             }
             0xE2 => {
                 let addr = 0xFF00u16 | self.c as u16;
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
             }
             0xE1 => {
                 let val = self.pop_stack(mmu);
@@ -1392,46 +1559,43 @@ impl Cpu {
                 self.push_stack(mmu, val);
             }
             0xE6 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.a &= val;
-                self.f = if self.a == 0 { 0x80 } else { 0 } | 0x20;
+                self.set_flags(self.a == 0, false, true, false);
             }
             0xE8 => {
-                let val = mmu.read_byte(self.pc) as i8 as i16 as u16;
+                let val = self.read8(mmu, self.pc) as i8 as i16 as u16;
                 self.pc = self.pc.wrapping_add(1);
                 let sp = self.sp;
                 let result = sp.wrapping_add(val);
-                self.f = if ((sp & 0xF) + (val & 0xF)) > 0xF {
-                    0x20
-                } else {
-                    0
-                } | if ((sp & 0xFF) + (val & 0xFF)) > 0xFF {
-                    0x10
-                } else {
-                    0
-                };
+                self.set_flags(
+                    false,
+                    false,
+                    (sp & 0xF) + (val & 0xF) > 0xF,
+                    (sp & 0xFF) + (val & 0xFF) > 0xFF,
+                );
                 self.sp = result;
             }
             0xE9 => {
                 self.pc = self.get_hl();
             }
             0xEA => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 let addr = (hi << 8) | lo;
-                mmu.write_byte(addr, self.a);
+                self.write8(mmu, addr, self.a);
             }
             0xF0 => {
-                let offset = mmu.read_byte(self.pc);
+                let offset = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let addr = 0xFF00u16 | offset as u16;
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
             }
             0xF2 => {
                 let addr = 0xFF00u16 | self.c as u16;
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
             }
             0xF1 => {
                 let val = self.pop_stack(mmu);
@@ -1443,81 +1607,73 @@ impl Cpu {
                 self.push_stack(mmu, val);
             }
             0xEE => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.a ^= val;
-                self.f = if self.a == 0 { 0x80 } else { 0 };
+                self.set_flags(self.a == 0, false, false, false);
             }
             0xF3 => {
                 self.ime = false;
             }
             0xF6 => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.a |= val;
-                self.f = if self.a == 0 { 0x80 } else { 0 };
+                self.set_flags(self.a == 0, false, false, false);
             }
             0xF8 => {
-                let val = mmu.read_byte(self.pc) as i8 as i16 as u16;
+                let val = self.read8(mmu, self.pc) as i8 as i16 as u16;
                 self.pc = self.pc.wrapping_add(1);
                 let sp = self.sp;
                 let res = sp.wrapping_add(val);
-                self.f = if ((sp & 0xF) + (val & 0xF)) > 0xF {
-                    0x20
-                } else {
-                    0
-                } | if ((sp & 0xFF) + (val & 0xFF)) > 0xFF {
-                    0x10
-                } else {
-                    0
-                };
+                self.set_flags(
+                    false,
+                    false,
+                    (sp & 0xF) + (val & 0xF) > 0xF,
+                    (sp & 0xFF) + (val & 0xFF) > 0xFF,
+                );
                 self.set_hl(res);
             }
             0xF9 => {
                 self.sp = self.get_hl();
             }
             0xFA => {
-                let lo = mmu.read_byte(self.pc) as u16;
-                let hi = mmu.read_byte(self.pc.wrapping_add(1)) as u16;
+                let lo = self.read8(mmu, self.pc) as u16;
+                let hi = self.read8(mmu, self.pc.wrapping_add(1)) as u16;
                 self.pc = self.pc.wrapping_add(2);
                 let addr = (hi << 8) | lo;
-                self.a = mmu.read_byte(addr);
+                self.a = self.read8(mmu, addr);
             }
             0xFB => {
                 self.ime_delay = true;
             }
             0xFE => {
-                let val = mmu.read_byte(self.pc);
+                let val = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 let res = self.a.wrapping_sub(val);
-                self.f = 0x40
-                    | if res == 0 { 0x80 } else { 0 }
-                    | if (self.a & 0x0F) < (val & 0x0F) {
-                        0x20
-                    } else {
-                        0
-                    }
-                    | if self.a < val { 0x10 } else { 0 };
+                self.set_flags(res == 0, true, (self.a & 0x0F) < (val & 0x0F), self.a < val);
             }
             0xCB => {
-                let op = mmu.read_byte(self.pc);
+                let op = self.read8(mmu, self.pc);
                 self.pc = self.pc.wrapping_add(1);
                 self.handle_cb(op, mmu);
                 extra_cycles = CB_CYCLES[op as usize];
             }
-            _ => panic!("unhandled opcode {:02X}", opcode),
+            0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+                if !self.lenient {
+                    self.locked_up = true;
+                    self.pc = self.pc.wrapping_sub(1);
+                    return;
+                }
+            }
         }
 
+        // Memory accesses already ticked the hardware as they happened; the
+        // rest of the instruction's cycles are internal and run here.
         let cycles = OPCODE_CYCLES[opcode as usize] as u16 + extra_cycles as u16;
-        self.cycles += cycles as u64;
-        let hw_cycles = if self.double_speed {
-            cycles / 2
-        } else {
-            cycles
-        };
-        mmu.timer.step(hw_cycles, &mut mmu.if_reg);
-        mmu.ppu.step(hw_cycles, &mut mmu.if_reg);
-        mmu.apu.lock().unwrap().step(hw_cycles);
+        if cycles > self.step_cycles {
+            self.tick(mmu, cycles - self.step_cycles);
+        }
 
         if enable_after {
             self.ime = true;
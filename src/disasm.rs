@@ -0,0 +1,97 @@
+/// Register operands in opcode encoding order; index 6 is `(HL)`.
+const R8: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+/// 16-bit register pairs for LD/INC/DEC/ADD HL.
+const R16: [&str; 4] = ["BC", "DE", "HL", "SP"];
+/// 16-bit register pairs for PUSH/POP.
+const R16_STACK: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CONDITIONS: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ALU: [&str; 8] = [
+    "ADD A,", "ADC A,", "SUB ", "SBC A,", "AND ", "XOR ", "OR ", "CP ",
+];
+const CB_SHIFTS: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+/// Decode the instruction at `pc` whose first bytes are `bytes`. Returns
+/// the mnemonic and the instruction length in bytes. Relative jumps are
+/// shown with their absolute target.
+pub fn disassemble(pc: u16, bytes: [u8; 3]) -> (String, u16) {
+    let [op, b1, b2] = bytes;
+    let d8 = format!("${b1:02X}");
+    let d16 = format!("${:04X}", u16::from_le_bytes([b1, b2]));
+    let rel = format!("${:04X}", pc.wrapping_add(2).wrapping_add(b1 as i8 as u16));
+    let x = op >> 6;
+    let y = ((op >> 3) & 7) as usize;
+    let z = (op & 7) as usize;
+    let p = y >> 1;
+
+    let (text, len) = match op {
+        0x00 => ("NOP".to_string(), 1),
+        0x08 => (format!("LD ({d16}),SP"), 3),
+        0x10 => ("STOP".to_string(), 2),
+        0x18 => (format!("JR {rel}"), 2),
+        0x20 | 0x28 | 0x30 | 0x38 => (format!("JR {},{rel}", CONDITIONS[y - 4]), 2),
+        0x76 => ("HALT".to_string(), 1),
+        0xC3 => (format!("JP {d16}"), 3),
+        0xC9 => ("RET".to_string(), 1),
+        0xCB => return disassemble_cb(b1),
+        0xCD => (format!("CALL {d16}"), 3),
+        0xD9 => ("RETI".to_string(), 1),
+        0xE0 => (format!("LDH ({d8}),A"), 2),
+        0xE2 => ("LD (C),A".to_string(), 1),
+        0xE8 => (format!("ADD SP,{}", b1 as i8), 2),
+        0xE9 => ("JP HL".to_string(), 1),
+        0xEA => (format!("LD ({d16}),A"), 3),
+        0xF0 => (format!("LDH A,({d8})"), 2),
+        0xF2 => ("LD A,(C)".to_string(), 1),
+        0xF3 => ("DI".to_string(), 1),
+        0xF8 => (format!("LD HL,SP{:+}", b1 as i8), 2),
+        0xF9 => ("LD SP,HL".to_string(), 1),
+        0xFA => (format!("LD A,({d16})"), 3),
+        0xFB => ("EI".to_string(), 1),
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD => {
+            (format!("DB ${op:02X}"), 1)
+        }
+        _ => match (x, z) {
+            (0, 1) if op & 0x08 == 0 => (format!("LD {},{d16}", R16[p]), 3),
+            (0, 1) => (format!("ADD HL,{}", R16[p]), 1),
+            (0, 2) => {
+                let mem = ["(BC)", "(DE)", "(HL+)", "(HL-)"][p];
+                if op & 0x08 == 0 {
+                    (format!("LD {mem},A"), 1)
+                } else {
+                    (format!("LD A,{mem}"), 1)
+                }
+            }
+            (0, 3) if op & 0x08 == 0 => (format!("INC {}", R16[p]), 1),
+            (0, 3) => (format!("DEC {}", R16[p]), 1),
+            (0, 4) => (format!("INC {}", R8[y]), 1),
+            (0, 5) => (format!("DEC {}", R8[y]), 1),
+            (0, 6) => (format!("LD {},{d8}", R8[y]), 2),
+            (0, _) => (
+                ["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"][y].to_string(),
+                1,
+            ),
+            (1, _) => (format!("LD {},{}", R8[y], R8[z]), 1),
+            (2, _) => (format!("{}{}", ALU[y], R8[z]), 1),
+            (_, 0) => (format!("RET {}", CONDITIONS[y]), 1),
+            (_, 1) => (format!("POP {}", R16_STACK[p]), 1),
+            (_, 2) => (format!("JP {},{d16}", CONDITIONS[y]), 3),
+            (_, 4) => (format!("CALL {},{d16}", CONDITIONS[y]), 3),
+            (_, 5) => (format!("PUSH {}", R16_STACK[p]), 1),
+            (_, 6) => (format!("{}{d8}", ALU[y]), 2),
+            _ => (format!("RST ${:02X}", y * 8), 1),
+        },
+    };
+    (text, len)
+}
+
+fn disassemble_cb(op: u8) -> (String, u16) {
+    let y = ((op >> 3) & 7) as usize;
+    let r = R8[(op & 7) as usize];
+    let text = match op >> 6 {
+        0 => format!("{} {r}", CB_SHIFTS[y]),
+        1 => format!("BIT {y},{r}"),
+        2 => format!("RES {y},{r}"),
+        _ => format!("SET {y},{r}"),
+    };
+    (text, 2)
+}
@@ -0,0 +1,59 @@
+use clap::ValueEnum;
+
+/// Software filters applied while upscaling the 160x144 framebuffer for
+/// presentation. The emulated pixels themselves are never modified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Filter {
+    /// Plain nearest-neighbor scaling.
+    #[default]
+    None,
+    /// Darken every other output row like a CRT.
+    Scanlines,
+    /// Darken the border of each scaled pixel like an LCD grid.
+    LcdGrid,
+}
+
+/// Brightness kept on darkened scanline rows, out of 256.
+const SCANLINE_LEVEL: u32 = 160;
+/// Brightness kept on LCD grid lines, out of 256.
+const GRID_LEVEL: u32 = 192;
+
+/// Scale each color channel of a 0x00RRGGBB pixel by `level / 256`.
+fn darken(color: u32, level: u32) -> u32 {
+    let r = (((color >> 16) & 0xFF) * level) >> 8;
+    let g = (((color >> 8) & 0xFF) * level) >> 8;
+    let b = ((color & 0xFF) * level) >> 8;
+    (r << 16) | (g << 8) | b
+}
+
+/// Upscale `src` (`width` x `height`) by an integer `scale` into `dst`,
+/// applying `filter`. `dst` must hold `width * height * scale * scale`
+/// pixels.
+pub fn upscale(
+    src: &[u32],
+    width: usize,
+    height: usize,
+    scale: usize,
+    filter: Filter,
+    dst: &mut [u32],
+) {
+    let out_width = width * scale;
+    assert_eq!(dst.len(), out_width * height * scale);
+    for (oy, row) in dst.chunks_exact_mut(out_width).enumerate() {
+        let sy = oy / scale;
+        let src_row = &src[sy * width..(sy + 1) * width];
+        for (ox, px) in row.iter_mut().enumerate() {
+            let color = src_row[ox / scale];
+            *px = match filter {
+                Filter::None => color,
+                Filter::Scanlines if oy % 2 == 1 => darken(color, SCANLINE_LEVEL),
+                Filter::LcdGrid
+                    if scale > 1 && (ox % scale == scale - 1 || oy % scale == scale - 1) =>
+                {
+                    darken(color, GRID_LEVEL)
+                }
+                _ => color,
+            };
+        }
+    }
+}
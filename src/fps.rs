@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Native Game Boy refresh rate: 4194304 Hz / 70224 cycles per frame.
+pub const GB_FRAME_RATE: f64 = 4_194_304.0 / 70_224.0;
+
+/// Rolling average of presented frames over a fixed time window.
+pub struct FpsCounter {
+    window: Duration,
+    stamps: VecDeque<Instant>,
+}
+
+impl FpsCounter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            stamps: VecDeque::new(),
+        }
+    }
+
+    /// Record a frame presented at `now`, dropping timestamps that fell out
+    /// of the averaging window.
+    pub fn record(&mut self, now: Instant) {
+        self.stamps.push_back(now);
+        while let Some(&first) = self.stamps.front()
+            && now.duration_since(first) > self.window
+        {
+            self.stamps.pop_front();
+        }
+    }
+
+    /// Average frames per second across the window, or 0 until at least two
+    /// frames have been recorded.
+    pub fn fps(&self) -> f64 {
+        let (Some(first), Some(last)) = (self.stamps.front(), self.stamps.back()) else {
+            return 0.0;
+        };
+        let elapsed = last.duration_since(*first).as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        (self.stamps.len() - 1) as f64 / elapsed
+    }
+
+    /// Emulation speed relative to real hardware, in percent.
+    pub fn speed_percent(&self) -> f64 {
+        self.fps() / GB_FRAME_RATE * 100.0
+    }
+}
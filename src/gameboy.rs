@@ -1,9 +1,103 @@
-use crate::{cpu::Cpu, mmu::Mmu};
+use crate::{
+    cartridge::NINTENDO_LOGO,
+    cpu::{Cpu, CpuState},
+    fps::GB_FRAME_RATE,
+    mmu::{Mmu, MmuState},
+    profile,
+    rewind::RewindBuffer,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::Path};
+
+/// Size of the DMG boot ROM.
+pub const DMG_BOOT_ROM_SIZE: usize = 0x100;
+/// Size of the CGB boot ROM, including the unmapped 0x100-0x1FF gap where
+/// the cartridge header shows through.
+pub const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+/// Reasons a boot ROM image was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootRomError {
+    WrongSize { expected: usize, actual: usize },
+    MissingLogo,
+}
+
+impl fmt::Display for BootRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootRomError::WrongSize { expected, actual } => {
+                write!(f, "boot ROM is {actual} bytes, expected {expected}")
+            }
+            BootRomError::MissingLogo => write!(f, "boot ROM does not contain the Nintendo logo"),
+        }
+    }
+}
+
+impl std::error::Error for BootRomError {}
+
+/// CPU cycles in one frame at normal speed.
+pub const FRAME_CYCLES: u32 = 70_224;
+
+/// Identifies savestate blobs.
+const SAVESTATE_MAGIC: [u8; 4] = *b"VBSS";
+/// Bumped whenever the savestate layout changes.
+pub const SAVESTATE_VERSION: u32 = 7;
+
+/// Identifies input recordings written by `save_recording`.
+const RECORDING_MAGIC: [u8; 4] = *b"VBIR";
+
+/// Reasons a savestate was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    NotASaveState,
+    UnsupportedVersion(u32),
+    /// The state was saved with a different ROM or hardware model.
+    WrongGame,
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "not a savestate"),
+            SaveStateError::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "savestate version {v} is not supported, expected {SAVESTATE_VERSION}"
+                )
+            }
+            SaveStateError::WrongGame => {
+                write!(f, "savestate belongs to a different ROM or hardware model")
+            }
+            SaveStateError::Corrupt(e) => write!(f, "savestate is corrupt: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+    cgb: bool,
+    /// `profile::rom_hash` of the ROM, if a cartridge was loaded.
+    rom_hash: Option<u32>,
+    cpu: CpuState,
+    mmu: MmuState,
+}
 
 pub struct GameBoy {
     pub cpu: Cpu,
     pub mmu: Mmu,
     pub cgb: bool,
+    rewind: Option<RewindBuffer>,
+    input_log: Option<InputLog>,
+}
+
+/// Per-frame joypad states, one byte each as passed to
+/// `Mmu::set_joypad_state`.
+enum InputLog {
+    Recording(Vec<u8>),
+    Playing { inputs: Vec<u8>, frame: usize },
 }
 
 impl GameBoy {
@@ -16,8 +110,252 @@ impl GameBoy {
             cpu: Cpu::new_with_mode(cgb),
             mmu: Mmu::new_with_mode(cgb),
             cgb,
+            rewind: None,
+            input_log: None,
+        }
+    }
+
+    /// Validate and map a boot ROM image for the current model. The size must
+    /// match the DMG or CGB boot ROM and the image must contain the Nintendo
+    /// logo it compares against the cartridge header. CGB boot ROMs only
+    /// store the first half of the logo. The CPU restarts at 0x0000 so the
+    /// boot ROM runs first.
+    pub fn load_boot_rom_bytes(&mut self, data: Vec<u8>) -> Result<(), BootRomError> {
+        let expected = if self.cgb {
+            CGB_BOOT_ROM_SIZE
+        } else {
+            DMG_BOOT_ROM_SIZE
+        };
+        if data.len() != expected {
+            return Err(BootRomError::WrongSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        let logo = &NINTENDO_LOGO[..0x18];
+        if !data.windows(logo.len()).any(|w| w == logo) {
+            return Err(BootRomError::MissingLogo);
+        }
+        self.mmu.load_boot_rom(data);
+        self.cpu.reset(self.cgb, true);
+        Ok(())
+    }
+
+    /// Run until the PPU completes a frame, or for one frame's worth of
+    /// cycles while the LCD is off, and consume the frame-ready flag. The
+    /// finished frame stays in the PPU framebuffer.
+    pub fn run_frame(&mut self) {
+        self.run_frame_with(|_| true);
+    }
+
+    /// Like `run_frame`, calling `before_step` before every instruction.
+    /// Returns false, leaving the frame unfinished, as soon as `before_step`
+    /// does.
+    #[cfg_attr(feature = "profiling", tracing::instrument(name = "frame", skip_all))]
+    pub fn run_frame_with(&mut self, mut before_step: impl FnMut(&mut Self) -> bool) -> bool {
+        self.log_frame_input();
+        let frame_cycles = FRAME_CYCLES << self.cpu.double_speed as u32;
+        let mut lcd_off_cycles = 0;
+        while !self.mmu.ppu.frame_ready() {
+            if !before_step(self) {
+                return false;
+            }
+            let cycles = self.cpu.step(&mut self.mmu);
+            // No frame is ever completed with the LCD off; return after a
+            // frame's worth of cycles so frontends keep presenting and
+            // polling input at the normal rate.
+            if !self.mmu.ppu.lcd_enabled() {
+                lcd_off_cycles += cycles;
+                if lcd_off_cycles >= frame_cycles {
+                    break;
+                }
+            }
+        }
+        self.mmu.ppu.clear_frame_flag();
+        if self.rewind.as_mut().is_some_and(RewindBuffer::frame_done) {
+            self.push_state();
+        }
+        true
+    }
+
+    /// Run `n` frames, drawing only the last. The skipped frames still run
+    /// the CPU, timer and APU, so audio keeps being produced.
+    pub fn run_frames(&mut self, n: usize) {
+        self.run_frames_with(n, |_| true);
+    }
+
+    /// Like `run_frames`, calling `before_step` before every instruction.
+    /// Returns false as soon as `before_step` does.
+    pub fn run_frames_with(
+        &mut self,
+        n: usize,
+        mut before_step: impl FnMut(&mut Self) -> bool,
+    ) -> bool {
+        let mut running = true;
+        for i in 0..n {
+            self.mmu.ppu.set_render_enabled(i + 1 == n);
+            running = self.run_frame_with(&mut before_step);
+            if !running {
+                break;
+            }
+        }
+        self.mmu.ppu.set_render_enabled(true);
+        running
+    }
+
+    /// Keep a snapshot every `interval` frames for `rewind_one`, holding at
+    /// most `depth` of them.
+    pub fn enable_rewind(&mut self, interval: u32, depth: usize) {
+        self.rewind = Some(RewindBuffer::new(interval, depth));
+    }
+
+    pub fn rewind_buffer(&self) -> Option<&RewindBuffer> {
+        self.rewind.as_ref()
+    }
+
+    /// Add a snapshot of the current state to the rewind buffer. Called by
+    /// `run_frame` once rewinding is enabled.
+    pub fn push_state(&mut self) {
+        let state = self.save_state();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(state);
         }
     }
+
+    /// Step back one snapshot: the newest is dropped and the machine
+    /// returns to the one before it. Returns false when there is nothing
+    /// older to return to.
+    pub fn rewind_one(&mut self) -> bool {
+        let Some(mut rewind) = self.rewind.take() else {
+            return false;
+        };
+        let ok = rewind.len() >= 2 && {
+            rewind.pop();
+            rewind.peek().is_some_and(|s| self.load_state(s).is_ok())
+        };
+        self.rewind = Some(rewind);
+        ok
+    }
+
+    /// Start capturing the joypad state at the beginning of every frame,
+    /// dropping any earlier recording or playback.
+    pub fn start_recording(&mut self) {
+        self.input_log = Some(InputLog::Recording(Vec::new()));
+    }
+
+    /// Write the inputs captured since `start_recording` to `path`.
+    pub fn save_recording(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let Some(InputLog::Recording(inputs)) = &self.input_log else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no input recording in progress",
+            ));
+        };
+        let mut out = RECORDING_MAGIC.to_vec();
+        out.extend_from_slice(inputs);
+        fs::write(path, out)
+    }
+
+    /// Replay a recording made by `save_recording`, one input per frame run
+    /// from now on. Live input is ignored until the recording runs out; run
+    /// from the same starting state as the recording to reproduce it.
+    pub fn play_recording(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = fs::read(path)?;
+        let inputs = data
+            .strip_prefix(&RECORDING_MAGIC)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not an input recording"))?;
+        self.input_log = Some(InputLog::Playing {
+            inputs: inputs.to_vec(),
+            frame: 0,
+        });
+        Ok(())
+    }
+
+    /// True while a recording is being played back, so frontends can stop
+    /// feeding live input.
+    pub fn is_playing_recording(&self) -> bool {
+        matches!(self.input_log, Some(InputLog::Playing { .. }))
+    }
+
+    /// Record or replay the joypad state for the frame about to run.
+    fn log_frame_input(&mut self) {
+        match &mut self.input_log {
+            Some(InputLog::Recording(inputs)) => inputs.push(self.mmu.input.state()),
+            Some(InputLog::Playing { inputs, frame }) => match inputs.get(*frame) {
+                Some(&state) => {
+                    *frame += 1;
+                    self.mmu.set_joypad_state(state);
+                }
+                None => self.input_log = None,
+            },
+            None => {}
+        }
+    }
+
+    /// Reset the machine as if it were switched off and on again, without
+    /// reloading the ROM. Cartridge RAM and the RTC survive, and a loaded
+    /// boot ROM is mapped again and run from 0x0000. Without one the
+    /// machine restarts in the post-boot state at 0x0100.
+    pub fn reset(&mut self) {
+        self.mmu.reset();
+        self.cpu.reset(self.cgb, self.mmu.boot_mapped);
+    }
+
+    /// Hash of the whole loaded ROM, identifying the game a savestate
+    /// belongs to.
+    fn rom_hash(&self) -> Option<u32> {
+        Some(profile::rom_hash(&self.mmu.cart.as_ref()?.rom))
+    }
+
+    /// Serialize the whole machine apart from the ROM into a versioned blob.
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = SaveState {
+            cgb: self.cgb,
+            rom_hash: self.rom_hash(),
+            cpu: self.cpu.save_state(),
+            mmu: self.mmu.save_state(),
+        };
+        let mut out = SAVESTATE_MAGIC.to_vec();
+        out.extend_from_slice(&SAVESTATE_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut out, &state).expect("savestate serialization failed");
+        out
+    }
+
+    /// Restore a blob produced by `save_state`. It must come from the same
+    /// ROM and hardware model; on error the machine is left unchanged.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let (magic, rest) = data
+            .split_first_chunk::<4>()
+            .ok_or(SaveStateError::NotASaveState)?;
+        if *magic != SAVESTATE_MAGIC {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let (version, body) = rest
+            .split_first_chunk::<4>()
+            .ok_or(SaveStateError::NotASaveState)?;
+        let version = u32::from_le_bytes(*version);
+        if version != SAVESTATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let state: SaveState =
+            bincode::deserialize(body).map_err(|e| SaveStateError::Corrupt(e.to_string()))?;
+        if state.cgb != self.cgb || state.rom_hash != self.rom_hash() {
+            return Err(SaveStateError::WrongGame);
+        }
+        self.mmu
+            .check_state(&state.mmu)
+            .map_err(SaveStateError::Corrupt)?;
+        self.cpu.load_state(&state.cpu);
+        self.mmu.load_state(&state.mmu);
+        Ok(())
+    }
+
+    /// Stereo audio samples the APU produces per video frame. A frontend
+    /// can pace presentation on the audio buffer draining by this amount
+    /// to keep audio and video in lockstep.
+    pub fn audio_samples_per_frame(&self) -> f64 {
+        self.mmu.apu.lock().unwrap().sample_rate() as f64 / GB_FRAME_RATE
+    }
 }
 
 impl Default for GameBoy {
@@ -0,0 +1,353 @@
+use crate::{cpu::StepResult, disasm, gameboy::GameBoy};
+use eframe::egui::{self, Color32, ColorImage, Key, TextureHandle, TextureOptions};
+
+/// Width and height of the tile viewer image: 16x24 tiles of 8x8 pixels.
+const TILE_VIEW_SIZE: [usize; 2] = [16 * 8, 24 * 8];
+/// Grey shades used by the tile viewer for color indices 0-3.
+const TILE_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// Emulator state driven by the debugger, independent of any rendering so
+/// it can be exercised without a window.
+pub struct DebuggerState {
+    pub gb: GameBoy,
+    pub paused: bool,
+    /// First address shown in the memory viewer.
+    pub memory_addr: u16,
+    /// Set when execution stopped on a breakpoint.
+    pub hit_breakpoint: Option<u16>,
+}
+
+impl DebuggerState {
+    pub fn new(gb: GameBoy) -> Self {
+        Self {
+            gb,
+            paused: false,
+            memory_addr: 0xC000,
+            hit_breakpoint: None,
+        }
+    }
+
+    /// Execute a single instruction.
+    pub fn step_instruction(&mut self) {
+        let cpu = &mut self.gb.cpu;
+        // Stepping executes the instruction even when PC is on a breakpoint.
+        if let StepResult::BreakpointHit(_) = cpu.step_checked(&mut self.gb.mmu) {
+            cpu.step_checked(&mut self.gb.mmu);
+        }
+    }
+
+    /// Run one frame through `GameBoy::run_frame_with`, stopping early if a
+    /// breakpoint is reached. Returns false if a breakpoint stopped it.
+    pub fn run_frame(&mut self) -> bool {
+        self.hit_breakpoint = None;
+        let mut hit = None;
+        let finished = self.gb.run_frame_with(|gb| {
+            hit = gb.cpu.check_breakpoint(&gb.mmu);
+            hit.is_none()
+        });
+        if !finished {
+            self.hit_breakpoint = hit;
+            self.paused = true;
+        }
+        finished
+    }
+
+    pub fn toggle_breakpoint(&mut self, addr: u16) {
+        let cpu = &mut self.gb.cpu;
+        if cpu.has_breakpoint(addr) {
+            cpu.remove_breakpoint(addr);
+        } else {
+            cpu.add_breakpoint(addr);
+        }
+    }
+
+    /// Disassemble `count` instructions starting at PC.
+    pub fn disassembly(&mut self, count: usize) -> Vec<(u16, String)> {
+        let mut addr = self.gb.cpu.pc;
+        let mut lines = Vec::with_capacity(count);
+        for _ in 0..count {
+            let bytes = [0, 1, 2].map(|i| self.gb.mmu.read_byte(addr.wrapping_add(i)));
+            let (text, len) = disasm::disassemble(addr, bytes);
+            lines.push((addr, text));
+            addr = addr.wrapping_add(len);
+        }
+        lines
+    }
+
+    /// Read `rows` lines of 16 bytes starting at the memory viewer address.
+    pub fn memory_rows(&mut self, rows: usize) -> Vec<(u16, [u8; 16])> {
+        (0..rows)
+            .map(|row| {
+                let base = self.memory_addr.wrapping_add(row as u16 * 16);
+                let bytes =
+                    std::array::from_fn(|i| self.gb.mmu.read_byte(base.wrapping_add(i as u16)));
+                (base, bytes)
+            })
+            .collect()
+    }
+
+    /// Render the 384 tiles of the current VRAM bank in grey shades.
+    pub fn tile_image(&self) -> ColorImage {
+        let [w, h] = TILE_VIEW_SIZE;
+        let mut image = ColorImage::new(TILE_VIEW_SIZE, Color32::WHITE);
+        let vram = &self.gb.mmu.ppu.vram[self.gb.mmu.ppu.vram_bank];
+        for tile in 0..(w / 8) * (h / 8) {
+            let (tx, ty) = (tile % (w / 8) * 8, tile / (w / 8) * 8);
+            for row in 0..8 {
+                let lo = vram[tile * 16 + row * 2];
+                let hi = vram[tile * 16 + row * 2 + 1];
+                for px in 0..8 {
+                    let bit = 7 - px;
+                    let color = (((hi >> bit) & 1) << 1 | ((lo >> bit) & 1)) as usize;
+                    let shade = TILE_SHADES[color];
+                    image[(tx + px, ty + row)] = Color32::from_gray(shade);
+                }
+            }
+        }
+        image
+    }
+
+    /// The current frame as an egui image.
+    pub fn screen_image(&self) -> ColorImage {
+        let pixels = self
+            .gb
+            .mmu
+            .ppu
+            .framebuffer()
+            .iter()
+            .map(|&c| Color32::from_rgb((c >> 16) as u8, (c >> 8) as u8, c as u8))
+            .collect();
+        ColorImage {
+            size: [160, 144],
+            pixels,
+        }
+    }
+}
+
+/// The egui debugger frontend.
+pub struct DebuggerApp {
+    state: DebuggerState,
+    screen: Option<TextureHandle>,
+    tiles: Option<TextureHandle>,
+    breakpoint_input: String,
+}
+
+impl DebuggerApp {
+    pub fn new(gb: GameBoy) -> Self {
+        Self {
+            state: DebuggerState::new(gb),
+            screen: None,
+            tiles: None,
+            breakpoint_input: String::new(),
+        }
+    }
+
+    fn update_input(&mut self, ctx: &egui::Context) {
+        const KEYS: [(Key, u8); 8] = [
+            (Key::ArrowRight, 0x01),
+            (Key::ArrowLeft, 0x02),
+            (Key::ArrowUp, 0x04),
+            (Key::ArrowDown, 0x08),
+            (Key::S, 0x10),
+            (Key::A, 0x20),
+            (Key::Backspace, 0x40),
+            (Key::Enter, 0x80),
+        ];
+        let mut state = 0xFFu8;
+        ctx.input(|i| {
+            for (key, mask) in KEYS {
+                if i.key_down(key) {
+                    state &= !mask;
+                }
+            }
+        });
+        self.state.gb.mmu.set_joypad_state(state);
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let label = if self.state.paused { "Run" } else { "Pause" };
+            if ui.button(label).clicked() {
+                self.state.paused = !self.state.paused;
+            }
+            if ui.button("Step").clicked() {
+                self.state.paused = true;
+                self.state.step_instruction();
+            }
+            if ui.button("Frame").clicked() {
+                self.state.paused = true;
+                self.state.run_frame();
+            }
+            if let Some(pc) = self.state.hit_breakpoint {
+                ui.label(format!("Breakpoint at {pc:04X}"));
+            }
+        });
+    }
+
+    fn registers(&self, ui: &mut egui::Ui) {
+        let cpu = &self.state.gb.cpu;
+        egui::Grid::new("registers").show(ui, |ui| {
+            let pairs = [
+                ("AF", u16::from_be_bytes([cpu.a, cpu.f])),
+                ("BC", u16::from_be_bytes([cpu.b, cpu.c])),
+                ("DE", u16::from_be_bytes([cpu.d, cpu.e])),
+                ("HL", cpu.get_hl()),
+                ("SP", cpu.sp),
+                ("PC", cpu.pc),
+            ];
+            for (name, val) in pairs {
+                ui.monospace(name);
+                ui.monospace(format!("{val:04X}"));
+                ui.end_row();
+            }
+            ui.monospace("IME");
+            ui.monospace(if cpu.ime { "1" } else { "0" });
+            ui.end_row();
+            ui.monospace("IF/IE");
+            ui.monospace(format!(
+                "{:02X}/{:02X}",
+                self.state.gb.mmu.if_reg, self.state.gb.mmu.ie_reg
+            ));
+            ui.end_row();
+        });
+    }
+
+    fn disassembly(&mut self, ui: &mut egui::Ui) {
+        for (addr, text) in self.state.disassembly(16) {
+            let marker = if self.state.gb.cpu.has_breakpoint(addr) {
+                "●"
+            } else {
+                " "
+            };
+            let line = format!("{marker} {addr:04X}  {text}");
+            // Clicking a line toggles a breakpoint on it.
+            if ui
+                .add(
+                    egui::Label::new(egui::RichText::new(line).monospace())
+                        .sense(egui::Sense::click()),
+                )
+                .clicked()
+            {
+                self.state.toggle_breakpoint(addr);
+            }
+        }
+    }
+
+    fn breakpoints(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.breakpoint_input);
+            if ui.button("Add").clicked()
+                && let Ok(addr) = u16::from_str_radix(self.breakpoint_input.trim(), 16)
+            {
+                self.state.gb.cpu.add_breakpoint(addr);
+                self.breakpoint_input.clear();
+            }
+        });
+        let mut remove = None;
+        let mut addrs: Vec<u16> = self.state.gb.cpu.breakpoints().collect();
+        addrs.sort_unstable();
+        for addr in addrs {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{addr:04X}"));
+                if ui.small_button("x").clicked() {
+                    remove = Some(addr);
+                }
+            });
+        }
+        if let Some(addr) = remove {
+            self.state.gb.cpu.remove_breakpoint(addr);
+        }
+    }
+
+    fn memory(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Address");
+            ui.add(egui::DragValue::new(&mut self.state.memory_addr).hexadecimal(4, false, true));
+        });
+        for (addr, bytes) in self.state.memory_rows(16) {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            ui.monospace(format!("{addr:04X}: {}", hex.join(" ")));
+        }
+    }
+
+    fn sprites(&self, ui: &mut egui::Ui) {
+        let oam = &self.state.gb.mmu.ppu.oam;
+        egui::Grid::new("sprites").striped(true).show(ui, |ui| {
+            for h in ["#", "Y", "X", "Tile", "Flags"] {
+                ui.strong(h);
+            }
+            ui.end_row();
+            for (i, s) in oam.chunks(4).enumerate() {
+                ui.monospace(format!("{i:02}"));
+                for b in s {
+                    ui.monospace(format!("{b:02X}"));
+                }
+                ui.end_row();
+            }
+        });
+    }
+}
+
+/// Upload `image` into `slot`, creating the texture on first use.
+fn update_texture(
+    ctx: &egui::Context,
+    slot: &mut Option<TextureHandle>,
+    name: &str,
+    image: ColorImage,
+) -> TextureHandle {
+    match slot {
+        Some(tex) => tex.set(image, TextureOptions::NEAREST),
+        None => *slot = Some(ctx.load_texture(name, image, TextureOptions::NEAREST)),
+    }
+    slot.clone().unwrap()
+}
+
+impl eframe::App for DebuggerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_input(ctx);
+        if !self.state.paused {
+            self.state.run_frame();
+            ctx.request_repaint();
+        }
+
+        let screen = update_texture(ctx, &mut self.screen, "screen", self.state.screen_image());
+        let tiles = update_texture(ctx, &mut self.tiles, "tiles", self.state.tile_image());
+
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| self.controls(ui));
+        egui::SidePanel::right("cpu").show(ctx, |ui| {
+            ui.heading("Registers");
+            self.registers(ui);
+            ui.separator();
+            ui.heading("Disassembly");
+            self.disassembly(ui);
+            ui.separator();
+            ui.heading("Breakpoints");
+            self.breakpoints(ui);
+        });
+        egui::SidePanel::left("video").show(ctx, |ui| {
+            ui.heading("Tiles");
+            ui.add(egui::Image::new(&tiles).fit_to_exact_size(egui::vec2(256.0, 384.0)));
+            ui.heading("Sprites");
+            egui::ScrollArea::vertical().show(ui, |ui| self.sprites(ui));
+        });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.add(egui::Image::new(&screen).fit_to_exact_size(egui::vec2(480.0, 432.0)));
+            ui.separator();
+            ui.heading("Memory");
+            self.memory(ui);
+        });
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.state.gb.mmu.save_cart_ram();
+    }
+}
+
+/// Open the debugger window and run until it is closed.
+pub fn run(gb: GameBoy) -> eframe::Result<()> {
+    eframe::run_native(
+        "vibeEmu debugger",
+        eframe::NativeOptions::default(),
+        Box::new(|_cc| Ok(Box::new(DebuggerApp::new(gb)))),
+    )
+}
@@ -0,0 +1,229 @@
+use crate::rtc::RTC_CYCLES_PER_SECOND;
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: u16 = 1440;
+/// Nibbles of scratch memory reachable through the command interface.
+const MEMORY_SIZE: usize = 0x100;
+/// Size of the `.rtc` save: scratch memory, minutes and days as
+/// little-endian u16s, and a little-endian u64 Unix timestamp.
+pub const HUC3_SAVE_LEN: usize = MEMORY_SIZE + 12;
+
+/// Values written to 0x0000-0x1FFF to choose what 0xA000-0xBFFF maps.
+const MODE_RAM_READ: u8 = 0x0;
+const MODE_RAM: u8 = 0xA;
+const MODE_COMMAND: u8 = 0xB;
+const MODE_RESPONSE: u8 = 0xC;
+const MODE_SEMAPHORE: u8 = 0xD;
+const MODE_IR: u8 = 0xE;
+
+/// Value read from the IR port when no light is received.
+pub const IR_IDLE: u8 = 0xC0;
+
+/// HuC3 mapper state: ROM/RAM banking, the register-select scheme at
+/// 0x0000-0x1FFF, the real-time clock and the infrared LED.
+///
+/// The clock is reached through a small command interface: a command is
+/// written in mode 0xB, executed by clearing bit 0 of the semaphore in
+/// mode 0xD, and its result read back in mode 0xC. Commands operate on a
+/// 256-nibble scratch memory whose first seven nibbles hold the clock.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Huc3 {
+    pub rom_bank: u8,
+    pub ram_bank: u8,
+    mode: u8,
+    /// Last command written in mode 0xB.
+    command: u8,
+    /// Command in bits 4-6 and result nibble in bits 0-3.
+    response: u8,
+    access_index: u8,
+    memory: Vec<u8>,
+    /// Minutes since midnight and day counter.
+    minutes: u16,
+    days: u16,
+    cycles: u32,
+    ir_led: bool,
+}
+
+impl Default for Huc3 {
+    fn default() -> Self {
+        Self {
+            rom_bank: 1,
+            ram_bank: 0,
+            mode: MODE_RAM_READ,
+            command: 0,
+            response: 0,
+            access_index: 0,
+            memory: vec![0; MEMORY_SIZE],
+            minutes: 0,
+            days: 0,
+            cycles: 0,
+            ir_led: false,
+        }
+    }
+}
+
+impl Huc3 {
+    /// Whether the scratch memory has its full size, as it must before the
+    /// command interface can index it. Used to reject corrupt savestates.
+    pub fn is_valid(&self) -> bool {
+        self.memory.len() == MEMORY_SIZE
+    }
+
+    /// Return the registers to their power-on state, keeping the clock and
+    /// its scratch memory running.
+    pub fn reset(&mut self) {
+        *self = Self {
+            memory: std::mem::take(&mut self.memory),
+            minutes: self.minutes,
+            days: self.days,
+            cycles: self.cycles,
+            ..Self::default()
+        };
+    }
+
+    /// Handle a write to 0x0000-0x7FFF.
+    pub fn write_control(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.mode = val & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = (val & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = val & 0x03,
+            _ => {}
+        }
+    }
+
+    /// Read 0xA000-0xBFFF; `ram` is the cartridge RAM.
+    pub fn read(&self, addr: u16, ram: &[u8]) -> u8 {
+        match self.mode {
+            MODE_RAM_READ | MODE_RAM => ram.get(self.ram_index(addr)).copied().unwrap_or(0xFF),
+            MODE_COMMAND | MODE_RESPONSE => 0x80 | self.response,
+            // The clock answers every command immediately.
+            MODE_SEMAPHORE => 0x01,
+            MODE_IR => IR_IDLE,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write 0xA000-0xBFFF; `ram` is the cartridge RAM.
+    pub fn write(&mut self, addr: u16, val: u8, ram: &mut [u8]) {
+        match self.mode {
+            MODE_RAM => {
+                if let Some(b) = ram.get_mut(self.ram_index(addr)) {
+                    *b = val;
+                }
+            }
+            MODE_COMMAND => self.command = val & 0x7F,
+            MODE_SEMAPHORE if val & 0x01 == 0 => self.execute(),
+            MODE_IR => self.ir_led = val & 0x01 != 0,
+            _ => {}
+        }
+    }
+
+    fn ram_index(&self, addr: u16) -> usize {
+        self.ram_bank as usize * 0x2000 + addr as usize - 0xA000
+    }
+
+    fn execute(&mut self) {
+        let cmd = self.command >> 4;
+        let arg = self.command & 0x0F;
+        let mut result = 0;
+        match cmd {
+            // Read a nibble and advance.
+            0x1 => {
+                result = self.memory[self.access_index as usize];
+                self.access_index = self.access_index.wrapping_add(1);
+            }
+            // Write a nibble and advance.
+            0x3 => {
+                self.memory[self.access_index as usize] = arg;
+                self.access_index = self.access_index.wrapping_add(1);
+            }
+            0x4 => self.access_index = (self.access_index & 0xF0) | arg,
+            0x5 => self.access_index = (self.access_index & 0x0F) | arg << 4,
+            0x6 => match arg {
+                0x0 => self.store_clock(),
+                0x1 => self.load_clock(),
+                // Status: report the clock as ready.
+                0x2 => result = 0x1,
+                _ => {}
+            },
+            _ => {}
+        }
+        self.response = (cmd & 0x07) << 4 | result;
+    }
+
+    /// Copy the clock into scratch nibbles 0-6.
+    fn store_clock(&mut self) {
+        for i in 0..3 {
+            self.memory[i] = (self.minutes >> (i * 4)) as u8 & 0x0F;
+        }
+        for i in 0..4 {
+            self.memory[3 + i] = (self.days >> (i * 4)) as u8 & 0x0F;
+        }
+    }
+
+    /// Set the clock from scratch nibbles 0-6.
+    fn load_clock(&mut self) {
+        let nibbles = |range: std::ops::Range<usize>| {
+            self.memory[range]
+                .iter()
+                .rev()
+                .fold(0u16, |acc, &n| acc << 4 | n as u16)
+        };
+        self.minutes = nibbles(0..3) % MINUTES_PER_DAY;
+        self.days = nibbles(3..7);
+        self.cycles = 0;
+    }
+
+    /// Advance the clock by `cycles` normal-speed system clock cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        const CYCLES_PER_MINUTE: u32 = RTC_CYCLES_PER_SECOND * 60;
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_MINUTE {
+            self.cycles -= CYCLES_PER_MINUTE;
+            self.minutes += 1;
+            if self.minutes == MINUTES_PER_DAY {
+                self.minutes = 0;
+                self.days = self.days.wrapping_add(1);
+            }
+        }
+    }
+
+    /// Serialize the clock and scratch memory in the `.rtc` layout, stamped
+    /// with `now` (Unix seconds).
+    pub fn to_save_bytes(&self, now: u64) -> [u8; HUC3_SAVE_LEN] {
+        let mut out = [0; HUC3_SAVE_LEN];
+        out[..MEMORY_SIZE].copy_from_slice(&self.memory);
+        out[MEMORY_SIZE..MEMORY_SIZE + 2].copy_from_slice(&self.minutes.to_le_bytes());
+        out[MEMORY_SIZE + 2..MEMORY_SIZE + 4].copy_from_slice(&self.days.to_le_bytes());
+        out[MEMORY_SIZE + 4..].copy_from_slice(&now.to_le_bytes());
+        out
+    }
+
+    /// Restore a clock saved by `to_save_bytes` and advance it by the time
+    /// elapsed between its timestamp and `now`. Returns false, leaving the
+    /// clock untouched, if `data` has the wrong size.
+    pub fn load_save_bytes(&mut self, data: &[u8], now: u64) -> bool {
+        if data.len() != HUC3_SAVE_LEN {
+            return false;
+        }
+        let word = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        self.memory.copy_from_slice(&data[..MEMORY_SIZE]);
+        self.minutes = word(MEMORY_SIZE) % MINUTES_PER_DAY;
+        self.days = word(MEMORY_SIZE + 2);
+        self.cycles = 0;
+        let saved = u64::from_le_bytes(data[MEMORY_SIZE + 4..].try_into().unwrap());
+        let elapsed = now.saturating_sub(saved);
+        let minutes = self.minutes as u64 + elapsed / 60;
+        self.minutes = (minutes % MINUTES_PER_DAY as u64) as u16;
+        self.days = self
+            .days
+            .wrapping_add((minutes / MINUTES_PER_DAY as u64) as u16);
+        self.tick((elapsed % 60) as u32 * RTC_CYCLES_PER_SECOND);
+        true
+    }
+
+    /// Whether the infrared LED is switched on.
+    pub fn ir_led(&self) -> bool {
+        self.ir_led
+    }
+}
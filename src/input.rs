@@ -1,3 +1,29 @@
+use crate::interrupts::{InterruptType, Interrupts};
+use serde::{Deserialize, Serialize};
+
+/// A joypad button, in the bit order of the state passed to
+/// `Input::update_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JoypadButton {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl JoypadButton {
+    /// Bit of this button in the joypad state; 0 means pressed.
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Input {
     p1: u8,
     state: u8,
@@ -12,13 +38,18 @@ impl Input {
     }
 
     pub fn read(&self) -> u8 {
-        let mut res = self.p1 & 0xF0;
-        if self.p1 & 0x10 == 0 {
-            res |= self.state & 0x0F;
-        } else if self.p1 & 0x20 == 0 {
-            res |= (self.state >> 4) & 0x0F;
-        } else {
-            res |= 0x0F;
+        (self.p1 & 0xF0) | Self::lines(self.p1, self.state)
+    }
+
+    /// Low nibble of P1 for the given select bits and button state. When
+    /// both lines are selected a pressed button on either pulls its bit low.
+    fn lines(p1: u8, state: u8) -> u8 {
+        let mut res = 0x0F;
+        if p1 & 0x10 == 0 {
+            res &= state & 0x0F;
+        }
+        if p1 & 0x20 == 0 {
+            res &= (state >> 4) & 0x0F;
         }
         res
     }
@@ -27,19 +58,32 @@ impl Input {
         self.p1 = (self.p1 & 0xCF) | (val & 0x30);
     }
 
+    /// Current button state; 0 means pressed.
+    pub fn state(&self) -> u8 {
+        self.state
+    }
+
     pub fn set_state(&mut self, state: u8) {
         self.state = state;
     }
 
-    /// Update the input state and set the joypad interrupt flag if any
-    /// button transitioned from released to pressed.
-    pub fn update_state(&mut self, state: u8, if_reg: &mut u8) {
+    /// Update the input state. Returns true if any P1 input bit on a
+    /// selected line transitioned from high to low, which should raise the
+    /// joypad interrupt.
+    pub fn button_event(&mut self, state: u8) -> bool {
         // Bits are active-low: 0 = pressed
-        let newly_pressed = self.state & !state;
-        if newly_pressed != 0 {
-            *if_reg |= 0x10; // Joypad interrupt
-        }
+        let before = Self::lines(self.p1, self.state);
+        let after = Self::lines(self.p1, state);
         self.state = state;
+        before & !after != 0
+    }
+
+    /// Like `button_event`, but sets the joypad interrupt flag in `if_reg`
+    /// directly.
+    pub fn update_state(&mut self, state: u8, if_reg: &mut u8) {
+        if self.button_event(state) {
+            Interrupts::request(if_reg, InterruptType::Joypad);
+        }
     }
 }
 
@@ -0,0 +1,103 @@
+/// Interrupt sources in priority order. Each maps to a bit in the IF and IE
+/// registers and to a fixed handler address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptType {
+    VBlank,
+    LcdStat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+impl InterruptType {
+    /// All interrupt sources from highest to lowest priority.
+    pub const ALL: [InterruptType; 5] = [
+        InterruptType::VBlank,
+        InterruptType::LcdStat,
+        InterruptType::Timer,
+        InterruptType::Serial,
+        InterruptType::Joypad,
+    ];
+
+    /// Bit mask of this interrupt within IF/IE.
+    pub const fn bit(self) -> u8 {
+        match self {
+            InterruptType::VBlank => 0x01,
+            InterruptType::LcdStat => 0x02,
+            InterruptType::Timer => 0x04,
+            InterruptType::Serial => 0x08,
+            InterruptType::Joypad => 0x10,
+        }
+    }
+
+    /// Address the CPU jumps to when servicing this interrupt.
+    pub const fn vector(self) -> u16 {
+        match self {
+            InterruptType::VBlank => 0x40,
+            InterruptType::LcdStat => 0x48,
+            InterruptType::Timer => 0x50,
+            InterruptType::Serial => 0x58,
+            InterruptType::Joypad => 0x60,
+        }
+    }
+}
+
+/// Helpers for requesting, querying and acknowledging interrupts through
+/// the IF and IE registers.
+pub struct Interrupts;
+
+impl Interrupts {
+    /// Set the IF bit for `interrupt`.
+    pub fn request(if_reg: &mut u8, interrupt: InterruptType) {
+        *if_reg |= interrupt.bit();
+    }
+
+    /// Clear the IF bit for `interrupt` once it has been serviced.
+    pub fn acknowledge(if_reg: &mut u8, interrupt: InterruptType) {
+        *if_reg &= !interrupt.bit();
+    }
+
+    /// Highest-priority interrupt that is both requested and enabled.
+    pub fn pending(if_reg: u8, ie_reg: u8) -> Option<InterruptType> {
+        let pending = if_reg & ie_reg;
+        InterruptType::ALL
+            .into_iter()
+            .find(|i| pending & i.bit() != 0)
+    }
+}
+
+/// Counts interrupt requests and passes each one to an optional callback,
+/// for debuggers and test harnesses. A request is seen when it sets an IF
+/// bit that was clear.
+#[derive(Default)]
+pub struct InterruptObserver {
+    callback: Option<Box<dyn FnMut(InterruptType)>>,
+    counts: [u64; 5],
+}
+
+impl InterruptObserver {
+    pub fn set_callback(&mut self, callback: Box<dyn FnMut(InterruptType)>) {
+        self.callback = Some(callback);
+    }
+
+    /// Requests seen per interrupt, in `InterruptType::ALL` order.
+    pub fn counts(&self) -> [u64; 5] {
+        self.counts
+    }
+
+    /// Report the interrupts requested between two IF values.
+    pub fn observe(&mut self, if_before: u8, if_after: u8) {
+        let raised = if_after & !if_before;
+        if raised & 0x1F == 0 {
+            return;
+        }
+        for (i, interrupt) in InterruptType::ALL.into_iter().enumerate() {
+            if raised & interrupt.bit() != 0 {
+                self.counts[i] += 1;
+                if let Some(callback) = self.callback.as_mut() {
+                    callback(interrupt);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::input::JoypadButton;
+use minifb::Key;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+
+/// Reasons a key binding config could not be loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMapError {
+    /// The file is not valid TOML or has the wrong shape.
+    Parse(String),
+    UnknownKey(String),
+    /// The key is one of `RESERVED_KEYS` and cannot also be a button.
+    ReservedKey(String),
+}
+
+impl fmt::Display for KeyMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyMapError::Parse(e) => write!(f, "{e}"),
+            KeyMapError::UnknownKey(k) => write!(f, "unknown key '{k}'"),
+            KeyMapError::ReservedKey(k) => write!(f, "key '{k}' is reserved for a hotkey"),
+        }
+    }
+}
+
+impl std::error::Error for KeyMapError {}
+
+/// Window frontend hotkeys.
+pub const QUIT_KEY: Key = Key::Escape;
+pub const PAUSE_KEY: Key = Key::P;
+pub const RESET_KEY: Key = Key::R;
+/// Held to fast-forward.
+pub const TURBO_KEY: Key = Key::Space;
+/// Held to rewind.
+pub const REWIND_KEY: Key = Key::Backspace;
+/// Toggle APU channels 1-4.
+pub const MUTE_KEYS: [Key; 4] = [Key::Key1, Key::Key2, Key::Key3, Key::Key4];
+
+/// Keys taken by the hotkeys above, which a config may not bind to a
+/// button.
+pub const RESERVED_KEYS: [Key; 9] = [
+    QUIT_KEY,
+    PAUSE_KEY,
+    RESET_KEY,
+    TURBO_KEY,
+    REWIND_KEY,
+    MUTE_KEYS[0],
+    MUTE_KEYS[1],
+    MUTE_KEYS[2],
+    MUTE_KEYS[3],
+];
+
+/// Keyboard keys bound to each joypad button.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: Vec<(Key, JoypadButton)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Key::Right, JoypadButton::Right),
+                (Key::Left, JoypadButton::Left),
+                (Key::Up, JoypadButton::Up),
+                (Key::Down, JoypadButton::Down),
+                (Key::S, JoypadButton::A),
+                (Key::A, JoypadButton::B),
+                (Key::LeftShift, JoypadButton::Select),
+                (Key::RightShift, JoypadButton::Select),
+                (Key::Enter, JoypadButton::Start),
+            ],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    keys: HashMap<JoypadButton, KeyList>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyMap {
+    /// Parse a TOML config whose `[keys]` table maps button names to a key
+    /// name or a list of them:
+    ///
+    /// ```toml
+    /// [keys]
+    /// a = "X"
+    /// b = "Z"
+    /// select = ["LeftShift", "RightShift"]
+    /// ```
+    ///
+    /// Buttons not listed keep their default keys. Key names are those of
+    /// `minifb::Key`, e.g. `Enter`, `Tab`, `NumPad5` or `Key5`; the
+    /// `RESERVED_KEYS` are rejected.
+    pub fn from_toml(text: &str) -> Result<Self, KeyMapError> {
+        let config: Config = toml::from_str(text).map_err(|e| KeyMapError::Parse(e.to_string()))?;
+        let mut map = Self::default();
+        for (button, keys) in config.keys {
+            let names = match keys {
+                KeyList::One(name) => vec![name],
+                KeyList::Many(names) => names,
+            };
+            map.bindings.retain(|&(_, b)| b != button);
+            for name in names {
+                let key = parse_key(&name).ok_or_else(|| KeyMapError::UnknownKey(name.clone()))?;
+                if RESERVED_KEYS.contains(&key) {
+                    return Err(KeyMapError::ReservedKey(name));
+                }
+                map.bindings.push((key, button));
+            }
+        }
+        Ok(map)
+    }
+
+    /// The button bound to `key`, if any.
+    pub fn button_for(&self, key: Key) -> Option<JoypadButton> {
+        self.bindings
+            .iter()
+            .find(|&&(k, _)| k == key)
+            .map(|&(_, b)| b)
+    }
+
+    /// Joypad state (0 = pressed) for the keys `is_down` reports as held.
+    pub fn joypad_state(&self, mut is_down: impl FnMut(Key) -> bool) -> u8 {
+        self.bindings
+            .iter()
+            .filter(|&&(key, _)| is_down(key))
+            .fold(0xFF, |state, &(_, button)| state & !button.mask())
+    }
+}
+
+/// Look up a `minifb::Key` by its variant name, ignoring case.
+fn parse_key(name: &str) -> Option<Key> {
+    KEY_NAMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, k)| k)
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, Key)] = &[$((stringify!($key), Key::$key)),*];
+    };
+}
+
+#[rustfmt::skip]
+key_names!(
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, A, B, C, D, E, F, G, H, I, J, K, L,
+    M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Down, Left, Right, Up, Apostrophe, Backquote, Backslash, Comma, Equal, LeftBracket, Minus,
+    Period, RightBracket, Semicolon, Slash, Backspace, Delete, End, Enter, Escape, Home, Insert,
+    Menu, PageDown, PageUp, Pause, Space, Tab, NumLock, CapsLock, ScrollLock, LeftShift, RightShift,
+    LeftCtrl, RightCtrl, NumPad0, NumPad1, NumPad2, NumPad3, NumPad4, NumPad5, NumPad6, NumPad7,
+    NumPad8, NumPad9, NumPadDot, NumPadSlash, NumPadAsterisk, NumPadMinus, NumPadPlus, NumPadEnter,
+    LeftAlt, RightAlt, LeftSuper, RightSuper,
+);
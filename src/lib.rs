@@ -4,9 +4,27 @@
 pub mod apu;
 pub mod cartridge;
 pub mod cpu;
+pub mod disasm;
+pub mod filter;
+pub mod fps;
 pub mod gameboy;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod huc3;
 pub mod input;
+pub mod interrupts;
+pub mod keymap;
+pub mod link;
+pub mod loop_detector;
+pub mod mbc7;
 pub mod mmu;
+pub mod palette;
 pub mod ppu;
+pub mod profile;
+pub mod rewind;
+pub mod rtc;
+pub mod screenshot;
 pub mod serial;
 pub mod timer;
+pub mod trace;
+pub mod wav;
@@ -0,0 +1,122 @@
+use crate::serial::LinkPort;
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+/// Message tags exchanged by `LinkCable`. Each message is a tag, a
+/// sequence number and one data byte. A REPLY carries the sequence number
+/// of the DATA it answers.
+const LINK_DATA: u8 = 0x01;
+const LINK_REPLY: u8 = 0x02;
+const MESSAGE_LEN: usize = 3;
+/// How long the clocking side waits for its partner's byte by default.
+pub const DEFAULT_LINK_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// A link cable to another emulator over TCP.
+///
+/// Whichever side starts an internal-clock transfer provides the clock: it
+/// sends its byte and blocks until the partner replies with the byte from
+/// its own SB, or until the timeout passes and the transfer reads 0xFF as
+/// an idle line would. The partner answers once its game has armed an
+/// external-clock transfer. Replies that arrive after their transfer timed
+/// out are recognised by their sequence number and dropped. If both sides
+/// clock at once, each answers the other with 0xFF.
+pub struct LinkCable {
+    stream: TcpStream,
+    rx: Vec<u8>,
+    seq: u8,
+    timeout: Duration,
+}
+
+impl LinkCable {
+    /// Wait for a partner to connect on `port`.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let (stream, _) = listener.accept()?;
+        Self::from_stream(stream)
+    }
+
+    /// Connect to a partner listening at `addr` (`host:port`).
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        Self::from_stream(TcpStream::connect(addr)?)
+    }
+
+    /// Use an already connected stream as the cable.
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            rx: Vec::new(),
+            seq: 0,
+            timeout: DEFAULT_LINK_TIMEOUT,
+        })
+    }
+
+    /// Set how long a clocked transfer waits for the partner's reply. The
+    /// emulator is blocked for at most this long per byte.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn send(&mut self, tag: u8, seq: u8, byte: u8) {
+        if let Err(e) = self.stream.write_all(&[tag, seq, byte]) {
+            log::warn!("Link cable write failed: {e}");
+        }
+    }
+
+    /// Read the next message, waiting up to the timeout if `block`.
+    fn recv(&mut self, block: bool) -> Option<(u8, u8, u8)> {
+        let setup = if block {
+            self.stream
+                .set_nonblocking(false)
+                .and_then(|_| self.stream.set_read_timeout(Some(self.timeout)))
+        } else {
+            self.stream.set_nonblocking(true)
+        };
+        if setup.is_err() {
+            return None;
+        }
+        let mut buf = [0; MESSAGE_LEN];
+        while self.rx.len() < MESSAGE_LEN {
+            match self.stream.read(&mut buf[..MESSAGE_LEN - self.rx.len()]) {
+                Ok(0) => return None,
+                Ok(n) => self.rx.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return None,
+            }
+        }
+        let msg = (self.rx[0], self.rx[1], self.rx[2]);
+        self.rx.clear();
+        Some(msg)
+    }
+}
+
+impl LinkPort for LinkCable {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.seq = self.seq.wrapping_add(1);
+        self.send(LINK_DATA, self.seq, byte);
+        loop {
+            match self.recv(true) {
+                Some((LINK_REPLY, seq, received)) if seq == self.seq => return received,
+                // The partner is clocking too.
+                Some((LINK_DATA, seq, _)) => self.send(LINK_REPLY, seq, 0xFF),
+                // Stale replies to transfers that already timed out.
+                Some(_) => {}
+                None => return 0xFF,
+            }
+        }
+    }
+
+    fn poll(&mut self, byte: u8) -> Option<u8> {
+        // Bytes the partner clocked earlier wait in the socket until now.
+        let (seq, received) = loop {
+            if let (LINK_DATA, seq, received) = self.recv(false)? {
+                break (seq, received);
+            }
+        };
+        self.send(LINK_REPLY, seq, byte);
+        Some(received)
+    }
+}
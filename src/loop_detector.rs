@@ -0,0 +1,55 @@
+use crate::cpu::Cpu;
+
+/// Consecutive jump-to-self instructions required before a loop is reported.
+pub const DEFAULT_THRESHOLD: u32 = 1000;
+
+/// Detects the CPU spinning on a jump-to-self such as `jr $`, which many
+/// test ROMs use to signal that they have finished.
+pub struct LoopDetector {
+    threshold: u32,
+    repeats: u32,
+    pc: u16,
+}
+
+impl LoopDetector {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            repeats: 0,
+            pc: 0,
+        }
+    }
+
+    /// Observe the CPU after a step that started at `pc_before`. Returns true
+    /// once PC has stayed on the same instruction for `threshold` consecutive
+    /// steps. Halted and stopped steps are ignored since HALT and STOP also
+    /// leave PC unchanged.
+    pub fn observe(&mut self, pc_before: u16, cpu: &Cpu) -> bool {
+        if cpu.halted || cpu.stopped {
+            return false;
+        }
+        if cpu.pc == pc_before && pc_before == self.pc {
+            self.repeats += 1;
+        } else {
+            self.pc = cpu.pc;
+            self.repeats = u32::from(cpu.pc == pc_before);
+        }
+        self.detected()
+    }
+
+    /// Returns true if a jump-to-self loop has been detected.
+    pub fn detected(&self) -> bool {
+        self.repeats >= self.threshold
+    }
+
+    /// Address of the looping instruction.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+}
+
+impl Default for LoopDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD)
+    }
+}
@@ -3,18 +3,37 @@
 mod apu;
 mod cartridge;
 mod cpu;
+mod disasm;
+mod filter;
+mod fps;
 mod gameboy;
+#[cfg(feature = "gui")]
+mod gui;
+mod huc3;
 mod input;
+mod interrupts;
+mod keymap;
+mod link;
+mod loop_detector;
+mod mbc7;
 mod mmu;
+mod palette;
 mod ppu;
+mod profile;
+mod rewind;
+mod rtc;
+mod screenshot;
 mod serial;
 mod timer;
+mod trace;
+mod wav;
 
 use clap::Parser;
 use log::info;
-use minifb::{Key, Scale, Window, WindowOptions};
+use minifb::{Window, WindowOptions};
+use std::process::ExitCode;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 struct Args {
@@ -41,22 +60,310 @@ struct Args {
     #[arg(long)]
     debug: bool,
 
+    /// Attach a Game Boy Printer and save its prints to this PNG
+    #[arg(long, value_name = "FILE")]
+    printer: Option<std::path::PathBuf>,
+
+    /// Wait for a link cable partner to connect on this TCP port
+    #[arg(long, value_name = "PORT", conflicts_with_all = ["printer", "link_connect"])]
+    link_listen: Option<u16>,
+
+    /// Connect a link cable to a partner at HOST:PORT
+    #[arg(long, value_name = "HOST:PORT", conflicts_with = "printer")]
+    link_connect: Option<String>,
+
     /// Run without opening a window
     #[arg(long)]
     headless: bool,
+
+    /// Do not open an audio output device
+    #[arg(long)]
+    no_audio: bool,
+
+    /// Write the audio output to this WAV file instead of playing it
+    #[arg(long, value_name = "FILE")]
+    wav: Option<std::path::PathBuf>,
+
+    /// Compare execution against a reference instruction trace and stop at
+    /// the first divergence
+    #[arg(long, value_name = "FILE")]
+    compare_trace: Option<std::path::PathBuf>,
+
+    /// Load DMG colors from a palette file (JASC-PAL, key=RRGGBB or hex list)
+    #[arg(long, value_name = "FILE")]
+    palette_file: Option<std::path::PathBuf>,
+
+    /// Built-in DMG color scheme
+    #[arg(long, value_enum, conflicts_with = "palette_file")]
+    palette: Option<palette::PalettePreset>,
+
+    /// Treat illegal opcodes as NOPs instead of locking up the CPU
+    #[arg(long)]
+    lenient: bool,
+
+    /// Override the mapper declared in the cartridge header
+    /// (none, mbc1, mbc3, mbc30, mbc5, camera)
+    #[arg(long, value_name = "TYPE")]
+    force_mbc: Option<cartridge::MbcType>,
+
+    /// Load per-game quirk settings from a profile file; the entry whose
+    /// ROM hash matches the cartridge is applied
+    #[arg(long, value_name = "FILE")]
+    profile: Option<std::path::PathBuf>,
+
+    /// Report every write to this address (hex, repeatable) along with
+    /// the PC of the writing instruction
+    #[arg(long, value_name = "ADDR", value_parser = parse_hex_addr)]
+    watch: Vec<u16>,
+
+    /// Print which cartridge mappers are supported and exit
+    #[arg(long)]
+    list_mbc: bool,
+
+    /// Check the cartridge's Nintendo logo and warn if it is corrupt
+    #[arg(long)]
+    verify_logo: bool,
+
+    /// Refuse to run cartridges that fail --verify-logo
+    #[arg(long, requires = "verify_logo")]
+    strict: bool,
+
+    /// Lock up like real hardware when the boot ROM finds a bad logo
+    #[arg(long, requires = "bootrom")]
+    enforce_logo: bool,
+
+    /// Presentation filter applied when upscaling the screen
+    #[arg(long, value_enum, default_value_t = filter::Filter::None)]
+    filter: filter::Filter,
+
+    /// Write SCX/SCY/WX/WY for each scanline of the first frame as CSV
+    #[arg(long, value_name = "FILE")]
+    dump_bg_scroll_log: Option<std::path::PathBuf>,
+
+    /// In headless mode, run until the ROM spins on a jump-to-self and then
+    /// print the final state and serial output
+    #[arg(long, requires = "headless")]
+    exit_on_infinite_loop: bool,
+
+    /// In headless mode, print a CRC32 of every frame for visual
+    /// regression checks
+    #[arg(long, requires = "headless")]
+    frame_hash: bool,
+
+    /// Save a PNG screenshot every N frames
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    screenshot_every: Option<u64>,
+
+    /// Directory for periodic screenshots
+    #[arg(long, value_name = "DIR", default_value = "screenshots")]
+    screenshot_dir: std::path::PathBuf,
+
+    /// Pace frames on the audio buffer instead of a fixed frame rate so
+    /// audio and video never drift apart
+    #[arg(long)]
+    av_sync: bool,
+
+    /// Open the egui debugger instead of the plain window
+    #[cfg(feature = "gui")]
+    #[arg(long, conflicts_with = "headless")]
+    gui: bool,
+
+    /// Show measured FPS and emulation speed in the window title
+    #[arg(long)]
+    show_fps: bool,
+
+    /// TOML config file with key bindings
+    #[arg(long, value_name = "FILE")]
+    config: Option<std::path::PathBuf>,
+
+    /// Record the joypad input of every frame to this file on exit
+    #[arg(long, value_name = "FILE", conflicts_with = "play")]
+    record: Option<std::path::PathBuf>,
+
+    /// Replay joypad input recorded with --record, ignoring the keyboard
+    /// until it runs out
+    #[arg(long, value_name = "FILE")]
+    play: Option<std::path::PathBuf>,
+
+    /// Seconds of play kept for rewinding with Backspace (0 disables)
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    rewind_seconds: u32,
+
+    /// Integer window scale factor
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=8))]
+    scale: u8,
+}
+
+/// Frames run per presented frame while fast-forwarding.
+const TURBO_FRAMES: usize = 4;
+
+/// Frames between rewind snapshots.
+const REWIND_INTERVAL: u32 = 4;
+
+/// Debugging checks run before every instruction. The previous
+/// instruction is remembered across frames so its outcome is judged once it
+/// has executed.
+struct StepChecks {
+    tracer: Option<trace::TraceComparator>,
+    detector: Option<loop_detector::LoopDetector>,
+    /// PC of the previous instruction and whether the CPU was already locked
+    /// up before it ran, and whether DMA kept it from executing.
+    prev: Option<(u16, bool, bool)>,
+}
+
+impl StepChecks {
+    /// Returns false if the reference trace diverged or an infinite loop was
+    /// detected and emulation should stop.
+    fn before_step(&mut self, gb: &mut gameboy::GameBoy) -> bool {
+        if !self.check_previous(gb) {
+            return false;
+        }
+        if let Some(t) = self.tracer.as_mut()
+            && !gb.cpu.halted
+            && !gb.cpu.stopped
+            && !gb.mmu.cpu_stalled()
+            && let Err(d) = t.check(&gb.cpu, &mut gb.mmu)
+        {
+            eprintln!("{d}");
+            return false;
+        }
+        self.prev = Some((gb.cpu.pc, gb.cpu.is_locked_up(), gb.mmu.cpu_stalled()));
+        true
+    }
+
+    /// Report a lock-up caused by the previous instruction and feed it to
+    /// the loop detector. Returns false if a loop was detected.
+    fn check_previous(&mut self, gb: &mut gameboy::GameBoy) -> bool {
+        let Some((pc, was_locked, stalled)) = self.prev.take() else {
+            return true;
+        };
+        if !was_locked && gb.cpu.is_locked_up() {
+            let opcode = gb.mmu.read_byte(pc);
+            eprintln!("CPU locked up on illegal opcode {opcode:02X} at {pc:04X}");
+        }
+        if let Some(d) = self.detector.as_mut()
+            && !stalled
+            && d.observe(pc, &gb.cpu)
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Check the last instruction executed before emulation stopped, which
+    /// no later `before_step` call saw.
+    fn finish(&mut self, gb: &mut gameboy::GameBoy) {
+        self.check_previous(gb);
+    }
+}
+
+fn parse_hex_addr(text: &str) -> Result<u16, String> {
+    let hex = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(hex, 16).map_err(|e| format!("invalid address '{text}': {e}"))
+}
+
+/// Print the watched writes recorded since the last call.
+fn dump_watch_hits(mmu: &mut mmu::Mmu) {
+    for hit in mmu.take_watch_hits() {
+        let name = mmu::io_reg_name(hit.addr)
+            .map(|n| format!(" ({n})"))
+            .unwrap_or_default();
+        println!(
+            "[WATCH] {:04X}{name} = {:02X} by PC {:04X}",
+            hit.addr, hit.value, hit.pc
+        );
+    }
 }
 
-fn main() {
+/// Block until no more than `frames` video frames worth of audio remain
+/// queued for the output stream.
+fn wait_for_audio(gb: &gameboy::GameBoy, frames: f64) {
+    let limit = (gb.audio_samples_per_frame() * frames * 2.0) as usize;
+    while gb.mmu.apu.lock().unwrap().buffered_samples() > limit {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Write the scroll register log of the frame just completed to `path`.
+fn dump_scroll_log(path: &std::path::Path, ppu: &ppu::Ppu) {
+    match std::fs::write(path, ppu.scroll_log_csv()) {
+        Ok(()) => println!("Wrote scroll log to {}", path.display()),
+        Err(e) => eprintln!("Failed to write scroll log: {e}"),
+    }
+}
+
+/// Move the audio generated since the last call into the WAV file, if
+/// enabled. Recording stops after a write error.
+fn record_audio(wav: &mut Option<wav::WavRecorder>, gb: &gameboy::GameBoy) {
+    let Some(w) = wav.as_mut() else {
+        return;
+    };
+    let samples = gb.mmu.apu.lock().unwrap().drain_samples();
+    if let Err(e) = w.write(&samples) {
+        eprintln!("Failed to write WAV file: {e}");
+        *wav = None;
+    }
+}
+
+/// Where the emulated printer's output goes.
+struct PrintOutput {
+    prints: std::sync::mpsc::Receiver<image::GrayImage>,
+    path: std::path::PathBuf,
+    saved: usize,
+}
+
+/// Save prints finished since the last call. Prints after the first get a
+/// numeric suffix, e.g. `out-2.png`.
+fn save_prints(output: &mut Option<PrintOutput>) {
+    let Some(out) = output.as_mut() else {
+        return;
+    };
+    for image in out.prints.try_iter() {
+        out.saved += 1;
+        let path = if out.saved == 1 {
+            out.path.clone()
+        } else {
+            let stem = out.path.file_stem().unwrap_or_default().to_string_lossy();
+            out.path.with_file_name(format!("{stem}-{}.png", out.saved))
+        };
+        match image.save(&path) {
+            Ok(()) => eprintln!("Printed to {}", path.display()),
+            Err(e) => eprintln!("Failed to save print {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Hand the presented frame to the screenshot recorder, if enabled.
+fn capture_frame(
+    recorder: &mut Option<screenshot::ScreenshotRecorder>,
+    scaled: &[u32],
+    scale: usize,
+) {
+    if let Some(r) = recorder.as_mut()
+        && let Err(e) = r.frame(scaled, 160 * scale, 144 * scale)
+    {
+        eprintln!("Failed to save screenshot: {e}");
+    }
+}
+
+fn main() -> ExitCode {
     env_logger::init();
     let args = Args::parse();
 
     info!("Starting emulator");
 
+    if args.list_mbc {
+        for (name, status) in cartridge::MBC_SUPPORT {
+            println!("{name:<14} {status}");
+        }
+        return ExitCode::SUCCESS;
+    }
+
     let rom_path = match args.rom {
         Some(p) => p,
         None => {
             eprintln!("No ROM supplied");
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
@@ -64,91 +371,346 @@ fn main() {
         Ok(c) => c,
         Err(e) => {
             eprintln!("Failed to load ROM: {e}");
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
+    // Command line flags take precedence over the profile.
+    let mut quirks = profile::Quirks::default();
+    if let Some(path) = &args.profile {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| profile::ProfileSet::parse(&text).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(set) => {
+                if let Some(q) = set.lookup(&cart.rom) {
+                    info!(
+                        "Applying quirk profile for ROM {:08X}",
+                        profile::rom_hash(&cart.rom)
+                    );
+                    quirks = *q;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to load profile: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    quirks.lenient |= args.lenient;
+    if args.force_mbc.is_some() {
+        quirks.force_mbc = args.force_mbc;
+    }
+
+    let detected = cart.detect_mbc();
+    // A forced mapper is applied with the rest of the quirks below.
+    if quirks.force_mbc.is_none() {
+        if detected != cart.mbc {
+            eprintln!(
+                "Warning: header declares {:?} but a {} KB ROM needs {detected:?}; try --force-mbc",
+                cart.mbc,
+                cart.rom.len() / 1024
+            );
+        } else if let Some(warning) = cart.mbc_warning() {
+            eprintln!("Warning: {warning}");
+        }
+    }
+
+    if args.verify_logo && !cart.logo_matches() {
+        if args.strict {
+            eprintln!("Nintendo logo mismatch, refusing to run a corrupt or unbootable ROM");
+            return ExitCode::FAILURE;
+        }
+        eprintln!("Warning: Nintendo logo mismatch, this ROM would not boot on hardware");
+    }
+
     let cgb_mode = if args.dmg {
         false
     } else if args.cgb {
         true
     } else {
-        cart.cgb
+        quirks.cgb.unwrap_or(cart.cgb)
     };
+    let logo_ok = cart.logo_matches();
     let mut gb = gameboy::GameBoy::new_with_mode(cgb_mode);
     gb.mmu.load_cart(cart);
+    quirks.apply(&mut gb);
+    for &addr in &args.watch {
+        gb.mmu.add_write_watch(addr);
+    }
+    let mut print_output = args.printer.clone().map(|path| {
+        let (tx, prints) = std::sync::mpsc::channel();
+        gb.mmu
+            .serial
+            .connect(Box::new(serial::Printer::new(Some(tx))));
+        PrintOutput {
+            prints,
+            path,
+            saved: 0,
+        }
+    });
+    let cable = if let Some(port) = args.link_listen {
+        eprintln!("Waiting for a link cable partner on port {port}");
+        Some(link::LinkCable::listen(port))
+    } else {
+        args.link_connect.as_deref().map(link::LinkCable::connect)
+    };
+    match cable {
+        Some(Ok(cable)) => gb.mmu.serial.connect(Box::new(cable)),
+        Some(Err(e)) => {
+            eprintln!("Failed to set up link cable: {e}");
+            return ExitCode::FAILURE;
+        }
+        None => {}
+    }
 
+    // Whether the boot ROM refuses the cartridge, again after every reset.
+    let mut boot_locks_up = false;
     if let Some(path) = args.bootrom {
         match std::fs::read(&path) {
-            Ok(data) => gb.mmu.load_boot_rom(data),
-            Err(e) => eprintln!("Failed to load boot ROM: {e}"),
+            Ok(data) => {
+                if let Err(e) = gb.load_boot_rom_bytes(data) {
+                    eprintln!("Invalid boot ROM: {e}");
+                    return ExitCode::FAILURE;
+                }
+                if args.enforce_logo && !logo_ok {
+                    eprintln!("Nintendo logo mismatch, the boot ROM locks up");
+                    gb.cpu.lock_up();
+                    boot_locks_up = true;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to load boot ROM: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if let Some(preset) = args.palette {
+        gb.mmu.ppu.set_dmg_palette(preset.into());
+    }
+
+    if let Some(path) = &args.palette_file {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| palette::DmgPalette::parse(&text).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(p) => gb.mmu.ppu.set_dmg_palette(p),
+            Err(e) => {
+                eprintln!("Failed to load palette file: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let keymap = match &args.config {
+        Some(path) => {
+            let parsed = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| keymap::KeyMap::from_toml(&text).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(k) => k,
+                Err(e) => {
+                    eprintln!("Failed to load config: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
         }
+        None => keymap::KeyMap::default(),
+    };
+
+    if args.record.is_some() {
+        gb.start_recording();
+    }
+    if let Some(path) = &args.play
+        && let Err(e) = gb.play_recording(path)
+    {
+        eprintln!("Failed to load input recording: {e}");
+        return ExitCode::FAILURE;
     }
 
+    let tracer = match args.compare_trace {
+        Some(path) => match trace::TraceComparator::from_file(&path) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                eprintln!("Failed to load reference trace: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
     println!(
         "Emulator initialized in {} mode",
         if cgb_mode { "CGB" } else { "DMG" }
     );
 
-    let _stream = apu::Apu::start_stream(Arc::clone(&gb.mmu.apu));
+    let stream = if args.no_audio || args.wav.is_some() {
+        None
+    } else {
+        apu::Apu::start_stream(Arc::clone(&gb.mmu.apu))
+    };
+
+    #[cfg(feature = "gui")]
+    if args.gui {
+        return match gui::run(gb) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Failed to open debugger: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
 
+    let detector = args
+        .exit_on_infinite_loop
+        .then(loop_detector::LoopDetector::default);
+    let mut checks = StepChecks {
+        tracer,
+        detector,
+        prev: None,
+    };
+    let mut recorder = match args.screenshot_every {
+        Some(every) => match screenshot::ScreenshotRecorder::new(&args.screenshot_dir, every) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                eprintln!("Failed to create screenshot directory: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let mut wav = match &args.wav {
+        Some(path) => {
+            let rate = gb.mmu.apu.lock().unwrap().sample_rate();
+            match wav::WavRecorder::create(path, rate) {
+                Ok(w) => Some(w),
+                Err(e) => {
+                    eprintln!("Failed to create WAV file: {e}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => None,
+    };
+    let mut scroll_log = args.dump_bg_scroll_log;
     let mut frame = vec![0u32; 160 * 144];
     let mut frame_count = 0u64;
+    let scale = args.scale as usize;
+    let mut scaled = vec![0u32; 160 * 144 * scale * scale];
 
     if !args.headless {
-        let mut window = Window::new(
+        let mut window = match Window::new(
             "vibeEmu",
-            160,
-            144,
-            WindowOptions {
-                scale: Scale::X2,
-                ..WindowOptions::default()
-            },
-        )
-        .expect("Failed to create window");
-        window.limit_update_rate(Some(Duration::from_micros(16_700)));
-
-        while window.is_open() && !window.is_key_down(Key::Escape) {
-            // Gather input
-            let mut state = 0xFFu8;
-            if window.is_key_down(Key::Right) {
-                state &= !0x01;
+            160 * scale,
+            144 * scale,
+            WindowOptions::default(),
+        ) {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("Failed to create window: {e}");
+                return ExitCode::FAILURE;
             }
-            if window.is_key_down(Key::Left) {
-                state &= !0x02;
+        };
+        // With A/V sync the audio device sets the pace instead.
+        let av_sync = args.av_sync && stream.is_some();
+        let frame_limit = Some(Duration::from_micros(16_700));
+        if !av_sync {
+            window.limit_update_rate(frame_limit);
+        }
+        let mut turbo = false;
+        let mut fps = fps::FpsCounter::new(Duration::from_secs(1));
+        let mut last_title = Instant::now();
+
+        if args.rewind_seconds > 0 {
+            let depth = args.rewind_seconds as usize * 60 / REWIND_INTERVAL as usize;
+            gb.enable_rewind(REWIND_INTERVAL, depth);
+        }
+
+        let mut paused = false;
+        while window.is_open() && !window.is_key_down(keymap::QUIT_KEY) {
+            if window.is_key_pressed(keymap::PAUSE_KEY, minifb::KeyRepeat::No) {
+                paused = !paused;
+                gb.mmu.apu.lock().unwrap().set_paused(paused);
             }
-            if window.is_key_down(Key::Up) {
-                state &= !0x04;
+            if window.is_key_pressed(keymap::RESET_KEY, minifb::KeyRepeat::No) {
+                gb.reset();
+                if boot_locks_up {
+                    gb.cpu.lock_up();
+                }
             }
-            if window.is_key_down(Key::Down) {
-                state &= !0x08;
+            for (channel, key) in keymap::MUTE_KEYS.into_iter().enumerate() {
+                if window.is_key_pressed(key, minifb::KeyRepeat::No) {
+                    let mut apu = gb.mmu.apu.lock().unwrap();
+                    let on = !apu.channel_enabled(channel + 1);
+                    apu.set_channel_enabled(channel + 1, on);
+                }
             }
-            if window.is_key_down(Key::S) {
-                state &= !0x10;
+            if paused {
+                window.update();
+                continue;
             }
-            if window.is_key_down(Key::A) {
-                state &= !0x20;
+
+            let state = keymap.joypad_state(|key| window.is_key_down(key));
+            if !gb.is_playing_recording() {
+                gb.mmu.set_joypad_state(state);
             }
-            if window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift) {
-                state &= !0x40;
+
+            // Holding Space fast-forwards: the frame rate is uncapped and
+            // only every TURBO_FRAMES-th frame is drawn.
+            if window.is_key_down(keymap::TURBO_KEY) != turbo {
+                turbo = !turbo;
+                if !av_sync {
+                    window.limit_update_rate(if turbo { None } else { frame_limit });
+                }
             }
-            if window.is_key_down(Key::Enter) {
-                state &= !0x80;
+            let frames = if turbo { TURBO_FRAMES } else { 1 };
+
+            // Holding Backspace steps back one snapshot per presented frame.
+            let rewinding = window.is_key_down(keymap::REWIND_KEY) && gb.rewind_one();
+            let running = rewinding || gb.run_frames_with(frames, |gb| checks.before_step(gb));
+            dump_watch_hits(&mut gb.mmu);
+            record_audio(&mut wav, &gb);
+            save_prints(&mut print_output);
+            if !running {
+                break;
             }
-            gb.mmu.input.update_state(state, &mut gb.mmu.if_reg);
 
-            while !gb.mmu.ppu.frame_ready() {
-                gb.cpu.step(&mut gb.mmu);
+            if let Some(path) = scroll_log.take() {
+                dump_scroll_log(&path, &gb.mmu.ppu);
             }
 
             frame.copy_from_slice(gb.mmu.ppu.framebuffer());
-            gb.mmu.ppu.clear_frame_flag();
 
+            if turbo {
+                // Keep only the newest audio so sound stays in sync once
+                // the key is released.
+                let max = (gb.audio_samples_per_frame() * 4.0) as usize;
+                gb.mmu.apu.lock().unwrap().trim_samples(max);
+            } else if av_sync {
+                wait_for_audio(&gb, 2.0);
+            }
+
+            filter::upscale(&frame, 160, 144, scale, args.filter, &mut scaled);
+            capture_frame(&mut recorder, &scaled, scale);
             window
-                .update_with_buffer(&frame, 160, 144)
+                .update_with_buffer(&scaled, 160 * scale, 144 * scale)
                 .expect("Failed to update window");
 
-            if args.debug && frame_count % 60 == 0 {
+            if args.show_fps {
+                let now = Instant::now();
+                fps.record(now);
+                if now.duration_since(last_title) >= Duration::from_secs(1) {
+                    last_title = now;
+                    window.set_title(&format!(
+                        "vibeEmu - {:.1} FPS ({:.0}%)",
+                        fps.fps(),
+                        fps.speed_percent()
+                    ));
+                }
+            }
+
+            if args.debug && frame_count.is_multiple_of(60) {
                 let serial = gb.mmu.take_serial();
                 if !serial.is_empty() {
                     print!("[SERIAL] ");
@@ -169,15 +731,40 @@ fn main() {
         }
     } else {
         const MAX_FRAMES: usize = 10;
-        for _ in 0..MAX_FRAMES {
-            while !gb.mmu.ppu.frame_ready() {
-                gb.cpu.step(&mut gb.mmu);
+        // With loop detection enabled, run until the ROM finishes instead.
+        let max_frames = if checks.detector.is_some() {
+            usize::MAX
+        } else {
+            MAX_FRAMES
+        };
+        for _ in 0..max_frames {
+            let running = gb.run_frame_with(|gb| checks.before_step(gb));
+            dump_watch_hits(&mut gb.mmu);
+            record_audio(&mut wav, &gb);
+            save_prints(&mut print_output);
+            if !running {
+                break;
+            }
+
+            if let Some(path) = scroll_log.take() {
+                dump_scroll_log(&path, &gb.mmu.ppu);
             }
 
             frame.copy_from_slice(gb.mmu.ppu.framebuffer());
-            gb.mmu.ppu.clear_frame_flag();
 
-            if args.debug && frame_count % 60 == 0 {
+            if args.frame_hash {
+                println!(
+                    "frame {frame_count}: {:08x}",
+                    screenshot::frame_hash(&frame)
+                );
+            }
+
+            if recorder.is_some() {
+                filter::upscale(&frame, 160, 144, scale, args.filter, &mut scaled);
+                capture_frame(&mut recorder, &scaled, scale);
+            }
+
+            if args.debug && frame_count.is_multiple_of(60) {
                 let serial = gb.mmu.take_serial();
                 if !serial.is_empty() {
                     print!("[SERIAL] ");
@@ -198,5 +785,33 @@ fn main() {
         }
     }
 
+    checks.finish(&mut gb);
+    if let Some(d) = checks.detector.as_ref().filter(|d| d.detected()) {
+        println!("Infinite loop detected at PC {:04X}", d.pc());
+        let serial = gb.mmu.take_serial();
+        if !serial.is_empty() {
+            println!("[SERIAL] {}", String::from_utf8_lossy(&serial));
+        }
+        println!("{}", gb.cpu.debug_state());
+    }
+
+    if checks.tracer.as_ref().is_some_and(|t| t.finished()) {
+        println!("Reference trace matched");
+    }
+
+    if let Some(path) = &args.record
+        && let Err(e) = gb.save_recording(path)
+    {
+        eprintln!("Failed to save input recording: {e}");
+    }
+
+    if let Some(w) = wav
+        && let Err(e) = w.finish()
+    {
+        eprintln!("Failed to write WAV file: {e}");
+    }
+
+    save_prints(&mut print_output);
     gb.mmu.save_cart_ram();
+    ExitCode::SUCCESS
 }
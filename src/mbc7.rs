@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+
+/// Size of the 93LC56 EEPROM: 128 16-bit words.
+pub const EEPROM_SIZE: usize = 256;
+/// Accelerometer reading with the cartridge held level.
+pub const ACCEL_CENTER: u16 = 0x8000;
+
+/// Bits of the EEPROM register at 0xA080.
+const EEPROM_CS: u8 = 0x80;
+const EEPROM_CLK: u8 = 0x40;
+const EEPROM_DI: u8 = 0x02;
+const EEPROM_DO: u8 = 0x01;
+
+/// Start bit, two opcode bits and eight address bits.
+const COMMAND_BITS: u8 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum EepromState {
+    /// Waiting for a start bit.
+    Idle,
+    /// Shifting in opcode and address.
+    Command,
+    /// Shifting out `word`, MSB first; `sent` bits are already out.
+    Read { word: u16, sent: u8 },
+    /// Shifting in a data word for WRITE (`Some(addr)`) or WRAL (`None`).
+    Write { addr: Option<u8> },
+    /// Command finished; ignore clocks until CS drops.
+    Done,
+}
+
+/// Serial protocol state of the 93LC56 EEPROM in 16-bit mode. The data
+/// itself lives in the cartridge RAM so it is saved like battery RAM.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Eeprom {
+    cs: bool,
+    clk: bool,
+    di: bool,
+    data_out: bool,
+    write_enabled: bool,
+    state: EepromState,
+    shift: u16,
+    bits: u8,
+}
+
+impl Default for Eeprom {
+    fn default() -> Self {
+        Self {
+            cs: false,
+            clk: false,
+            di: false,
+            data_out: true,
+            write_enabled: false,
+            state: EepromState::Idle,
+            shift: 0,
+            bits: 0,
+        }
+    }
+}
+
+impl Eeprom {
+    fn read(&self) -> u8 {
+        let mut val = 0;
+        if self.cs {
+            val |= EEPROM_CS;
+        }
+        if self.clk {
+            val |= EEPROM_CLK;
+        }
+        if self.di {
+            val |= EEPROM_DI;
+        }
+        if self.data_out {
+            val |= EEPROM_DO;
+        }
+        val
+    }
+
+    fn write(&mut self, val: u8, mem: &mut [u8]) {
+        let cs = val & EEPROM_CS != 0;
+        let clk = val & EEPROM_CLK != 0;
+        self.di = val & EEPROM_DI != 0;
+        if !cs {
+            // Deselecting aborts any command and reports ready.
+            self.state = EepromState::Idle;
+            self.data_out = true;
+        } else if clk && !self.clk {
+            self.clock(mem);
+        }
+        self.cs = cs;
+        self.clk = clk;
+    }
+
+    /// Handle a rising clock edge while selected.
+    fn clock(&mut self, mem: &mut [u8]) {
+        let bit = self.di as u16;
+        match self.state {
+            EepromState::Idle => {
+                if self.di {
+                    self.state = EepromState::Command;
+                    self.shift = 1;
+                    self.bits = 1;
+                }
+            }
+            EepromState::Command => {
+                self.shift = self.shift << 1 | bit;
+                self.bits += 1;
+                if self.bits == COMMAND_BITS {
+                    self.execute(mem);
+                }
+            }
+            EepromState::Read { word, sent } => {
+                self.data_out = word >> (15 - sent) & 1 != 0;
+                self.state = if sent == 15 {
+                    EepromState::Done
+                } else {
+                    EepromState::Read {
+                        word,
+                        sent: sent + 1,
+                    }
+                };
+            }
+            EepromState::Write { addr } => {
+                self.shift = self.shift << 1 | bit;
+                self.bits += 1;
+                if self.bits == 16 {
+                    match addr {
+                        Some(addr) => store(mem, addr, self.shift),
+                        None => (0..128).for_each(|a| store(mem, a, self.shift)),
+                    }
+                    self.data_out = true;
+                    self.state = EepromState::Done;
+                }
+            }
+            EepromState::Done => {}
+        }
+    }
+
+    fn execute(&mut self, mem: &mut [u8]) {
+        let opcode = (self.shift >> 8) & 0x03;
+        let addr = (self.shift & 0x7F) as u8;
+        self.state = EepromState::Done;
+        match opcode {
+            // READ: a dummy 0 bit precedes the data.
+            0b10 => {
+                self.data_out = false;
+                self.state = EepromState::Read {
+                    word: load(mem, addr),
+                    sent: 0,
+                };
+            }
+            0b01 if self.write_enabled => self.begin_write(Some(addr)),
+            0b11 if self.write_enabled => store(mem, addr, 0xFFFF),
+            0b00 => match (self.shift >> 6) & 0x03 {
+                0b11 => self.write_enabled = true,
+                0b00 => self.write_enabled = false,
+                0b10 if self.write_enabled => (0..128).for_each(|a| store(mem, a, 0xFFFF)),
+                0b01 if self.write_enabled => self.begin_write(None),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn begin_write(&mut self, addr: Option<u8>) {
+        self.state = EepromState::Write { addr };
+        self.shift = 0;
+        self.bits = 0;
+    }
+}
+
+fn load(mem: &[u8], addr: u8) -> u16 {
+    let i = addr as usize * 2;
+    match mem.get(i..i + 2) {
+        Some(&[lo, hi]) => u16::from_le_bytes([lo, hi]),
+        _ => 0xFFFF,
+    }
+}
+
+fn store(mem: &mut [u8], addr: u8, word: u16) {
+    let i = addr as usize * 2;
+    if let Some(dst) = mem.get_mut(i..i + 2) {
+        dst.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+/// MBC7 mapper state: ROM banking, the two-step RAM enable, the
+/// accelerometer latch and the EEPROM interface.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mbc7 {
+    pub rom_bank: u8,
+    ram_enable: bool,
+    ram_enable2: bool,
+    /// Current tilt as an offset from level, set by the frontend.
+    tilt: (i16, i16),
+    /// Latched X and Y readings.
+    accel: [u16; 2],
+    /// The latch was erased and is ready for a new reading.
+    accel_erased: bool,
+    eeprom: Eeprom,
+}
+
+impl Default for Mbc7 {
+    fn default() -> Self {
+        Self {
+            rom_bank: 1,
+            ram_enable: false,
+            ram_enable2: false,
+            tilt: (0, 0),
+            accel: [ACCEL_CENTER; 2],
+            accel_erased: false,
+            eeprom: Eeprom::default(),
+        }
+    }
+}
+
+impl Mbc7 {
+    /// Set the tilt reported by the next accelerometer latch.
+    pub fn set_tilt(&mut self, x: i16, y: i16) {
+        self.tilt = (x, y);
+    }
+
+    /// Handle a write to 0x0000-0x7FFF.
+    pub fn write_control(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enable = val & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = val & 0x7F,
+            0x4000..=0x5FFF => self.ram_enable2 = val == 0x40,
+            _ => {}
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.ram_enable && self.ram_enable2
+    }
+
+    /// Read the register block at 0xA000-0xBFFF.
+    pub fn read(&self, addr: u16) -> u8 {
+        if !self.enabled() || addr >= 0xB000 {
+            return 0xFF;
+        }
+        let [x, y] = self.accel;
+        match addr & 0x00F0 {
+            0x20 => x as u8,
+            0x30 => (x >> 8) as u8,
+            0x40 => y as u8,
+            0x50 => (y >> 8) as u8,
+            0x60 => 0x00,
+            0x80 => self.eeprom.read(),
+            _ => 0xFF,
+        }
+    }
+
+    /// Write the register block at 0xA000-0xBFFF. `eeprom` holds the
+    /// EEPROM contents.
+    pub fn write(&mut self, addr: u16, val: u8, eeprom: &mut [u8]) {
+        if !self.enabled() || addr >= 0xB000 {
+            return;
+        }
+        match addr & 0x00F0 {
+            0x00 if val == 0x55 => {
+                self.accel = [ACCEL_CENTER; 2];
+                self.accel_erased = true;
+            }
+            0x10 if val == 0xAA && self.accel_erased => {
+                let (x, y) = self.tilt;
+                self.accel = [
+                    ACCEL_CENTER.wrapping_add(x as u16),
+                    ACCEL_CENTER.wrapping_add(y as u16),
+                ];
+                self.accel_erased = false;
+            }
+            0x80 => self.eeprom.write(val, eeprom),
+            _ => {}
+        }
+    }
+}
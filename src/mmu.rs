@@ -1,7 +1,131 @@
-use crate::{apu::Apu, cartridge::Cartridge, input::Input, ppu::Ppu, serial::Serial, timer::Timer};
+use crate::{
+    apu::{Apu, ApuState},
+    cartridge::{Cartridge, CartridgeState},
+    input::Input,
+    interrupts::{InterruptObserver, InterruptType, Interrupts},
+    ppu::{Ppu, PpuState},
+    serial::{Serial, SerialState},
+    timer::Timer,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
 
 const WRAM_BANK_SIZE: usize = 0x1000;
+/// Bytes copied per HDMA block.
+const HDMA_BLOCK_SIZE: u16 = 0x10;
+/// CPU cycles a VRAM DMA block takes from the CPU at normal speed: eight
+/// M-cycles. In double speed the transfer takes the same time, so twice as
+/// many CPU cycles.
+const HDMA_BLOCK_STALL: u16 = 32;
+/// Watch hits kept between `take_watch_hits` calls; older ones are dropped.
+pub const MAX_WATCH_HITS: usize = 1024;
+
+/// A write to a watched address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    /// Address of the instruction that performed the write.
+    pub pc: u16,
+}
+
+/// Conventional name of the I/O register at `addr`, for debug output.
+pub fn io_reg_name(addr: u16) -> Option<&'static str> {
+    let name = match addr {
+        0xFF00 => "P1",
+        0xFF01 => "SB",
+        0xFF02 => "SC",
+        0xFF04 => "DIV",
+        0xFF05 => "TIMA",
+        0xFF06 => "TMA",
+        0xFF07 => "TAC",
+        0xFF0F => "IF",
+        0xFF10 => "NR10",
+        0xFF11 => "NR11",
+        0xFF12 => "NR12",
+        0xFF13 => "NR13",
+        0xFF14 => "NR14",
+        0xFF16 => "NR21",
+        0xFF17 => "NR22",
+        0xFF18 => "NR23",
+        0xFF19 => "NR24",
+        0xFF1A => "NR30",
+        0xFF1B => "NR31",
+        0xFF1C => "NR32",
+        0xFF1D => "NR33",
+        0xFF1E => "NR34",
+        0xFF20 => "NR41",
+        0xFF21 => "NR42",
+        0xFF22 => "NR43",
+        0xFF23 => "NR44",
+        0xFF24 => "NR50",
+        0xFF25 => "NR51",
+        0xFF26 => "NR52",
+        0xFF30..=0xFF3F => "WAVE",
+        0xFF40 => "LCDC",
+        0xFF41 => "STAT",
+        0xFF42 => "SCY",
+        0xFF43 => "SCX",
+        0xFF44 => "LY",
+        0xFF45 => "LYC",
+        0xFF46 => "DMA",
+        0xFF47 => "BGP",
+        0xFF48 => "OBP0",
+        0xFF49 => "OBP1",
+        0xFF4A => "WY",
+        0xFF4B => "WX",
+        0xFF4C => "KEY0",
+        0xFF4D => "KEY1",
+        0xFF4F => "VBK",
+        0xFF50 => "BANK",
+        0xFF51 => "HDMA1",
+        0xFF52 => "HDMA2",
+        0xFF53 => "HDMA3",
+        0xFF54 => "HDMA4",
+        0xFF55 => "HDMA5",
+        0xFF56 => "RP",
+        0xFF68 => "BCPS",
+        0xFF69 => "BCPD",
+        0xFF6A => "OCPS",
+        0xFF6B => "OCPD",
+        0xFF6C => "OPRI",
+        0xFF70 => "SVBK",
+        0xFF76 => "PCM12",
+        0xFF77 => "PCM34",
+        0xFFFF => "IE",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Memory, I/O and DMA state of the whole bus, used by savestates. The boot
+/// ROM image, watchpoints and cartridge ROM are not included.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MmuState {
+    wram: Vec<u8>,
+    wram_bank: usize,
+    hram: Vec<u8>,
+    boot_mapped: bool,
+    if_reg: u8,
+    ie_reg: u8,
+    key1: u8,
+    rp: u8,
+    dma_cycles: u16,
+    dma_source: u16,
+    hdma_src: u16,
+    hdma_dst: u16,
+    hdma_blocks: u8,
+    hdma_active: bool,
+    hdma_last_mode: u8,
+    vram_dma_stall: u16,
+    serial: SerialState,
+    ppu: PpuState,
+    apu: ApuState,
+    timer: Timer,
+    input: Input,
+    cart: Option<CartridgeState>,
+}
 
 pub struct Mmu {
     pub wram: [[u8; WRAM_BANK_SIZE]; 8],
@@ -21,7 +145,25 @@ pub struct Mmu {
     pub rp: u8,
     pub dma_cycles: u16,
     dma_source: u16,
+    /// CGB VRAM DMA source and destination addresses (HDMA1-HDMA4)
+    hdma_src: u16,
+    hdma_dst: u16,
+    /// 16-byte blocks left in the current or last VRAM DMA
+    hdma_blocks: u8,
+    /// True while an HBlank DMA is waiting for further HBlanks
+    hdma_active: bool,
+    /// PPU mode seen by the previous `hdma_step`, used to detect HBlank entry
+    hdma_last_mode: u8,
+    /// CPU cycles still to be taken from the CPU by VRAM DMA blocks that
+    /// have been copied.
+    pub vram_dma_stall: u16,
     cgb_mode: bool,
+    /// Addresses whose writes are recorded in `watch_hits`.
+    write_watches: HashSet<u16>,
+    watch_hits: VecDeque<WatchHit>,
+    interrupt_observer: InterruptObserver,
+    /// Address of the instruction being executed, set by the CPU.
+    current_pc: u16,
 }
 
 impl Mmu {
@@ -43,14 +185,24 @@ impl Mmu {
             ie_reg: 0,
             serial: Serial::new(cgb),
             ppu,
-            apu: Arc::new(Mutex::new(Apu::new())),
+            apu: Arc::new(Mutex::new(Apu::new_with_mode(cgb))),
             timer,
             input: Input::new(),
             key1: if cgb { 0x7E } else { 0 },
             rp: 0,
             dma_cycles: 0,
             dma_source: 0,
+            hdma_src: 0,
+            hdma_dst: 0x8000,
+            hdma_blocks: 0,
+            hdma_active: false,
+            hdma_last_mode: 0,
+            vram_dma_stall: 0,
             cgb_mode: cgb,
+            write_watches: HashSet::new(),
+            watch_hits: VecDeque::new(),
+            interrupt_observer: InterruptObserver::default(),
+            current_pc: 0,
         }
     }
 
@@ -63,20 +215,144 @@ impl Mmu {
         self.cart = Some(cart);
         if self.cgb_mode && is_dmg {
             self.ppu.apply_dmg_compatibility_palettes();
+            // Without a boot ROM nothing writes KEY0, so select DMG
+            // compatibility mode here as the boot ROM would.
+            if !self.boot_mapped {
+                self.ppu.set_dmg_compat(true);
+            }
+        }
+    }
+
+    /// Whether CGB-only registers are accessible: the hardware is a CGB and
+    /// KEY0 did not select DMG compatibility mode.
+    fn cgb_regs(&self) -> bool {
+        self.cgb_mode && !self.ppu.dmg_compat()
+    }
+
+    /// Whether the cartridge's rumble motor is on, for frontends that can
+    /// vibrate a gamepad.
+    pub fn is_rumbling(&self) -> bool {
+        self.cart.as_ref().is_some_and(|c| c.rumble_state())
+    }
+
+    /// Attach a link partner that receives each byte shifted out of SB and
+    /// returns the byte shifted in.
+    pub fn set_serial_peer(&mut self, peer: Box<dyn FnMut(u8) -> u8>) {
+        self.serial.connect(Box::new(peer));
+    }
+
+    /// Return to the power-on state: memory is cleared and every component
+    /// restarts. The cartridge keeps its RAM and clock, the boot ROM is
+    /// mapped again, and the audio output, link partner, DMG palette,
+    /// watchpoints and interrupt callback stay attached.
+    pub fn reset(&mut self) {
+        let fresh = Self::new_with_mode(self.cgb_mode);
+        let mut old = std::mem::replace(self, fresh);
+        self.apu = old.apu;
+        self.apu.lock().unwrap().reset();
+        self.serial = old.serial;
+        self.serial.reset();
+        self.ppu.set_dmg_palette(old.ppu.dmg_palette());
+        self.write_watches = std::mem::take(&mut old.write_watches);
+        self.interrupt_observer = std::mem::take(&mut old.interrupt_observer);
+        if let Some(boot_rom) = old.boot_rom {
+            self.load_boot_rom(boot_rom);
+        }
+        if let Some(mut cart) = old.cart {
+            cart.reset();
+            self.load_cart(cart);
+        }
+    }
+
+    /// Capture everything on the bus apart from the CPU for a savestate.
+    pub fn save_state(&self) -> MmuState {
+        MmuState {
+            wram: self.wram.concat(),
+            wram_bank: self.wram_bank,
+            hram: self.hram.to_vec(),
+            boot_mapped: self.boot_mapped,
+            if_reg: self.if_reg,
+            ie_reg: self.ie_reg,
+            key1: self.key1,
+            rp: self.rp,
+            dma_cycles: self.dma_cycles,
+            dma_source: self.dma_source,
+            hdma_src: self.hdma_src,
+            hdma_dst: self.hdma_dst,
+            hdma_blocks: self.hdma_blocks,
+            hdma_active: self.hdma_active,
+            hdma_last_mode: self.hdma_last_mode,
+            vram_dma_stall: self.vram_dma_stall,
+            serial: self.serial.save_state(),
+            ppu: self.ppu.save_state(),
+            apu: self.apu.lock().unwrap().save_state(),
+            timer: self.timer.clone(),
+            input: self.input.clone(),
+            cart: self.cart.as_ref().map(Cartridge::save_state),
+        }
+    }
+
+    /// Restore state captured by `save_state` with the same cartridge
+    /// loaded.
+    pub fn load_state(&mut self, state: &MmuState) {
+        for (bank, src) in self
+            .wram
+            .iter_mut()
+            .zip(state.wram.chunks_exact(WRAM_BANK_SIZE))
+        {
+            bank.copy_from_slice(src);
+        }
+        if state.hram.len() == self.hram.len() {
+            self.hram.copy_from_slice(&state.hram);
+        }
+        self.wram_bank = state.wram_bank.clamp(1, 7);
+        self.boot_mapped = state.boot_mapped && self.boot_rom.is_some();
+        self.if_reg = state.if_reg;
+        self.ie_reg = state.ie_reg;
+        self.key1 = state.key1;
+        self.rp = state.rp;
+        self.dma_cycles = state.dma_cycles;
+        self.dma_source = state.dma_source;
+        self.hdma_src = state.hdma_src;
+        self.hdma_dst = state.hdma_dst;
+        self.hdma_blocks = state.hdma_blocks;
+        self.hdma_active = state.hdma_active;
+        self.hdma_last_mode = state.hdma_last_mode;
+        self.vram_dma_stall = state.vram_dma_stall;
+        self.serial.load_state(&state.serial);
+        self.ppu.load_state(&state.ppu);
+        self.apu.lock().unwrap().load_state(&state.apu);
+        self.timer = state.timer.clone();
+        self.input = state.input.clone();
+        if let (Some(cart), Some(saved)) = (self.cart.as_mut(), &state.cart) {
+            cart.load_state(saved);
+        }
+    }
+
+    /// Check the parts of `state` that `load_state` cannot restore safely
+    /// if they are malformed.
+    pub fn check_state(&self, state: &MmuState) -> Result<(), String> {
+        Ppu::check_state(&state.ppu)?;
+        match (&self.cart, &state.cart) {
+            (Some(cart), Some(saved)) => cart.check_state(saved),
+            _ => Ok(()),
         }
     }
 
     pub fn save_cart_ram(&self) {
-        if let Some(cart) = &self.cart {
-            if let Err(e) = cart.save_ram() {
-                eprintln!("Failed to save RAM: {e}");
-            }
+        if let Some(cart) = &self.cart
+            && let Err(e) = cart.save_ram()
+        {
+            eprintln!("Failed to save RAM: {e}");
         }
     }
 
     pub fn load_boot_rom(&mut self, data: Vec<u8>) {
         self.boot_rom = Some(data);
         self.boot_mapped = true;
+        // The boot ROM starts in CGB mode and selects DMG compatibility
+        // itself through KEY0, even if a DMG cart was loaded first.
+        self.ppu.set_dmg_compat(false);
     }
 
     pub fn read_byte(&mut self, addr: u16) -> u8 {
@@ -86,6 +362,14 @@ impl Mmu {
                 .as_ref()
                 .and_then(|b| b.get(addr as usize).copied())
                 .unwrap_or(0xFF),
+            0x0200..=0x08FF
+                if self.boot_mapped && self.boot_rom.as_ref().is_some_and(|b| b.len() > 0x100) =>
+            {
+                self.boot_rom
+                    .as_ref()
+                    .and_then(|b| b.get(addr as usize).copied())
+                    .unwrap_or(0xFF)
+            }
             0x0000..=0x7FFF => self.cart.as_ref().map(|c| c.read(addr)).unwrap_or(0xFF),
             0x8000..=0x9FFF => {
                 if self.ppu.mode == 3 {
@@ -100,7 +384,8 @@ impl Mmu {
             0xE000..=0xEFFF => self.wram[0][(addr - 0xE000) as usize],
             0xF000..=0xFDFF => self.wram[self.wram_bank][(addr - 0xF000) as usize],
             0xFE00..=0xFE9F => {
-                if self.ppu.mode == 2 || self.ppu.mode == 3 {
+                // OAM is owned by the DMA unit while a transfer runs.
+                if self.dma_active() || self.ppu.mode == 2 || self.ppu.mode == 3 {
                     0xFF
                 } else {
                     self.ppu.oam[(addr - 0xFE00) as usize]
@@ -113,34 +398,55 @@ impl Mmu {
             0xFF0F => self.if_reg,
             0xFF10..=0xFF3F => self.apu.lock().unwrap().read_reg(addr),
             0xFF40..=0xFF4B | 0xFF68..=0xFF6B => self.ppu.read_reg(addr),
-            0xFF4D => {
-                if self.cgb_mode {
-                    (self.key1 & 0x81) | 0x7E
-                } else {
-                    0xFF
-                }
-            }
-            0xFF56 => {
-                if self.cgb_mode {
-                    self.rp | 0xC0
+            0xFF4D if self.cgb_regs() => (self.key1 & 0x81) | 0x7E,
+            0xFF56 if self.cgb_regs() => self.rp | 0xC0,
+            // Only bit 0 of VBK is implemented; the rest read as 1.
+            0xFF4F => self.ppu.vram_bank as u8 | 0xFE,
+            0xFF55 if self.cgb_regs() => {
+                let remaining = self.hdma_blocks.wrapping_sub(1) & 0x7F;
+                if self.hdma_active {
+                    remaining
                 } else {
-                    0xFF
+                    0x80 | remaining
                 }
             }
-            0xFF4F => self.ppu.vram_bank as u8,
-            0xFF70 => self.wram_bank as u8,
+            0xFF70 if self.cgb_regs() => self.wram_bank as u8 | 0xF8,
+            // PCM12/PCM34 stay readable in DMG compatibility mode.
+            0xFF76 if self.cgb_mode => self.apu.lock().unwrap().pcm12(),
+            0xFF77 if self.cgb_mode => self.apu.lock().unwrap().pcm34(),
             0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
             0xFFFF => self.ie_reg,
             _ => 0xFF,
         }
     }
 
+    /// Read on behalf of the DMA units. Unlike `read_byte` this is not
+    /// subject to the CPU's VRAM and OAM lockout during PPU modes 2 and 3.
+    fn read_byte_internal(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF => self.ppu.vram[self.ppu.vram_bank][(addr - 0x8000) as usize],
+            0xFE00..=0xFE9F => self.ppu.oam[(addr - 0xFE00) as usize],
+            _ => self.read_byte(addr),
+        }
+    }
+
     pub fn write_byte(&mut self, addr: u16, val: u8) {
+        if let Some(name) = io_reg_name(addr) {
+            log::trace!("write {name} ({addr:04X}) = {val:02X}");
+        }
+        if self.write_watches.contains(&addr) {
+            if self.watch_hits.len() == MAX_WATCH_HITS {
+                self.watch_hits.pop_front();
+            }
+            self.watch_hits.push_back(WatchHit {
+                addr,
+                value: val,
+                pc: self.current_pc,
+            });
+        }
         match addr {
-            0x8000..=0x9FFF => {
-                if self.ppu.mode != 3 {
-                    self.ppu.vram[self.ppu.vram_bank][(addr - 0x8000) as usize] = val;
-                }
+            0x8000..=0x9FFF if self.ppu.mode != 3 => {
+                self.ppu.vram[self.ppu.vram_bank][(addr - 0x8000) as usize] = val;
             }
             0x0000..=0x7FFF | 0xA000..=0xBFFF => {
                 if let Some(cart) = self.cart.as_mut() {
@@ -151,27 +457,21 @@ impl Mmu {
             0xD000..=0xDFFF => self.wram[self.wram_bank][(addr - 0xD000) as usize] = val,
             0xE000..=0xEFFF => self.wram[0][(addr - 0xE000) as usize] = val,
             0xF000..=0xFDFF => self.wram[self.wram_bank][(addr - 0xF000) as usize] = val,
-            0xFE00..=0xFE9F => {
-                if self.ppu.mode != 2 && self.ppu.mode != 3 {
-                    self.ppu.oam[(addr - 0xFE00) as usize] = val;
-                }
+            0xFE00..=0xFE9F if self.ppu.mode != 2 && self.ppu.mode != 3 => {
+                self.ppu.oam[(addr - 0xFE00) as usize] = val;
             }
             0xFEA0..=0xFEFF => {}
             0xFF00 => self.input.write(val),
-            0xFF01 | 0xFF02 => self.serial.write(addr, val, &mut self.if_reg),
-            0xFF04..=0xFF07 => self.timer.write(addr, val, &mut self.if_reg),
+            0xFF01 | 0xFF02 => self.serial.write(addr, val),
+            0xFF04..=0xFF07 => self.timer.write(addr, val),
             0xFF0F => self.if_reg = (val & 0x1F) | (self.if_reg & 0xE0),
             0xFF10..=0xFF3F => self.apu.lock().unwrap().write_reg(addr, val),
             0xFF40..=0xFF45 | 0xFF47..=0xFF4B | 0xFF68..=0xFF6B => self.ppu.write_reg(addr, val),
-            0xFF4D => {
-                if self.cgb_mode {
-                    self.key1 = (self.key1 & 0x80) | (val & 0x01);
-                }
+            0xFF4D if self.cgb_regs() => {
+                self.key1 = (self.key1 & 0x80) | (val & 0x01);
             }
-            0xFF56 => {
-                if self.cgb_mode {
-                    self.rp = val & 0xC1;
-                }
+            0xFF56 if self.cgb_regs() => {
+                self.rp = val & 0xC1;
             }
             0xFF4F => self.ppu.vram_bank = (val & 0x01) as usize,
             0xFF46 => {
@@ -179,8 +479,26 @@ impl Mmu {
                 self.dma_source = (val as u16) << 8;
                 self.dma_cycles = 640;
             }
+            // KEY0 is only writable while the CGB boot ROM is mapped; bit 2
+            // selects DMG compatibility mode.
+            0xFF4C if self.cgb_mode && self.boot_mapped => {
+                self.ppu.set_dmg_compat(val & 0x04 != 0);
+            }
             0xFF50 => self.boot_mapped = false,
-            0xFF70 => {
+            0xFF51 if self.cgb_regs() => {
+                self.hdma_src = (self.hdma_src & 0x00F0) | ((val as u16) << 8);
+            }
+            0xFF52 if self.cgb_regs() => {
+                self.hdma_src = (self.hdma_src & 0xFF00) | (val & 0xF0) as u16;
+            }
+            0xFF53 if self.cgb_regs() => {
+                self.hdma_dst = 0x8000 | (self.hdma_dst & 0x00F0) | (((val & 0x1F) as u16) << 8);
+            }
+            0xFF54 if self.cgb_regs() => {
+                self.hdma_dst = (self.hdma_dst & 0xFF00) | (val & 0xF0) as u16;
+            }
+            0xFF55 if self.cgb_regs() => self.start_hdma(val),
+            0xFF70 if self.cgb_regs() => {
                 let bank = (val & 0x07) as usize;
                 self.wram_bank = if bank == 0 { 1 } else { bank };
             }
@@ -190,10 +508,60 @@ impl Mmu {
         }
     }
 
+    /// Raise `interrupt` by setting its bit in IF.
+    pub fn request_interrupt(&mut self, interrupt: InterruptType) {
+        let before = self.if_reg;
+        Interrupts::request(&mut self.if_reg, interrupt);
+        self.note_interrupts(before);
+    }
+
+    /// Call `callback` for every interrupt requested from now on.
+    pub fn set_interrupt_callback(&mut self, callback: Box<dyn FnMut(InterruptType)>) {
+        self.interrupt_observer.set_callback(callback);
+    }
+
+    /// Interrupt requests seen so far, in `InterruptType::ALL` order.
+    pub fn interrupt_counts(&self) -> [u64; 5] {
+        self.interrupt_observer.counts()
+    }
+
+    /// Report interrupts raised since IF held `if_before` to the observer.
+    pub fn note_interrupts(&mut self, if_before: u8) {
+        self.interrupt_observer.observe(if_before, self.if_reg);
+    }
+
+    /// Update the joypad buttons (0 = pressed), raising the joypad interrupt
+    /// when a selected line goes low.
+    pub fn set_joypad_state(&mut self, state: u8) {
+        if self.input.button_event(state) {
+            self.request_interrupt(InterruptType::Joypad);
+        }
+    }
+
     pub fn take_serial(&mut self) -> Vec<u8> {
         self.serial.take_output()
     }
 
+    /// Record every bus write to `addr`, whatever memory it maps to.
+    pub fn add_write_watch(&mut self, addr: u16) {
+        self.write_watches.insert(addr);
+    }
+
+    pub fn remove_write_watch(&mut self, addr: u16) {
+        self.write_watches.remove(&addr);
+    }
+
+    /// Return and clear the writes recorded since the last call. Only the
+    /// last `MAX_WATCH_HITS` are kept.
+    pub fn take_watch_hits(&mut self) -> Vec<WatchHit> {
+        self.watch_hits.drain(..).collect()
+    }
+
+    /// Tell the bus which instruction is executing, for watchpoint reports.
+    pub fn set_current_pc(&mut self, pc: u16) {
+        self.current_pc = pc;
+    }
+
     /// Advance the ongoing OAM DMA transfer if active.
     pub fn dma_step(&mut self, cycles: u16) {
         for _ in 0..cycles {
@@ -201,9 +569,15 @@ impl Mmu {
                 break;
             }
             let progress = 640 - self.dma_cycles;
-            if progress % 4 == 0 && progress / 4 < 0xA0 {
+            if progress.is_multiple_of(4) && progress / 4 < 0xA0 {
                 let idx: u16 = progress / 4;
-                let byte = self.read_byte(self.dma_source.wrapping_add(idx));
+                let mut src = self.dma_source.wrapping_add(idx);
+                // DMA sees WRAM for every source above 0xDFFF, including the
+                // pages that the CPU sees as OAM, I/O and HRAM.
+                if src >= 0xE000 {
+                    src -= 0x2000;
+                }
+                let byte = self.read_byte_internal(src);
                 self.ppu.oam[idx as usize] = byte;
             }
             self.dma_cycles -= 1;
@@ -214,6 +588,65 @@ impl Mmu {
     pub fn dma_active(&self) -> bool {
         self.dma_cycles > 0
     }
+
+    /// Return true while OAM or VRAM DMA keeps the CPU from executing.
+    pub fn cpu_stalled(&self) -> bool {
+        self.dma_active() || self.vram_dma_stall > 0
+    }
+
+    /// Return true while an HBlank VRAM DMA still has blocks to copy.
+    pub fn hdma_in_progress(&self) -> bool {
+        self.hdma_active
+    }
+
+    /// Handle a write to HDMA5. Bit 7 clear starts a general-purpose DMA that
+    /// copies everything at once, or stops an active HBlank DMA; bit 7 set
+    /// starts an HBlank DMA copying one block per HBlank.
+    fn start_hdma(&mut self, val: u8) {
+        if self.hdma_active && val & 0x80 == 0 {
+            self.hdma_active = false;
+            return;
+        }
+        self.hdma_blocks = (val & 0x7F) + 1;
+        if val & 0x80 != 0 {
+            self.hdma_active = true;
+            self.hdma_last_mode = self.ppu.mode;
+        } else {
+            while self.hdma_blocks > 0 {
+                self.hdma_copy_block();
+            }
+        }
+    }
+
+    /// Copy one 16-byte block from the HDMA source into the current VRAM bank
+    /// and charge the CPU for it.
+    fn hdma_copy_block(&mut self) {
+        for _ in 0..HDMA_BLOCK_SIZE {
+            let byte = self.read_byte_internal(self.hdma_src);
+            let offset = (self.hdma_dst & 0x1FFF) as usize;
+            self.ppu.vram[self.ppu.vram_bank][offset] = byte;
+            self.hdma_src = self.hdma_src.wrapping_add(1);
+            self.hdma_dst = 0x8000 | (self.hdma_dst.wrapping_add(1) & 0x1FFF);
+        }
+        self.hdma_blocks -= 1;
+        let double_speed = self.key1 & 0x80 != 0;
+        self.vram_dma_stall += HDMA_BLOCK_STALL << double_speed as u16;
+    }
+
+    /// Copy the next HBlank DMA block if the PPU just entered HBlank. Call
+    /// after advancing the PPU.
+    pub fn hdma_step(&mut self) {
+        let mode = self.ppu.mode;
+        let entered_hblank = mode == 0 && self.hdma_last_mode != 0;
+        self.hdma_last_mode = mode;
+        if !self.hdma_active || !entered_hblank || self.ppu.read_reg(0xFF40) & 0x80 == 0 {
+            return;
+        }
+        self.hdma_copy_block();
+        if self.hdma_blocks == 0 {
+            self.hdma_active = false;
+        }
+    }
 }
 
 impl Default for Mmu {
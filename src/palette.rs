@@ -0,0 +1,151 @@
+use clap::ValueEnum;
+use std::fmt;
+
+/// Default green DMG shades in 0x00RRGGBB order, lightest first.
+const DEFAULT_SHADES: [u32; 4] = [0x009BBC0F, 0x008BAC0F, 0x00306230, 0x000F380F];
+const GREYSCALE_SHADES: [u32; 4] = [0x00FFFFFF, 0x00AAAAAA, 0x00555555, 0x00000000];
+const AMBER_SHADES: [u32; 4] = [0x00FFC040, 0x00C08020, 0x00704810, 0x00301800];
+
+/// Built-in DMG color schemes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum PalettePreset {
+    /// The green tint of the original DMG screen.
+    #[default]
+    Green,
+    /// Neutral greys, as on the Game Boy Pocket.
+    Greyscale,
+    /// Orange shades like an amber monochrome monitor.
+    Amber,
+}
+
+impl PalettePreset {
+    /// The preset's four shades, lightest first.
+    pub fn shades(self) -> [u32; 4] {
+        match self {
+            PalettePreset::Green => DEFAULT_SHADES,
+            PalettePreset::Greyscale => GREYSCALE_SHADES,
+            PalettePreset::Amber => AMBER_SHADES,
+        }
+    }
+}
+
+impl From<PalettePreset> for DmgPalette {
+    fn from(preset: PalettePreset) -> Self {
+        Self::uniform(preset.shades())
+    }
+}
+
+/// Colors used for the four DMG shades of the background and both object
+/// palettes, lightest first, in 0x00RRGGBB order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmgPalette {
+    pub bg: [u32; 4],
+    pub obj0: [u32; 4],
+    pub obj1: [u32; 4],
+}
+
+impl Default for DmgPalette {
+    fn default() -> Self {
+        Self::uniform(DEFAULT_SHADES)
+    }
+}
+
+/// Reasons a palette file could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteError {
+    /// A line could not be read as a color.
+    InvalidColor { line: usize, text: String },
+    /// The file held a number of colors other than 4 or 12.
+    WrongCount(usize),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::InvalidColor { line, text } => {
+                write!(f, "line {line}: invalid color '{text}'")
+            }
+            PaletteError::WrongCount(n) => write!(f, "expected 4 or 12 colors, found {n}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl DmgPalette {
+    /// Use the same four shades for the background and both object palettes.
+    pub fn uniform(shades: [u32; 4]) -> Self {
+        Self {
+            bg: shades,
+            obj0: shades,
+            obj1: shades,
+        }
+    }
+
+    /// Parse a palette file. Supported layouts are JASC-PAL (`.pal` files
+    /// with one `R G B` decimal triple per line), ini-style `key=RRGGBB`
+    /// lines, and plain lists of hex colors with an optional `#` prefix.
+    /// Four colors apply to every palette; twelve are read as BG, OBJ0 and
+    /// OBJ1 in that order. Blank lines and lines starting with `;` or `[`
+    /// are ignored.
+    pub fn parse(text: &str) -> Result<Self, PaletteError> {
+        let mut lines = text.lines().enumerate().peekable();
+        let jasc = lines
+            .peek()
+            .is_some_and(|(_, l)| l.trim().eq_ignore_ascii_case("JASC-PAL"));
+        if jasc {
+            // Skip the header, version and color count lines.
+            lines.nth(2);
+        }
+
+        let mut colors = Vec::new();
+        for (i, line) in lines {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('[') {
+                continue;
+            }
+            let value = line.split_once('=').map_or(line, |(_, v)| v.trim());
+            let color = if jasc {
+                parse_rgb_triple(value)
+            } else {
+                parse_hex(value)
+            };
+            colors.push(color.ok_or_else(|| PaletteError::InvalidColor {
+                line: i + 1,
+                text: line.to_string(),
+            })?);
+        }
+
+        let shades = |i: usize| -> [u32; 4] { colors[i * 4..i * 4 + 4].try_into().unwrap() };
+        match colors.len() {
+            4 => Ok(Self::uniform(shades(0))),
+            12 => Ok(Self {
+                bg: shades(0),
+                obj0: shades(1),
+                obj1: shades(2),
+            }),
+            n => Err(PaletteError::WrongCount(n)),
+        }
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    let hex = text
+        .trim_start_matches('#')
+        .trim_start_matches("0x")
+        .trim_start_matches("0X");
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn parse_rgb_triple(text: &str) -> Option<u32> {
+    let mut parts = text.split_whitespace().map(|p| p.parse::<u8>().ok());
+    let (Some(Some(r)), Some(Some(g)), Some(Some(b)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    Some(((r as u32) << 16) | ((g as u32) << 8) | b as u32)
+}
@@ -1,9 +1,27 @@
+use crate::{
+    interrupts::{InterruptType, Interrupts},
+    palette::DmgPalette,
+    screenshot,
+};
+use image::ImageResult;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Dots per scanline, including VBlank lines.
+const LINE_DOTS: u16 = 456;
+/// Length of the OAM scan (mode 2).
+const OAM_SCAN_DOTS: u16 = 80;
+/// Shortest possible mode 3: no fine scroll and no sprites.
+const MIN_DRAW_DOTS: u16 = 172;
+
 pub struct Ppu {
     pub vram: [[u8; 0x2000]; 2],
     pub vram_bank: usize,
     pub oam: [u8; 0xA0],
 
     cgb: bool,
+    /// CGB running in DMG compatibility mode (KEY0)
+    dmg_compat: bool,
 
     lcdc: u8,
     stat: u8,
@@ -15,11 +33,16 @@ pub struct Ppu {
     bgp: u8,
     obp0: u8,
     obp1: u8,
+    /// Colors used for DMG shades, selected by BGP/OBP0/OBP1
+    dmg_palette: DmgPalette,
     wy: u8,
     wx: u8,
 
     /// Internal window line counter
     win_line_counter: u8,
+    /// LY matched WY at some point this frame; the window can only appear
+    /// from then on, even if WY changes afterwards.
+    wy_triggered: bool,
 
     bgpi: u8,
     bgpd: [u8; 0x40],
@@ -28,8 +51,14 @@ pub struct Ppu {
     /// Object priority mode register (OPRI)
     opri: u8,
 
-    mode_clock: u16,
+    /// Dots elapsed since the start of the current line.
+    line_dots: u16,
+    /// Length of mode 3 on the current line, set by the OAM scan.
+    draw_dots: u16,
     pub mode: u8,
+    /// The LCD was just switched on. The first line skips the OAM scan and
+    /// reports mode 0 in its place.
+    first_line: bool,
 
     pub framebuffer: [u32; 160 * 144],
     line_priority: [bool; 160],
@@ -39,11 +68,47 @@ pub struct Ppu {
     sprite_count: usize,
     /// Indicates a completed frame is available in `framebuffer`
     frame_ready: bool,
+    /// Scroll registers used for each visible line of the current frame
+    line_scroll: [LineScroll; 144],
+    prev_stat_irq: u8,
+    /// When false, scanlines are timed but not drawn, e.g. for frame skip.
+    render_enabled: bool,
+}
+
+/// Memory, registers and timing state of the PPU, used by savestates. The
+/// DMG color palette is a frontend setting and is not included.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PpuState {
+    vram: Vec<u8>,
+    vram_bank: usize,
+    oam: Vec<u8>,
+    dmg_compat: bool,
+    /// LCDC, STAT, SCY, SCX, LY, LYC, DMA, BGP, OBP0, OBP1, WY and WX.
+    regs: [u8; 12],
+    win_line_counter: u8,
+    wy_triggered: bool,
+    bgpi: u8,
+    bgpd: Vec<u8>,
+    obpi: u8,
+    obpd: Vec<u8>,
+    opri: u8,
+    line_dots: u16,
+    draw_dots: u16,
+    mode: u8,
+    first_line: bool,
+    framebuffer: Vec<u32>,
+    frame_ready: bool,
     prev_stat_irq: u8,
 }
 
-/// Default DMG palette colors in 0x00RRGGBB order for `minifb`.
-const DMG_PALETTE: [u32; 4] = [0x009BBC0F, 0x008BAC0F, 0x00306230, 0x000F380F];
+/// SCX/SCY/WX/WY as sampled when a visible scanline was rendered.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LineScroll {
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+}
 
 #[derive(Copy, Clone, Default)]
 struct Sprite {
@@ -61,6 +126,7 @@ impl Ppu {
             vram_bank: 0,
             oam: [0; 0xA0],
             cgb,
+            dmg_compat: false,
             lcdc: 0,
             stat: 0,
             scy: 0,
@@ -71,23 +137,29 @@ impl Ppu {
             bgp: 0,
             obp0: 0,
             obp1: 0,
+            dmg_palette: DmgPalette::default(),
             wy: 0,
             wx: 0,
             win_line_counter: 0,
+            wy_triggered: false,
             bgpi: 0,
             bgpd: [0; 0x40],
             obpi: 0,
             obpd: [0; 0x40],
             opri: 0,
-            mode_clock: 0,
+            line_dots: 0,
+            draw_dots: MIN_DRAW_DOTS,
             mode: 2,
+            first_line: false,
             framebuffer: [0; 160 * 144],
             line_priority: [false; 160],
             line_color_zero: [false; 160],
             line_sprites: [Sprite::default(); 10],
             sprite_count: 0,
             frame_ready: false,
+            line_scroll: [LineScroll::default(); 144],
             prev_stat_irq: 0,
+            render_enabled: true,
         }
     }
 
@@ -112,19 +184,85 @@ impl Ppu {
                 self.sprite_count += 1;
             }
         }
-        if self.cgb && self.opri & 0x01 == 0 {
+        if self.cgb_features() && self.opri & 0x01 == 0 {
             // CGB-style priority: use OAM order only
             self.line_sprites[..self.sprite_count].sort_by_key(|s| s.oam_index);
         } else {
             // DMG-style priority: sort by X position then OAM index
             self.line_sprites[..self.sprite_count].sort_by_key(|s| (s.x, s.oam_index));
         }
+        self.draw_dots = self.mode3_length();
+    }
+
+    /// Dots spent in mode 3 on this line: 172, plus the pixels discarded for
+    /// SCX fine scrolling and a penalty for every sprite fetched, following
+    /// the approximation in Pan Docs.
+    fn mode3_length(&self) -> u16 {
+        let mut dots = MIN_DRAW_DOTS + (self.scx & 0x07) as u16;
+        if self.lcdc & 0x02 == 0 {
+            return dots;
+        }
+        // BG tile columns whose fetch a sprite already waited for.
+        let mut seen = [false; 22];
+        for s in &self.line_sprites[..self.sprite_count] {
+            dots += 6;
+            if s.x == -8 {
+                dots += 5;
+                continue;
+            }
+            let px = (s.x + 8 + (self.scx & 0x07) as i16) as usize;
+            let col = px / 8;
+            if col < seen.len() && !seen[col] {
+                seen[col] = true;
+                dots += (7 - (px % 8) as u16).saturating_sub(2);
+            }
+        }
+        dots
     }
 
     pub fn new() -> Self {
         Self::new_with_mode(false)
     }
 
+    /// Whether CGB-only rendering features (attributes, palette RAM
+    /// selection, OAM-order priority) are active.
+    fn cgb_features(&self) -> bool {
+        self.cgb && !self.dmg_compat
+    }
+
+    /// Enter or leave DMG compatibility mode, as selected through KEY0.
+    pub fn set_dmg_compat(&mut self, on: bool) {
+        self.dmg_compat = on;
+    }
+
+    pub fn dmg_compat(&self) -> bool {
+        self.dmg_compat
+    }
+
+    /// Color of a background shade (0-3) already mapped through BGP. In DMG
+    /// compatibility mode it comes from CGB background palette 0.
+    fn bg_shade(&self, shade: u8) -> u32 {
+        if self.cgb {
+            let off = shade as usize * 2;
+            Self::decode_cgb_color(self.bgpd[off], self.bgpd[off + 1])
+        } else {
+            self.dmg_palette.bg[shade as usize]
+        }
+    }
+
+    /// Color of a sprite shade (0-3) already mapped through OBP0/OBP1. In
+    /// DMG compatibility mode it comes from CGB object palette 0 or 1.
+    fn obj_shade(&self, palette: usize, shade: u8) -> u32 {
+        if self.cgb {
+            let off = palette * 8 + shade as usize * 2;
+            Self::decode_cgb_color(self.obpd[off], self.obpd[off + 1])
+        } else if palette == 1 {
+            self.dmg_palette.obj1[shade as usize]
+        } else {
+            self.dmg_palette.obj0[shade as usize]
+        }
+    }
+
     fn decode_cgb_color(lo: u8, hi: u8) -> u32 {
         let raw = ((hi as u16) << 8) | lo as u16;
         let r = ((raw & 0x1F) as u8) << 3 | ((raw & 0x1F) as u8 >> 2);
@@ -142,6 +280,7 @@ impl Ppu {
         self.bgp = 0xFC;
         self.mode = 1;
         self.win_line_counter = 0;
+        self.wy_triggered = false;
     }
 
     /// Load the default CGB palettes used when running a DMG cartridge in
@@ -171,6 +310,23 @@ impl Ppu {
         }
     }
 
+    /// Colors currently used for DMG shades.
+    pub fn dmg_palette(&self) -> DmgPalette {
+        self.dmg_palette
+    }
+
+    /// Replace the colors used for DMG shades. Has no effect on CGB
+    /// rendering, which uses the palette RAM.
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        self.dmg_palette = palette;
+    }
+
+    /// Enable or disable drawing. While disabled the PPU keeps its timing,
+    /// interrupts and window state but leaves the framebuffer untouched.
+    pub fn set_render_enabled(&mut self, enabled: bool) {
+        self.render_enabled = enabled;
+    }
+
     /// Returns true if a full frame has been rendered and is ready to display.
     pub fn frame_ready(&self) -> bool {
         self.frame_ready
@@ -187,16 +343,51 @@ impl Ppu {
         &self.framebuffer
     }
 
+    /// Returns the framebuffer as tightly packed bytes in R, G, B, A order,
+    /// independent of host endianness. Alpha is always 0xFF.
+    pub fn framebuffer_rgba8(&self) -> Vec<u8> {
+        self.framebuffer
+            .iter()
+            .flat_map(|&c| [(c >> 16) as u8, (c >> 8) as u8, c as u8, 0xFF])
+            .collect()
+    }
+
+    /// Save the current framebuffer as a 160x144 PNG.
+    pub fn save_png(&self, path: &Path) -> ImageResult<()> {
+        screenshot::save_png(path, &self.framebuffer, 160, 144)
+    }
+
+    /// Returns the scroll registers each visible line was rendered with.
+    pub fn line_scroll(&self) -> &[LineScroll; 144] {
+        &self.line_scroll
+    }
+
+    /// Format `line_scroll()` as CSV with one row per scanline.
+    pub fn scroll_log_csv(&self) -> String {
+        let mut out = String::from("ly,scx,scy,wx,wy\n");
+        for (ly, s) in self.line_scroll.iter().enumerate() {
+            out.push_str(&format!("{ly},{},{},{},{}\n", s.scx, s.scy, s.wx, s.wy));
+        }
+        out
+    }
+
     /// Clears the frame ready flag after a frame has been consumed.
     pub fn clear_frame_flag(&mut self) {
         self.frame_ready = false;
     }
 
+    /// Whether LCDC bit 7 has the display switched on.
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
     pub fn read_reg(&mut self, addr: u16) -> u8 {
         match addr {
             0xFF40 => self.lcdc,
             0xFF41 => {
-                (self.stat & 0x78) | (self.mode & 0x03) | if self.ly == self.lyc { 0x04 } else { 0 }
+                0x80 | (self.stat & 0x78)
+                    | (self.mode & 0x03)
+                    | if self.ly == self.lyc { 0x04 } else { 0 }
             }
             0xFF42 => self.scy,
             0xFF43 => self.scx,
@@ -208,7 +399,10 @@ impl Ppu {
             0xFF49 => self.obp1,
             0xFF4A => self.wy,
             0xFF4B => self.wx,
-            0xFF68 => self.bgpi,
+            // Bit 6 of the index registers is unused and reads as 1.
+            0xFF68 => self.bgpi | 0x40,
+            // Palette RAM is locked while the PPU reads it in mode 3.
+            0xFF69 if self.mode == 3 => 0xFF,
             0xFF69 => {
                 let val = self.bgpd[(self.bgpi & 0x3F) as usize];
                 if self.bgpi & 0x80 != 0 {
@@ -216,7 +410,8 @@ impl Ppu {
                 }
                 val
             }
-            0xFF6A => self.obpi,
+            0xFF6A => self.obpi | 0x40,
+            0xFF6B if self.mode == 3 => 0xFF,
             0xFF6B => {
                 let val = self.obpd[(self.obpi & 0x3F) as usize];
                 if self.obpi & 0x80 != 0 {
@@ -231,7 +426,20 @@ impl Ppu {
 
     pub fn write_reg(&mut self, addr: u16, val: u8) {
         match addr {
-            0xFF40 => self.lcdc = val,
+            0xFF40 => {
+                let was_on = self.lcdc & 0x80 != 0;
+                self.lcdc = val;
+                match (was_on, val & 0x80 != 0) {
+                    (true, false) => self.lcd_off(),
+                    (false, true) => {
+                        self.ly = 0;
+                        self.mode = 0;
+                        self.line_dots = 0;
+                        self.first_line = true;
+                    }
+                    _ => {}
+                }
+            }
             0xFF41 => self.stat = (self.stat & 0x07) | (val & 0xF8),
             0xFF42 => self.scy = val,
             0xFF43 => self.scx = val,
@@ -243,18 +451,23 @@ impl Ppu {
             0xFF49 => self.obp1 = val,
             0xFF4A => self.wy = val,
             0xFF4B => self.wx = val,
-            0xFF68 => self.bgpi = val,
+            0xFF68 => self.bgpi = val & 0xBF,
+            // Writes during mode 3 are dropped, but still advance the index.
             0xFF69 => {
                 let idx = (self.bgpi & 0x3F) as usize;
-                self.bgpd[idx] = val;
+                if self.mode != 3 {
+                    self.bgpd[idx] = val;
+                }
                 if self.bgpi & 0x80 != 0 {
                     self.bgpi = (self.bgpi & 0x80) | ((idx as u8 + 1) & 0x3F);
                 }
             }
-            0xFF6A => self.obpi = val,
+            0xFF6A => self.obpi = val & 0xBF,
             0xFF6B => {
                 let idx = (self.obpi & 0x3F) as usize;
-                self.obpd[idx] = val;
+                if self.mode != 3 {
+                    self.obpd[idx] = val;
+                }
                 if self.obpi & 0x80 != 0 {
                     self.obpi = (self.obpi & 0x80) | ((idx as u8 + 1) & 0x3F);
                 }
@@ -269,29 +482,47 @@ impl Ppu {
             return;
         }
 
+        self.line_scroll[self.ly as usize] = LineScroll {
+            scx: self.scx,
+            scy: self.scy,
+            wx: self.wx,
+            wy: self.wy,
+        };
+
+        if self.ly == self.wy {
+            self.wy_triggered = true;
+        }
+
+        // In DMG compatibility mode the CGB renders like a DMG, only
+        // taking the final colors from palette RAM.
+        let cgb = self.cgb_features();
+
         self.line_priority.fill(false);
         self.line_color_zero.fill(false);
 
-        let bg_enabled = if self.cgb {
-            true
-        } else {
-            self.lcdc & 0x01 != 0
-        };
-        let master_priority = if self.cgb {
-            self.lcdc & 0x01 != 0
-        } else {
-            true
-        };
+        let bg_enabled = if cgb { true } else { self.lcdc & 0x01 != 0 };
+        let master_priority = if cgb { self.lcdc & 0x01 != 0 } else { true };
+        let window_visible =
+            bg_enabled && self.lcdc & 0x20 != 0 && self.wy_triggered && self.wx <= 166;
+
+        if !self.render_enabled {
+            // The window line counter still has to advance so the frames
+            // that are drawn place the window correctly.
+            if window_visible {
+                self.win_line_counter = self.win_line_counter.wrapping_add(1);
+            }
+            return;
+        }
 
         // Pre-fill the scanline. When the background is disabled via LCDC bit 0
         // in DMG mode, the Game Boy outputs color 0 for every pixel and sprites
         // treat the line as having color 0. The framebuffer is initialized with
         // this color so sprite rendering can overlay on top.
-        let bg_color = if self.cgb {
+        let bg_color = if cgb {
             Self::decode_cgb_color(self.bgpd[0], self.bgpd[1])
         } else {
             let idx = self.bgp & 0x03;
-            DMG_PALETTE[idx as usize]
+            self.bg_shade(idx)
         };
         for x in 0..160usize {
             let idx = self.ly as usize * 160 + x;
@@ -329,7 +560,7 @@ impl Ppu {
                 let mut priority = false;
                 let mut palette = 0usize;
                 let mut bank = 0usize;
-                if self.cgb {
+                if cgb {
                     let attr = self.vram[1][tile_map_base + tile_row * 32 + tile_col];
                     palette = (attr & 0x07) as usize;
                     bank = if attr & 0x08 != 0 { 1 } else { 0 };
@@ -344,7 +575,7 @@ impl Ppu {
                 let lo = self.vram[bank][addr + tile_y * 2];
                 let hi = self.vram[bank][addr + tile_y * 2 + 1];
                 let color_id = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
-                let (color, color_idx) = if self.cgb {
+                let (color, color_idx) = if cgb {
                     let off = palette * 8 + color_id as usize * 2;
                     (
                         Self::decode_cgb_color(self.bgpd[off], self.bgpd[off + 1]),
@@ -352,7 +583,7 @@ impl Ppu {
                     )
                 } else {
                     let idx = (self.bgp >> (color_id * 2)) & 0x03;
-                    (DMG_PALETTE[idx as usize], idx)
+                    (self.bg_shade(idx), idx)
                 };
                 let idx = self.ly as usize * 160 + x as usize;
                 self.framebuffer[idx] = color;
@@ -362,16 +593,17 @@ impl Ppu {
 
             // window
             let mut window_drawn = false;
-            if self.lcdc & 0x20 != 0 && self.ly >= self.wy && self.wx <= 166 {
-                let wx = self.wx.wrapping_sub(7) as u16;
+            if window_visible {
+                // WX below 7 starts the window partly off the left edge.
+                let wx = self.wx as i16 - 7;
                 let window_map_base = if self.lcdc & 0x40 != 0 {
                     0x1C00
                 } else {
                     0x1800
                 };
                 let window_y = self.win_line_counter as usize;
-                for x in wx..160 {
-                    let window_x = (x - wx) as usize;
+                for x in wx.max(0) as u16..160 {
+                    let window_x = (x as i16 - wx) as usize;
                     let tile_col = window_x / 8;
                     let tile_row = window_y / 8;
                     let mut tile_y = window_y % 8;
@@ -386,7 +618,7 @@ impl Ppu {
                     let mut priority = false;
                     let mut palette = 0usize;
                     let mut bank = 0usize;
-                    if self.cgb {
+                    if cgb {
                         let attr = self.vram[1][window_map_base + tile_row * 32 + tile_col];
                         palette = (attr & 0x07) as usize;
                         bank = if attr & 0x08 != 0 { 1 } else { 0 };
@@ -401,7 +633,7 @@ impl Ppu {
                     let lo = self.vram[bank][addr + tile_y * 2];
                     let hi = self.vram[bank][addr + tile_y * 2 + 1];
                     let color_id = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
-                    let (color, color_idx) = if self.cgb {
+                    let (color, color_idx) = if cgb {
                         let off = palette * 8 + color_id as usize * 2;
                         (
                             Self::decode_cgb_color(self.bgpd[off], self.bgpd[off + 1]),
@@ -409,7 +641,7 @@ impl Ppu {
                         )
                     } else {
                         let idx = (self.bgp >> (color_id * 2)) & 0x03;
-                        (DMG_PALETTE[idx as usize], idx)
+                        (self.bg_shade(idx), idx)
                     };
                     let idx = self.ly as usize * 160 + x as usize;
                     self.framebuffer[idx] = color;
@@ -438,7 +670,7 @@ impl Ppu {
                 if s.flags & 0x40 != 0 {
                     line_idx = sprite_height - 1 - line_idx;
                 }
-                let bank = if self.cgb {
+                let bank = if cgb {
                     ((s.flags >> 3) & 0x01) as usize
                 } else {
                     0
@@ -457,38 +689,136 @@ impl Ppu {
                     if !(0i16..160i16).contains(&sx) || drawn[sx as usize] {
                         continue;
                     }
+                    // The first opaque sprite pixel claims the position even
+                    // when it ends up hidden behind the background, so lower
+                    // priority sprites never show through it.
+                    drawn[sx as usize] = true;
                     let bg_zero = if !bg_enabled {
                         true
                     } else {
                         self.line_color_zero[sx as usize]
                     };
                     if master_priority {
-                        if self.cgb && self.line_priority[sx as usize] && !bg_zero {
+                        if cgb && self.line_priority[sx as usize] && !bg_zero {
                             continue;
                         }
                         if s.flags & 0x80 != 0 && !bg_zero {
                             continue;
                         }
                     }
-                    let color = if self.cgb {
+                    let color = if cgb {
                         let palette = (s.flags & 0x07) as usize;
                         let off = palette * 8 + color_id as usize * 2;
                         Self::decode_cgb_color(self.obpd[off], self.obpd[off + 1])
                     } else if s.flags & 0x10 != 0 {
                         let idxc = (self.obp1 >> (color_id * 2)) & 0x03;
-                        DMG_PALETTE[idxc as usize]
+                        self.obj_shade(1, idxc)
                     } else {
                         let idxc = (self.obp0 >> (color_id * 2)) & 0x03;
-                        DMG_PALETTE[idxc as usize]
+                        self.obj_shade(0, idxc)
                     };
                     let idx = self.ly as usize * 160 + sx as usize;
                     self.framebuffer[idx] = color;
-                    drawn[sx as usize] = true;
                 }
             }
         }
     }
 
+    /// Capture VRAM, OAM, palette RAM, registers and timing for a savestate.
+    pub fn save_state(&self) -> PpuState {
+        PpuState {
+            vram: self.vram.concat(),
+            vram_bank: self.vram_bank,
+            oam: self.oam.to_vec(),
+            dmg_compat: self.dmg_compat,
+            regs: [
+                self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.dma, self.bgp,
+                self.obp0, self.obp1, self.wy, self.wx,
+            ],
+            win_line_counter: self.win_line_counter,
+            wy_triggered: self.wy_triggered,
+            bgpi: self.bgpi,
+            bgpd: self.bgpd.to_vec(),
+            obpi: self.obpi,
+            obpd: self.obpd.to_vec(),
+            opri: self.opri,
+            line_dots: self.line_dots,
+            draw_dots: self.draw_dots,
+            mode: self.mode,
+            first_line: self.first_line,
+            framebuffer: self.framebuffer.to_vec(),
+            frame_ready: self.frame_ready,
+            prev_stat_irq: self.prev_stat_irq,
+        }
+    }
+
+    /// Check the timing in `state` before `load_state` restores it, so a
+    /// corrupt savestate cannot make rendering index past the framebuffer.
+    pub fn check_state(state: &PpuState) -> Result<(), String> {
+        let ly = state.regs[4];
+        if ly > 153 {
+            return Err(format!("LY {ly} is past the last line"));
+        }
+        if state.mode > 3 {
+            return Err(format!("PPU mode {} does not exist", state.mode));
+        }
+        if matches!(state.mode, 2 | 3) && ly >= 144 {
+            return Err(format!("PPU mode {} on VBlank line {ly}", state.mode));
+        }
+        if state.line_dots >= LINE_DOTS || state.draw_dots > LINE_DOTS - OAM_SCAN_DOTS {
+            return Err("PPU line timing is out of range".to_string());
+        }
+        Ok(())
+    }
+
+    /// Restore state captured by `save_state`. Buffers of the wrong size
+    /// are left untouched.
+    pub fn load_state(&mut self, state: &PpuState) {
+        for (bank, src) in self.vram.iter_mut().zip(state.vram.chunks_exact(0x2000)) {
+            bank.copy_from_slice(src);
+        }
+        copy_if_len(&mut self.oam, &state.oam);
+        copy_if_len(&mut self.bgpd, &state.bgpd);
+        copy_if_len(&mut self.obpd, &state.obpd);
+        copy_if_len(&mut self.framebuffer, &state.framebuffer);
+        self.vram_bank = state.vram_bank & 1;
+        self.dmg_compat = state.dmg_compat;
+        [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.dma, self.bgp,
+            self.obp0, self.obp1, self.wy, self.wx,
+        ] = state.regs;
+        self.win_line_counter = state.win_line_counter;
+        self.wy_triggered = state.wy_triggered;
+        self.bgpi = state.bgpi;
+        self.obpi = state.obpi;
+        self.opri = state.opri;
+        self.line_dots = state.line_dots;
+        self.draw_dots = state.draw_dots;
+        self.mode = state.mode;
+        self.first_line = state.first_line;
+        self.frame_ready = state.frame_ready;
+        self.prev_stat_irq = state.prev_stat_irq;
+        // The sprites latched by the OAM scan are not saved; OAM cannot
+        // change during mode 3, so scanning again finds the same ones.
+        if self.mode == 3 {
+            self.oam_scan();
+        }
+    }
+
+    /// Current PPU mode: 0 HBlank, 1 VBlank, 2 OAM scan, 3 drawing.
+    pub fn current_mode(&self) -> u8 {
+        self.mode
+    }
+
+    /// Dots elapsed since the start of the current line (0-455).
+    pub fn dots_this_line(&self) -> u16 {
+        self.line_dots
+    }
+
+    #[cfg_attr(
+        feature = "profiling",
+        tracing::instrument(name = "Ppu::step", skip_all)
+    )]
     pub fn step(&mut self, cycles: u16, if_reg: &mut u8) {
         let mut remaining = cycles;
         while remaining > 0 {
@@ -497,97 +827,98 @@ impl Ppu {
             if self.lcdc & 0x80 == 0 {
                 self.mode = 0;
                 self.ly = 0;
-                self.mode_clock = 0;
+                self.line_dots = 0;
                 self.win_line_counter = 0;
+                self.wy_triggered = false;
                 continue;
             }
 
-            self.mode_clock += increment;
+            self.line_dots += increment;
 
             match self.mode {
-                0 => {
-                    if self.mode_clock >= 204 {
-                        self.mode_clock -= 204;
-                        self.ly += 1;
-                        if self.ly == 144 {
-                            self.frame_ready = true;
-                            self.mode = 1;
-                            if self.stat & 0x10 != 0 {
-                                *if_reg |= 0x02;
-                            }
-                            *if_reg |= 0x01;
-                        } else {
-                            self.mode = 2;
-                            if self.stat & 0x20 != 0 {
-                                *if_reg |= 0x02;
-                            }
-                        }
-                    }
+                0 if self.first_line && self.line_dots >= OAM_SCAN_DOTS => {
+                    self.first_line = false;
+                    self.oam_scan();
+                    self.mode = 3;
                 }
-                1 => {
-                    if self.mode_clock >= 456 {
-                        self.mode_clock -= 456;
-                        self.ly += 1;
-                        if self.ly > 153 {
-                            self.ly = 0;
-                            self.frame_ready = false;
-                            self.win_line_counter = 0;
-                            self.mode = 2;
-                            if self.stat & 0x20 != 0 {
-                                *if_reg |= 0x02;
-                            }
-                        }
+                0 if !self.first_line && self.line_dots >= LINE_DOTS => {
+                    self.line_dots -= LINE_DOTS;
+                    self.ly += 1;
+                    if self.ly == 144 {
+                        self.frame_ready = true;
+                        self.mode = 1;
+                        Interrupts::request(if_reg, InterruptType::VBlank);
+                    } else {
+                        self.mode = 2;
                     }
                 }
-                2 => {
-                    if self.mode_clock >= 80 {
-                        self.mode_clock -= 80;
-                        self.oam_scan();
-                        self.mode = 3;
+                1 if self.line_dots >= LINE_DOTS => {
+                    self.line_dots -= LINE_DOTS;
+                    self.ly += 1;
+                    if self.ly > 153 {
+                        self.ly = 0;
+                        self.frame_ready = false;
+                        self.win_line_counter = 0;
+                        self.wy_triggered = false;
+                        self.mode = 2;
                     }
                 }
-                3 => {
-                    if self.mode_clock >= 172 {
-                        self.mode_clock -= 172;
-                        self.render_scanline();
-                        self.mode = 0;
-                        if self.stat & 0x08 != 0 {
-                            *if_reg |= 0x02;
-                        }
-                    }
+                2 if self.line_dots >= OAM_SCAN_DOTS => {
+                    self.oam_scan();
+                    self.mode = 3;
+                }
+                3 if self.line_dots >= OAM_SCAN_DOTS + self.draw_dots => {
+                    self.render_scanline();
+                    self.mode = 0;
                 }
                 _ => {}
             }
 
+            // All STAT sources share one interrupt line, see
+            // `update_stat_irq`.
             self.update_stat_irq(if_reg);
         }
     }
 
+    /// Stop the LCD: LY and the mode reset to 0, the STAT line drops and the
+    /// screen goes blank.
+    fn lcd_off(&mut self) {
+        self.ly = 0;
+        self.mode = 0;
+        self.line_dots = 0;
+        self.win_line_counter = 0;
+        self.wy_triggered = false;
+        self.prev_stat_irq = 0;
+        let blank = if self.cgb_features() {
+            0x00FFFFFF
+        } else {
+            self.bg_shade(0)
+        };
+        self.framebuffer.fill(blank);
+    }
+
+    /// Request a STAT interrupt on a rising edge of the combined STAT line.
+    /// While one enabled source holds the line high, other sources becoming
+    /// active do not raise a new interrupt ("STAT blocking").
     fn update_stat_irq(&mut self, if_reg: &mut u8) {
         let mut current = 0u8;
         if self.ly == self.lyc && self.stat & 0x40 != 0 {
             current |= 0x40;
         }
         match self.mode {
-            0 => {
-                if self.stat & 0x08 != 0 {
-                    current |= 0x08;
-                }
+            0 if self.stat & 0x08 != 0 => {
+                current |= 0x08;
             }
-            1 => {
-                if self.stat & 0x10 != 0 {
-                    current |= 0x10;
-                }
+            1 if self.stat & 0x10 != 0 => {
+                current |= 0x10;
             }
-            2 => {
-                if self.stat & 0x20 != 0 {
-                    current |= 0x20;
-                }
+            2 if self.stat & 0x20 != 0 => {
+                current |= 0x20;
             }
             _ => {}
         }
-        if current & !self.prev_stat_irq != 0 {
-            *if_reg |= 0x02;
+        if current != 0 && self.prev_stat_irq == 0 {
+            Interrupts::request(if_reg, InterruptType::LcdStat);
         }
         self.prev_stat_irq = current;
     }
@@ -598,3 +929,9 @@ impl Default for Ppu {
         Self::new()
     }
 }
+
+fn copy_if_len<T: Copy>(dst: &mut [T], src: &[T]) {
+    if dst.len() == src.len() {
+        dst.copy_from_slice(src);
+    }
+}
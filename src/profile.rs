@@ -0,0 +1,160 @@
+use crate::{cartridge::MbcType, gameboy::GameBoy};
+use std::{collections::HashMap, fmt};
+
+/// Per-game accuracy settings that would otherwise be passed as individual
+/// command line flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// Mapper to use instead of the one declared in the header.
+    pub force_mbc: Option<MbcType>,
+    /// Run illegal opcodes as NOPs instead of locking up.
+    pub lenient: bool,
+    /// Force DMG (`false`) or CGB (`true`) hardware. Only consulted when the
+    /// `GameBoy` is created, so `apply` ignores it.
+    pub cgb: Option<bool>,
+}
+
+impl Quirks {
+    /// Enable these quirks on a `GameBoy` whose cartridge is already loaded.
+    pub fn apply(&self, gb: &mut GameBoy) {
+        gb.cpu.lenient |= self.lenient;
+        if let Some(mbc) = self.force_mbc
+            && let Some(cart) = gb.mmu.cart.as_mut()
+        {
+            cart.force_mbc(mbc);
+        }
+    }
+}
+
+/// Reasons a profile file could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileError {
+    /// A section header was not a CRC32 in hex.
+    InvalidHash {
+        line: usize,
+        text: String,
+    },
+    /// A setting appeared before the first section header.
+    MissingSection {
+        line: usize,
+    },
+    /// A line was neither a section header nor `key = value`.
+    InvalidLine {
+        line: usize,
+        text: String,
+    },
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::InvalidHash { line, text } => {
+                write!(f, "line {line}: invalid ROM hash '{text}'")
+            }
+            ProfileError::MissingSection { line } => {
+                write!(f, "line {line}: setting outside of a [ROM hash] section")
+            }
+            ProfileError::InvalidLine { line, text } => {
+                write!(f, "line {line}: expected 'key = value', found '{text}'")
+            }
+            ProfileError::UnknownKey { line, key } => write!(f, "line {line}: unknown key '{key}'"),
+            ProfileError::InvalidValue { line, key, value } => {
+                write!(f, "line {line}: invalid value '{value}' for '{key}'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// CRC32 of a ROM image, the key used to match profile entries.
+pub fn rom_hash(rom: &[u8]) -> u32 {
+    crc32fast::hash(rom)
+}
+
+/// A set of quirk profiles keyed by ROM hash.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileSet {
+    entries: HashMap<u32, Quirks>,
+}
+
+impl ProfileSet {
+    /// Parse an ini-style profile file. Each section is headed by a ROM's
+    /// CRC32 in hex and lists its quirks:
+    ///
+    /// ```text
+    /// [1A2B3C4D]
+    /// force_mbc = mbc5
+    /// lenient = true
+    /// mode = dmg
+    /// ```
+    ///
+    /// Blank lines and lines starting with `;` or `#` are ignored.
+    pub fn parse(text: &str) -> Result<Self, ProfileError> {
+        let mut entries = HashMap::new();
+        let mut current = None;
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let hash = u32::from_str_radix(section.trim().trim_start_matches("0x"), 16)
+                    .map_err(|_| ProfileError::InvalidHash {
+                        line: line_no,
+                        text: section.to_string(),
+                    })?;
+                entries.entry(hash).or_insert_with(Quirks::default);
+                current = Some(hash);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ProfileError::InvalidLine {
+                    line: line_no,
+                    text: line.to_string(),
+                });
+            };
+            let hash = current.ok_or(ProfileError::MissingSection { line: line_no })?;
+            let quirks = entries.get_mut(&hash).unwrap();
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || ProfileError::InvalidValue {
+                line: line_no,
+                key: key.to_string(),
+                value: value.to_string(),
+            };
+            match key {
+                "force_mbc" => quirks.force_mbc = Some(value.parse().map_err(|_| invalid())?),
+                "lenient" => quirks.lenient = value.parse().map_err(|_| invalid())?,
+                "mode" => {
+                    quirks.cgb = match value.to_ascii_lowercase().as_str() {
+                        "dmg" => Some(false),
+                        "cgb" => Some(true),
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => {
+                    return Err(ProfileError::UnknownKey {
+                        line: line_no,
+                        key: key.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// Quirks for the ROM image `rom`, if the set has an entry for it.
+    pub fn lookup(&self, rom: &[u8]) -> Option<&Quirks> {
+        self.entries.get(&rom_hash(rom))
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+
+/// Ring buffer of savestates taken every few frames, used to step the
+/// emulation backwards.
+///
+/// Only the newest snapshot is stored in full. Each older one is kept as the
+/// XOR of itself and the snapshot after it, with runs of zero bytes
+/// collapsed, since consecutive states differ in few bytes.
+pub struct RewindBuffer {
+    /// Frames between snapshots.
+    interval: u32,
+    /// Maximum number of snapshots kept.
+    depth: usize,
+    /// Frames run since the last snapshot.
+    frames: u32,
+    newest: Option<Vec<u8>>,
+    /// Older snapshots as deltas, oldest first.
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    /// Snapshot every `interval` frames (at least 1), keeping at most
+    /// `depth` snapshots.
+    pub fn new(interval: u32, depth: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            depth: depth.max(1),
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Count a finished frame. Returns true when a snapshot is due.
+    pub fn frame_done(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < self.interval {
+            return false;
+        }
+        self.frames = 0;
+        true
+    }
+
+    /// Store a new snapshot, dropping the oldest one beyond the depth.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(prev) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&prev, &state));
+        }
+        self.newest = Some(state);
+        while self.len() > self.depth {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Remove and return the newest snapshot.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self.deltas.pop_back().map(|d| decode_delta(&newest, &d));
+        self.frames = 0;
+        Some(newest)
+    }
+
+    /// The newest snapshot, if any.
+    pub fn peek(&self) -> Option<&[u8]> {
+        self.newest.as_deref()
+    }
+
+    /// Number of snapshots held.
+    pub fn len(&self) -> usize {
+        self.newest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes used by the stored snapshots.
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+}
+
+/// Marks a run of zero bytes in a delta, followed by the run length as a
+/// little-endian u16. A literal 0x00 is therefore always encoded as a run.
+const ZERO_RUN: u8 = 0x00;
+/// Marks a delta between states of different sizes; the older state follows
+/// verbatim.
+const RAW: u8 = 0xFF;
+const DELTA: u8 = 0x01;
+
+/// Encode `older` relative to `newer`.
+fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    if older.len() != newer.len() {
+        let mut out = vec![RAW];
+        out.extend_from_slice(older);
+        return out;
+    }
+    let mut out = vec![DELTA];
+    let mut diff = older.iter().zip(newer).map(|(a, b)| a ^ b).peekable();
+    while let Some(byte) = diff.next() {
+        if byte != 0 {
+            out.push(byte);
+            continue;
+        }
+        let mut run = 1u16;
+        while run < u16::MAX && diff.next_if_eq(&0).is_some() {
+            run += 1;
+        }
+        out.push(ZERO_RUN);
+        out.extend_from_slice(&run.to_le_bytes());
+    }
+    out
+}
+
+/// Rebuild the older state from `newer` and a delta made by `encode_delta`.
+fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let Some((&kind, body)) = delta.split_first() else {
+        return Vec::new();
+    };
+    if kind == RAW {
+        return body.to_vec();
+    }
+    let mut out = Vec::with_capacity(newer.len());
+    let mut bytes = body.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte != ZERO_RUN {
+            out.push(newer[out.len()] ^ byte);
+            continue;
+        }
+        let run = u16::from_le_bytes([*bytes.next().unwrap(), *bytes.next().unwrap()]);
+        let start = out.len();
+        out.extend_from_slice(&newer[start..start + run as usize]);
+    }
+    out
+}
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+
+/// Clock cycles in one second of the 32.768 kHz RTC crystal, in units of
+/// the normal-speed system clock.
+pub const RTC_CYCLES_PER_SECOND: u32 = 4_194_304;
+
+/// Register numbers as selected through the MBC3 RAM bank register.
+pub const RTC_SECONDS: u8 = 0x08;
+pub const RTC_MINUTES: u8 = 0x09;
+pub const RTC_HOURS: u8 = 0x0A;
+pub const RTC_DAY_LOW: u8 = 0x0B;
+pub const RTC_DAY_HIGH: u8 = 0x0C;
+
+/// Size of the BGB/VBA `.rtc` layout: live and latched registers as ten
+/// little-endian u32s, followed by a 64-bit Unix timestamp.
+pub const RTC_SAVE_LEN: usize = 48;
+/// Older saves that stored only the five live registers.
+const LEGACY_SAVE_LEN: usize = 5;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Day-high bits: day counter bit 8, halt, and day counter carry.
+const DAY_HIGH_MSB: u8 = 0x01;
+const DAY_HIGH_HALT: u8 = 0x40;
+const DAY_HIGH_CARRY: u8 = 0x80;
+
+/// Writable bits of the seconds, minutes, hours, day-low and day-high
+/// registers.
+const REG_MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+/// The real-time clock of an MBC3 cartridge. Software reads a latched copy
+/// of the counters, updated by writing 0 then 1 to 0x6000-0x7FFF.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rtc {
+    /// Live seconds, minutes, hours, day low and day high registers.
+    regs: [u8; 5],
+    latched: [u8; 5],
+    /// Last value written to the latch register.
+    latch_prev: u8,
+    /// Cycles counted towards the next second.
+    cycles: u32,
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            regs: [0; 5],
+            latched: [0; 5],
+            latch_prev: 0xFF,
+            cycles: 0,
+        }
+    }
+
+    /// Live counters in register order (seconds first).
+    pub fn registers(&self) -> [u8; 5] {
+        self.regs
+    }
+
+    /// Counters as of the last latch, in register order.
+    pub fn latched(&self) -> [u8; 5] {
+        self.latched
+    }
+
+    pub fn halted(&self) -> bool {
+        self.regs[4] & DAY_HIGH_HALT != 0
+    }
+
+    /// Read the latched value of register `reg` (0x08-0x0C).
+    pub fn read(&self, reg: u8) -> u8 {
+        match reg {
+            RTC_SECONDS..=RTC_DAY_HIGH => self.latched[(reg - RTC_SECONDS) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    /// Write the live register `reg` (0x08-0x0C).
+    pub fn write(&mut self, reg: u8, val: u8) {
+        if !(RTC_SECONDS..=RTC_DAY_HIGH).contains(&reg) {
+            return;
+        }
+        let idx = (reg - RTC_SECONDS) as usize;
+        self.regs[idx] = val & REG_MASKS[idx];
+        // Writing the seconds restarts the current second.
+        if reg == RTC_SECONDS {
+            self.cycles = 0;
+        }
+    }
+
+    /// Handle a write to the latch register; a 0 followed by a 1 copies the
+    /// live counters into the latched ones.
+    pub fn write_latch(&mut self, val: u8) {
+        if self.latch_prev == 0x00 && val == 0x01 {
+            self.latched = self.regs;
+        }
+        self.latch_prev = val;
+    }
+
+    /// Advance the clock by `cycles` normal-speed system clock cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.halted() {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= RTC_CYCLES_PER_SECOND {
+            self.cycles -= RTC_CYCLES_PER_SECOND;
+            self.advance_second();
+        }
+    }
+
+    /// Advance the clock by whole seconds, e.g. for time that passed while
+    /// the emulator wasn't running.
+    pub fn advance_seconds(&mut self, mut secs: u64) {
+        if self.halted() {
+            return;
+        }
+        // Out-of-range counters wrap differently; count them back into range
+        // one second at a time, then add the rest in one go.
+        while secs > 0 && !self.in_range() {
+            self.advance_second();
+            secs -= 1;
+        }
+        if secs == 0 {
+            return;
+        }
+        let [s, m, h, ..] = self.regs.map(u64::from);
+        let total = s + 60 * m + 3600 * h + SECONDS_PER_DAY * self.day() as u64 + secs;
+        let days = total / SECONDS_PER_DAY;
+        let rem = total % SECONDS_PER_DAY;
+        if days > 0x1FF {
+            self.regs[4] |= DAY_HIGH_CARRY;
+        }
+        self.set_day((days & 0x1FF) as u16);
+        self.regs[0] = (rem % 60) as u8;
+        self.regs[1] = (rem / 60 % 60) as u8;
+        self.regs[2] = (rem / 3600) as u8;
+    }
+
+    fn in_range(&self) -> bool {
+        self.regs[0] < 60 && self.regs[1] < 60 && self.regs[2] < 24
+    }
+
+    /// Serialize in the `.rtc` layout, stamped with `now` (Unix seconds).
+    pub fn to_save_bytes(&self, now: u64) -> [u8; RTC_SAVE_LEN] {
+        let mut out = [0; RTC_SAVE_LEN];
+        for (i, &reg) in self.regs.iter().chain(&self.latched).enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&(reg as u32).to_le_bytes());
+        }
+        out[40..].copy_from_slice(&now.to_le_bytes());
+        out
+    }
+
+    /// Restore a clock saved by `to_save_bytes` and advance it by the time
+    /// elapsed between its timestamp and `now`. Five-byte saves holding only
+    /// the live registers are accepted but cannot account for elapsed time.
+    pub fn from_save_bytes(data: &[u8], now: u64) -> Option<Self> {
+        let mut rtc = Self::new();
+        match data.len() {
+            RTC_SAVE_LEN => {
+                // Registers are stored as u32s; only the low byte matters.
+                let word = |i: usize| data[i * 4];
+                rtc.regs = std::array::from_fn(|i| word(i) & REG_MASKS[i]);
+                rtc.latched = std::array::from_fn(|i| word(i + 5) & REG_MASKS[i]);
+                let saved = u64::from_le_bytes(data[40..48].try_into().unwrap());
+                rtc.advance_seconds(now.saturating_sub(saved));
+            }
+            LEGACY_SAVE_LEN => {
+                rtc.regs = std::array::from_fn(|i| data[i] & REG_MASKS[i]);
+                rtc.latched = rtc.regs;
+            }
+            _ => return None,
+        }
+        Some(rtc)
+    }
+
+    /// Count one second. Counters set out of range by software count up to
+    /// their bit width and wrap to 0 without carrying, as on hardware.
+    fn advance_second(&mut self) {
+        let [s, m, h, ..] = &mut self.regs;
+        *s = (*s + 1) & 0x3F;
+        if *s != 60 {
+            return;
+        }
+        *s = 0;
+        *m = (*m + 1) & 0x3F;
+        if *m != 60 {
+            return;
+        }
+        *m = 0;
+        *h = (*h + 1) & 0x1F;
+        if *h != 24 {
+            return;
+        }
+        *h = 0;
+        let day = self.day() + 1;
+        if day > 0x1FF {
+            self.regs[4] |= DAY_HIGH_CARRY;
+        }
+        self.set_day(day & 0x1FF);
+    }
+
+    /// The 9-bit day counter.
+    pub fn day(&self) -> u16 {
+        (((self.regs[4] & DAY_HIGH_MSB) as u16) << 8) | self.regs[3] as u16
+    }
+
+    fn set_day(&mut self, day: u16) {
+        self.regs[3] = day as u8;
+        self.regs[4] = (self.regs[4] & !DAY_HIGH_MSB) | ((day >> 8) as u8 & DAY_HIGH_MSB);
+    }
+}
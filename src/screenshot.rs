@@ -0,0 +1,79 @@
+use image::{ImageResult, Rgb, RgbImage};
+use std::path::{Path, PathBuf};
+
+/// Upper bound on screenshots written by one recorder so a long run cannot
+/// fill the disk.
+pub const MAX_SCREENSHOTS: usize = 1000;
+
+/// Save a 0x00RRGGBB framebuffer as a PNG.
+pub fn save_png(path: &Path, pixels: &[u32], width: usize, height: usize) -> ImageResult<()> {
+    let img = RgbImage::from_fn(width as u32, height as u32, |x, y| {
+        let c = pixels[y as usize * width + x as usize];
+        Rgb([(c >> 16) as u8, (c >> 8) as u8, c as u8])
+    });
+    img.save(path)
+}
+
+/// CRC32 of a 0x00RRGGBB framebuffer, for comparing rendered frames without
+/// storing images.
+pub fn frame_hash(pixels: &[u32]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for p in pixels {
+        hasher.update(&p.to_le_bytes());
+    }
+    hasher.finalize()
+}
+
+/// Writes every Nth presented frame to a numbered PNG sequence in a
+/// directory.
+pub struct ScreenshotRecorder {
+    dir: PathBuf,
+    every: u64,
+    frames: u64,
+    written: usize,
+    limit: usize,
+}
+
+impl ScreenshotRecorder {
+    /// Create a recorder saving one frame out of every `every` into `dir`,
+    /// creating the directory if needed.
+    pub fn new<P: AsRef<Path>>(dir: P, every: u64) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            every: every.max(1),
+            frames: 0,
+            written: 0,
+            limit: MAX_SCREENSHOTS,
+        })
+    }
+
+    /// Override the maximum number of screenshots written.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Count a presented frame and save it if it is due. Returns the path of
+    /// the written file, if any.
+    pub fn frame(
+        &mut self,
+        pixels: &[u32],
+        width: usize,
+        height: usize,
+    ) -> ImageResult<Option<PathBuf>> {
+        self.frames += 1;
+        if !self.frames.is_multiple_of(self.every) || self.written >= self.limit {
+            return Ok(None);
+        }
+        let path = self.dir.join(format!("{:05}.png", self.written));
+        save_png(&path, pixels, width, height)?;
+        self.written += 1;
+        Ok(Some(path))
+    }
+
+    /// Number of screenshots written so far.
+    pub fn written(&self) -> usize {
+        self.written
+    }
+}
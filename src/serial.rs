@@ -1,7 +1,27 @@
+use crate::interrupts::{InterruptType, Interrupts};
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Sender;
+
 pub trait LinkPort {
     /// Transfer a byte over the link. Returns the byte received from the
     /// partner. Implementations may perform the transfer immediately.
     fn transfer(&mut self, byte: u8) -> u8;
+
+    /// Called periodically while an external-clock transfer is waiting.
+    /// Returns the byte shifted in if the partner clocked a transfer, in
+    /// which case `byte` is what the partner receives.
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// Any `FnMut(u8) -> u8` can act as the link partner: it is called with
+/// each byte shifted out and returns the byte shifted in.
+impl<F: FnMut(u8) -> u8> LinkPort for F {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self(byte)
+    }
 }
 
 /// A stub link port used when no cable is attached.
@@ -25,12 +45,34 @@ impl LinkPort for NullLinkPort {
     }
 }
 
+/// CPU cycles per bit with the normal 8192 Hz internal clock.
+const BIT_CYCLES: u16 = 512;
+/// CPU cycles per bit with the CGB fast 262144 Hz internal clock.
+const FAST_BIT_CYCLES: u16 = 16;
+
+/// Serial registers and transfer progress, used by savestates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerialState {
+    sb: u8,
+    sc: u8,
+    bits_left: u8,
+    bit_timer: u16,
+    poll_timer: u16,
+}
+
 /// Represents the Game Boy serial registers.
 /// This struct handles SB/SC behavior and raises the serial interrupt
 /// when a transfer completes.
 pub struct Serial {
     sb: u8,
     sc: u8,
+    cgb: bool,
+    /// Bits still to shift in the current internal-clock transfer.
+    bits_left: u8,
+    /// Cycles until the next bit is shifted.
+    bit_timer: u16,
+    /// Cycles until the link port is next polled for an external clock.
+    poll_timer: u16,
     pub(crate) out_buf: Vec<u8>,
     port: Box<dyn LinkPort>,
 }
@@ -40,6 +82,10 @@ impl Serial {
         Self {
             sb: 0,
             sc: if cgb { 0x7F } else { 0x7E },
+            cgb,
+            bits_left: 0,
+            bit_timer: 0,
+            poll_timer: 0,
             out_buf: Vec::new(),
             port: Box::new(NullLinkPort::default()),
         }
@@ -49,6 +95,36 @@ impl Serial {
         self.port = port;
     }
 
+    /// Return the registers to their power-on state, keeping the link
+    /// partner connected.
+    pub fn reset(&mut self) {
+        *self = Self {
+            port: std::mem::replace(&mut self.port, Box::new(NullLinkPort::default())),
+            out_buf: std::mem::take(&mut self.out_buf),
+            ..Self::new(self.cgb)
+        };
+    }
+
+    /// Capture the registers and transfer progress for a savestate. The
+    /// link partner and captured output are not included.
+    pub fn save_state(&self) -> SerialState {
+        SerialState {
+            sb: self.sb,
+            sc: self.sc,
+            bits_left: self.bits_left,
+            bit_timer: self.bit_timer,
+            poll_timer: self.poll_timer,
+        }
+    }
+
+    pub fn load_state(&mut self, state: &SerialState) {
+        self.sb = state.sb;
+        self.sc = state.sc;
+        self.bits_left = state.bits_left;
+        self.bit_timer = state.bit_timer;
+        self.poll_timer = state.poll_timer;
+    }
+
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
             0xFF01 => self.sb,
@@ -57,23 +133,83 @@ impl Serial {
         }
     }
 
-    pub fn write(&mut self, addr: u16, val: u8, if_reg: &mut u8) {
+    pub fn write(&mut self, addr: u16, val: u8) {
         match addr {
             0xFF01 => self.sb = val,
             0xFF02 => {
                 self.sc = val;
-                if val & 0x80 != 0 {
-                    self.out_buf.push(self.sb);
-                    let received = self.port.transfer(self.sb);
-                    self.sb = received;
-                    self.sc &= 0x7F;
-                    *if_reg |= 0x08;
+                if val & 0x81 == 0x81 {
+                    self.bits_left = 8;
+                    self.bit_timer = self.bit_period();
+                } else {
+                    // Clearing bit 7 aborts a transfer. With the external
+                    // clock selected nothing shifts until a partner clocks us.
+                    self.bits_left = 0;
                 }
             }
             _ => {}
         }
     }
 
+    /// Whether an internal-clock transfer is currently shifting.
+    pub fn transfer_active(&self) -> bool {
+        self.bits_left > 0
+    }
+
+    fn bit_period(&self) -> u16 {
+        if self.cgb && self.sc & 0x02 != 0 {
+            FAST_BIT_CYCLES
+        } else {
+            BIT_CYCLES
+        }
+    }
+
+    /// Advance an in-progress transfer by `cycles` CPU cycles. Once all
+    /// eight bits have shifted SB holds the received byte, SC bit 7 is
+    /// cleared and the serial interrupt is requested.
+    pub fn step(&mut self, cycles: u16, if_reg: &mut u8) {
+        if self.sc & 0x81 == 0x80 {
+            self.step_external(cycles, if_reg);
+            return;
+        }
+        let mut cycles = cycles;
+        while self.bits_left > 0 && cycles > 0 {
+            let run = cycles.min(self.bit_timer);
+            cycles -= run;
+            self.bit_timer -= run;
+            if self.bit_timer == 0 {
+                self.bits_left -= 1;
+                self.bit_timer = self.bit_period();
+                if self.bits_left == 0 {
+                    self.complete(if_reg);
+                }
+            }
+        }
+    }
+
+    /// Wait for the partner to clock a transfer. The port is polled once
+    /// per bit period rather than every step.
+    fn step_external(&mut self, cycles: u16, if_reg: &mut u8) {
+        self.poll_timer = self.poll_timer.saturating_sub(cycles);
+        if self.poll_timer > 0 {
+            return;
+        }
+        self.poll_timer = BIT_CYCLES;
+        if let Some(byte) = self.port.poll(self.sb) {
+            self.out_buf.push(self.sb);
+            self.sb = byte;
+            self.sc &= 0x7F;
+            Interrupts::request(if_reg, InterruptType::Serial);
+        }
+    }
+
+    fn complete(&mut self, if_reg: &mut u8) {
+        self.out_buf.push(self.sb);
+        self.sb = self.port.transfer(self.sb);
+        self.sc &= 0x7F;
+        Interrupts::request(if_reg, InterruptType::Serial);
+    }
+
     pub fn take_output(&mut self) -> Vec<u8> {
         let out = self.out_buf.clone();
         self.out_buf.clear();
@@ -84,3 +220,237 @@ impl Serial {
         &self.out_buf
     }
 }
+
+/// Printer command bytes.
+const PRINTER_INIT: u8 = 0x01;
+const PRINTER_PRINT: u8 = 0x02;
+const PRINTER_DATA: u8 = 0x04;
+const PRINTER_STATUS: u8 = 0x0F;
+
+/// Status bits reported at the end of each packet.
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_UNPRINTED_DATA: u8 = 0x08;
+
+/// Tiles per printed row; the paper is 160 pixels wide.
+const PRINTER_TILES_PER_ROW: usize = 20;
+/// Grey levels for the four printed shades, lightest first.
+const PRINTER_SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// Position within a printer packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacketState {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    /// The printer answers with its device ID...
+    DeviceId,
+    /// ...then with its status.
+    Status,
+}
+
+/// A Game Boy Printer attached to the link port.
+///
+/// Packets are `88 33`, command, compression flag, little-endian data
+/// length, data, a little-endian checksum of everything after the magic
+/// bytes, and two trailing bytes during which the printer answers with
+/// 0x81 and its status. DATA packets append 2bpp tiles to the print
+/// buffer; PRINT renders the buffer into a greyscale image and, when an
+/// output channel is set, sends it there for the frontend to save.
+pub struct Printer {
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    len: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    /// Tile data received since the last INIT or PRINT.
+    buffer: Vec<u8>,
+    image: Option<GrayImage>,
+    output: Option<Sender<GrayImage>>,
+    prints: usize,
+}
+
+impl Printer {
+    /// Create a printer that sends a copy of each print to `output`.
+    pub fn new(output: Option<Sender<GrayImage>>) -> Self {
+        Self {
+            state: PacketState::Magic1,
+            command: 0,
+            compressed: false,
+            len: 0,
+            data: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            buffer: Vec::new(),
+            image: None,
+            output,
+            prints: 0,
+        }
+    }
+
+    /// The most recently printed image.
+    pub fn image(&self) -> Option<&GrayImage> {
+        self.image.as_ref()
+    }
+
+    /// Number of PRINT commands executed.
+    pub fn prints(&self) -> usize {
+        self.prints
+    }
+
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+        self.state = match self.state {
+            PacketState::Magic1 if byte == 0x88 => PacketState::Magic2,
+            PacketState::Magic1 => PacketState::Magic1,
+            PacketState::Magic2 if byte == 0x33 => PacketState::Command,
+            PacketState::Magic2 => PacketState::Magic1,
+            PacketState::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.len = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.len |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.data.clear();
+                if self.len == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.len as usize {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                self.execute();
+                PacketState::DeviceId
+            }
+            PacketState::DeviceId => {
+                reply = 0x81;
+                PacketState::Status
+            }
+            PacketState::Status => {
+                reply = self.status;
+                PacketState::Magic1
+            }
+        };
+        reply
+    }
+
+    fn execute(&mut self) {
+        if self.received_checksum != self.checksum {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+        match self.command {
+            PRINTER_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            }
+            PRINTER_DATA => {
+                let data = std::mem::take(&mut self.data);
+                if self.compressed {
+                    decompress_rle(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                if !self.buffer.is_empty() {
+                    self.status |= STATUS_UNPRINTED_DATA;
+                }
+            }
+            // Printing finishes instantly, so the status never reports busy.
+            PRINTER_PRINT => {
+                let palette = self.data.get(2).copied().unwrap_or(0xE4);
+                self.print(palette);
+                self.status &= !STATUS_UNPRINTED_DATA;
+            }
+            PRINTER_STATUS => {}
+            cmd => log::warn!("Unknown printer command {cmd:02X}"),
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        let image = render_tiles(&std::mem::take(&mut self.buffer), palette);
+        self.prints += 1;
+        // The frontend may have stopped listening; the print is still kept.
+        if let Some(output) = &self.output {
+            let _ = output.send(image.clone());
+        }
+        self.image = Some(image);
+    }
+}
+
+impl LinkPort for Printer {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.receive(byte)
+    }
+}
+
+/// Expand printer RLE data: a control byte with bit 7 set repeats the next
+/// byte `(ctrl & 0x7F) + 2` times; otherwise `ctrl + 1` literal bytes
+/// follow.
+fn decompress_rle(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while let Some(&ctrl) = data.get(i) {
+        if ctrl & 0x80 != 0 {
+            let Some(&byte) = data.get(i + 1) else { break };
+            out.extend(std::iter::repeat_n(byte, (ctrl & 0x7F) as usize + 2));
+            i += 2;
+        } else {
+            let end = (i + 2 + ctrl as usize).min(data.len());
+            out.extend_from_slice(&data[i + 1..end]);
+            i = end;
+        }
+    }
+}
+
+/// Render rows of 20 2bpp tiles into a 160-pixel-wide image, mapping each
+/// colour through `palette` as BGP does.
+fn render_tiles(tiles: &[u8], palette: u8) -> GrayImage {
+    let tile_rows = tiles.len().div_ceil(16 * PRINTER_TILES_PER_ROW);
+    let width = (PRINTER_TILES_PER_ROW * 8) as u32;
+    GrayImage::from_fn(width, (tile_rows * 8) as u32, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let tile = (y / 8) * PRINTER_TILES_PER_ROW + x / 8;
+        let row = tile * 16 + (y % 8) * 2;
+        let lo = tiles.get(row).copied().unwrap_or(0);
+        let hi = tiles.get(row + 1).copied().unwrap_or(0);
+        let bit = 7 - (x % 8);
+        let color = ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1);
+        let shade = (palette >> (color * 2)) & 0x03;
+        Luma([PRINTER_SHADES[shade as usize]])
+    })
+}
@@ -1,3 +1,13 @@
+use crate::interrupts::{InterruptType, Interrupts};
+use serde::{Deserialize, Serialize};
+
+/// Length of the TIMA overflow sequence in T-cycles: one M-cycle reading 0,
+/// then one M-cycle in which TIMA is reloaded from TMA.
+const RELOAD_CYCLES: u8 = 8;
+/// `reload` value at which TIMA is loaded from TMA.
+const RELOAD_AT: u8 = RELOAD_CYCLES / 2;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Timer {
     /// 16-bit internal divider counter. DIV register is the upper 8 bits.
     pub div: u16,
@@ -8,6 +18,8 @@ pub struct Timer {
     /// Timer control
     pub tac: u8,
     last_signal: bool,
+    /// T-cycles left in the overflow sequence, 0 when idle.
+    reload: u8,
 }
 
 impl Timer {
@@ -18,6 +30,7 @@ impl Timer {
             tma: 0,
             tac: 0,
             last_signal: false,
+            reload: 0,
         }
     }
 
@@ -31,25 +44,39 @@ impl Timer {
         }
     }
 
-    pub fn write(&mut self, addr: u16, val: u8, if_reg: &mut u8) {
+    pub fn write(&mut self, addr: u16, val: u8) {
         match addr {
             0xFF04 => {
                 let prev = Self::signal_with(self.div, self.tac);
                 self.div = 0;
                 let new = Self::signal_with(self.div, self.tac);
                 if prev && !new {
-                    self.increment(if_reg);
+                    self.increment();
                 }
                 self.last_signal = new;
             }
-            0xFF05 => self.tima = val,
-            0xFF06 => self.tma = val,
+            // A write in the M-cycle after an overflow cancels the reload;
+            // one in the reload cycle is overwritten by TMA.
+            0xFF05 => match self.reload {
+                0 => self.tima = val,
+                r if r > RELOAD_AT => {
+                    self.tima = val;
+                    self.reload = 0;
+                }
+                _ => {}
+            },
+            0xFF06 => {
+                self.tma = val;
+                if (1..=RELOAD_AT).contains(&self.reload) {
+                    self.tima = val;
+                }
+            }
             0xFF07 => {
                 let prev = Self::signal_with(self.div, self.tac);
                 self.tac = val & 0x07;
                 let new = Self::signal_with(self.div, self.tac);
                 if prev && !new {
-                    self.increment(if_reg);
+                    self.increment();
                 }
                 self.last_signal = new;
             }
@@ -57,63 +84,69 @@ impl Timer {
         }
     }
 
-    /// Advance the timer by `cycles` CPU cycles and update IF when TIMA
-    /// overflows.
+    /// Returns the full 16-bit internal divider.
+    pub fn internal_div(&self) -> u16 {
+        self.div
+    }
+
+    /// Position the internal divider directly, e.g. to line up a test just
+    /// before a falling edge. The edge detector is resynchronized so the
+    /// jump itself never ticks TIMA.
+    pub fn set_internal_div(&mut self, div: u16) {
+        self.div = div;
+        self.last_signal = self.signal();
+    }
+
+    /// Advance the timer by `cycles` CPU cycles. After TIMA overflows it
+    /// reads 0 for one M-cycle, then is reloaded from TMA and raises the
+    /// timer interrupt.
     pub fn step(&mut self, cycles: u16, if_reg: &mut u8) {
         for _ in 0..cycles {
+            if self.reload > 0 {
+                self.reload -= 1;
+                if self.reload == RELOAD_AT {
+                    self.tima = self.tma;
+                    Interrupts::request(if_reg, InterruptType::Timer);
+                }
+            }
             let prev = self.last_signal;
             self.div = self.div.wrapping_add(1);
             let new = self.signal();
             if prev && !new {
-                self.increment(if_reg);
+                self.increment();
             }
             self.last_signal = new;
         }
     }
 
-    fn increment(&mut self, if_reg: &mut u8) {
+    fn increment(&mut self) {
         if self.tima == 0xFF {
-            self.tima = self.tma;
-            *if_reg |= 0x04;
+            self.tima = 0;
+            self.reload = RELOAD_CYCLES;
         } else {
             self.tima = self.tima.wrapping_add(1);
         }
     }
 
-    fn timer_bit(&self) -> u8 {
-        match self.tac & 0x03 {
-            0x00 => ((self.div >> 9) & 1) as u8,
-            0x01 => ((self.div >> 3) & 1) as u8,
-            0x02 => ((self.div >> 5) & 1) as u8,
-            0x03 => ((self.div >> 7) & 1) as u8,
-            _ => 0,
-        }
-    }
-
-    fn timer_bit_with(div: u16, tac: u8) -> u8 {
+    /// Bit of the internal divider whose falling edge increments TIMA for
+    /// the TAC clock select: 4096, 262144, 65536 or 16384 Hz.
+    fn timer_bit(tac: u8) -> u16 {
         match tac & 0x03 {
-            0x00 => ((div >> 9) & 1) as u8,
-            0x01 => ((div >> 3) & 1) as u8,
-            0x02 => ((div >> 5) & 1) as u8,
-            0x03 => ((div >> 7) & 1) as u8,
-            _ => 0,
+            0x00 => 9,
+            0x01 => 3,
+            0x02 => 5,
+            _ => 7,
         }
     }
 
     fn signal(&self) -> bool {
-        if self.tac & 0x04 == 0 {
-            false
-        } else {
-            self.timer_bit() != 0
-        }
+        Self::signal_with(self.div, self.tac)
     }
 
+    /// The timer enable ANDed with the selected divider bit. TIMA ticks on
+    /// its falling edge, including ones caused by writing DIV or TAC.
     fn signal_with(div: u16, tac: u8) -> bool {
-        if tac & 0x04 == 0 {
-            false
-        } else {
-            Self::timer_bit_with(div, tac) != 0
-        }
+        tac & 0x04 != 0 && (div >> Self::timer_bit(tac)) & 1 != 0
     }
 }
 
@@ -0,0 +1,210 @@
+use crate::{cpu::Cpu, mmu::Mmu};
+use std::{fmt, fs, io, path::Path};
+
+/// Number of preceding reference lines printed alongside a divergence.
+const CONTEXT_LINES: usize = 3;
+
+/// CPU state captured before an instruction executes. Fields that are
+/// missing from a reference line are `None` and skipped during comparison.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: Option<u16>,
+    pub opcode: Option<u8>,
+    pub af: Option<u16>,
+    pub bc: Option<u16>,
+    pub de: Option<u16>,
+    pub hl: Option<u16>,
+    pub sp: Option<u16>,
+}
+
+impl TraceEntry {
+    /// Snapshot the current CPU registers and the opcode at PC.
+    pub fn capture(cpu: &Cpu, mmu: &mut Mmu) -> Self {
+        Self {
+            pc: Some(cpu.pc),
+            opcode: Some(mmu.read_byte(cpu.pc)),
+            af: Some(((cpu.a as u16) << 8) | cpu.f as u16),
+            bc: Some(((cpu.b as u16) << 8) | cpu.c as u16),
+            de: Some(((cpu.d as u16) << 8) | cpu.e as u16),
+            hl: Some(cpu.get_hl()),
+            sp: Some(cpu.sp),
+        }
+    }
+
+    /// Parse a reference trace line made of `KEY:VALUE` tokens. Both paired
+    /// registers (`AF:01B0`) and single registers (`A:01 F:B0`) are accepted,
+    /// as is the opcode via `OP:xx` or the first byte of `PCMEM:xx,...`.
+    /// Returns `None` for lines without any recognized field.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut entry = Self::default();
+        let mut hi = [None::<u8>; 4];
+        let mut lo = [None::<u8>; 4];
+        let mut found = false;
+        for token in line.split(|c: char| c.is_whitespace() || c == '|') {
+            let Some((key, value)) = token.split_once(':') else {
+                continue;
+            };
+            let value = value.trim_start_matches("0x").trim_start_matches('$');
+            let byte = || u8::from_str_radix(value, 16).ok();
+            let word = || u16::from_str_radix(value, 16).ok();
+            let parsed = match key.to_ascii_uppercase().as_str() {
+                "PC" => word().map(|v| entry.pc = Some(v)),
+                "SP" => word().map(|v| entry.sp = Some(v)),
+                "AF" => word().map(|v| entry.af = Some(v)),
+                "BC" => word().map(|v| entry.bc = Some(v)),
+                "DE" => word().map(|v| entry.de = Some(v)),
+                "HL" => word().map(|v| entry.hl = Some(v)),
+                "OP" => byte().map(|v| entry.opcode = Some(v)),
+                "PCMEM" => value
+                    .split(',')
+                    .next()
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    .map(|v| entry.opcode = Some(v)),
+                "A" => byte().map(|v| hi[0] = Some(v)),
+                "F" => byte().map(|v| lo[0] = Some(v)),
+                "B" => byte().map(|v| hi[1] = Some(v)),
+                "C" => byte().map(|v| lo[1] = Some(v)),
+                "D" => byte().map(|v| hi[2] = Some(v)),
+                "E" => byte().map(|v| lo[2] = Some(v)),
+                "H" => byte().map(|v| hi[3] = Some(v)),
+                "L" => byte().map(|v| lo[3] = Some(v)),
+                _ => None,
+            };
+            found |= parsed.is_some();
+        }
+        let pairs = [&mut entry.af, &mut entry.bc, &mut entry.de, &mut entry.hl];
+        for (i, pair) in pairs.into_iter().enumerate() {
+            if let (Some(h), Some(l)) = (hi[i], lo[i]) {
+                *pair = Some(((h as u16) << 8) | l as u16);
+            }
+        }
+        found.then_some(entry)
+    }
+
+    /// Returns the names of fields present in `self` that differ in `other`.
+    fn mismatches(&self, other: &Self) -> Vec<&'static str> {
+        let mut out = Vec::new();
+        let mut check = |name, a: Option<u16>, b: Option<u16>| {
+            if let (Some(a), Some(b)) = (a, b)
+                && a != b
+            {
+                out.push(name);
+            }
+        };
+        check("PC", self.pc, other.pc);
+        check(
+            "OP",
+            self.opcode.map(u16::from),
+            other.opcode.map(u16::from),
+        );
+        check("AF", self.af, other.af);
+        check("BC", self.bc, other.bc);
+        check("DE", self.de, other.de);
+        check("HL", self.hl, other.hl);
+        check("SP", self.sp, other.sp);
+        out
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut field = |f: &mut fmt::Formatter<'_>, name: &str, val: Option<u16>, width| {
+            if let Some(v) = val {
+                if !first {
+                    write!(f, " ")?;
+                }
+                first = false;
+                write!(f, "{name}:{v:0width$X}")?;
+            }
+            Ok(())
+        };
+        field(f, "PC", self.pc, 4)?;
+        field(f, "OP", self.opcode.map(u16::from), 2)?;
+        field(f, "AF", self.af, 4)?;
+        field(f, "BC", self.bc, 4)?;
+        field(f, "DE", self.de, 4)?;
+        field(f, "HL", self.hl, 4)?;
+        field(f, "SP", self.sp, 4)
+    }
+}
+
+/// First point where execution diverged from the reference trace.
+#[derive(Debug)]
+pub struct Divergence {
+    /// 1-based line number in the reference file.
+    pub line: usize,
+    pub expected: TraceEntry,
+    pub actual: TraceEntry,
+    pub fields: Vec<&'static str>,
+    /// Reference lines executed just before the divergent one.
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Trace diverged at line {} ({})",
+            self.line,
+            self.fields.join(", ")
+        )?;
+        for line in &self.context {
+            writeln!(f, "    {line}")?;
+        }
+        writeln!(f, "expected: {}", self.expected)?;
+        write!(f, "actual:   {}", self.actual)
+    }
+}
+
+/// Compares executed instructions against a reference trace line by line.
+pub struct TraceComparator {
+    /// Parsed entries paired with their 1-based line number and raw text.
+    entries: Vec<(usize, String, TraceEntry)>,
+    pos: usize,
+}
+
+impl TraceComparator {
+    pub fn from_text(text: &str) -> Self {
+        let entries = text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, l)| TraceEntry::parse(l).map(|e| (i + 1, l.trim().to_string(), e)))
+            .collect();
+        Self { entries, pos: 0 }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_text(&fs::read_to_string(path)?))
+    }
+
+    /// Returns true once every reference line has been matched.
+    pub fn finished(&self) -> bool {
+        self.pos >= self.entries.len()
+    }
+
+    /// Compare the state about to execute against the next reference line.
+    pub fn check(&mut self, cpu: &Cpu, mmu: &mut Mmu) -> Result<(), Box<Divergence>> {
+        if self.finished() {
+            return Ok(());
+        }
+        let actual = TraceEntry::capture(cpu, mmu);
+        let (line, _, expected) = &self.entries[self.pos];
+        let fields = expected.mismatches(&actual);
+        if fields.is_empty() {
+            self.pos += 1;
+            return Ok(());
+        }
+        let start = self.pos.saturating_sub(CONTEXT_LINES);
+        Err(Box::new(Divergence {
+            line: *line,
+            expected: *expected,
+            actual,
+            fields,
+            context: self.entries[start..self.pos]
+                .iter()
+                .map(|(n, text, _)| format!("{n}: {text}"))
+                .collect(),
+        }))
+    }
+}
@@ -0,0 +1,36 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::{fs::File, io::BufWriter, path::Path};
+
+/// Writes interleaved stereo samples from the APU to a 16-bit WAV file.
+pub struct WavRecorder {
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl WavRecorder {
+    /// Create `path` for stereo audio at `sample_rate` Hz.
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> hound::Result<Self> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        Ok(Self {
+            writer: WavWriter::create(path, spec)?,
+        })
+    }
+
+    /// Append interleaved left/right samples.
+    pub fn write(&mut self, samples: &[i16]) -> hound::Result<()> {
+        for &s in samples {
+            self.writer.write_sample(s)?;
+        }
+        Ok(())
+    }
+
+    /// Fill in the header lengths and close the file. Dropping the recorder
+    /// also finalizes it, but ignores errors.
+    pub fn finish(self) -> hound::Result<()> {
+        self.writer.finalize()
+    }
+}
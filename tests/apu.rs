@@ -1,4 +1,4 @@
-use vibeEmu::apu::Apu;
+use vibeEmu::apu::{Apu, CGB_WAVE_RAM, DMG_WAVE_RAM, SCOPE_LEN};
 
 #[test]
 fn frame_sequencer_tick() {
@@ -88,11 +88,516 @@ fn sweep_trigger_and_step() {
     // set frequency 0x200
     apu.write_reg(0xFF13, 0x00);
     apu.write_reg(0xFF14, 0x82); // high bits=2, trigger
-    // immediately applied sweep -> freq should be 0x300
-    assert_eq!(apu.ch1_frequency(), 0x300);
+    // the trigger only checks for overflow; the frequency is unchanged
+    assert_eq!(apu.ch1_frequency(), 0x200);
     // advance until the sequencer clocks sweep (step 2)
     apu.step(8192); // advance to step 1
     apu.step(8192); // advance to step 2
     apu.step(8192); // advance to step 3 (sweep clocked on previous step)
-    assert_eq!(apu.ch1_frequency(), 0x480);
+    assert_eq!(apu.ch1_frequency(), 0x300);
+}
+
+#[test]
+fn sweep_overflow_on_trigger_disables_channel() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF12, 0xF0); // DAC on
+    apu.write_reg(0xFF10, 0x71); // period=7, shift=1
+    apu.write_reg(0xFF13, 0x00);
+    apu.write_reg(0xFF14, 0x87); // 0x700 + 0x380 overflows
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x00);
+    assert_eq!(apu.ch1_frequency(), 0x700);
+
+    // Without a shift there is no calculation and no overflow.
+    apu.write_reg(0xFF10, 0x70);
+    apu.write_reg(0xFF14, 0x87);
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x01);
+
+    // A result of exactly 2047 does not overflow.
+    apu.write_reg(0xFF10, 0x71);
+    apu.write_reg(0xFF13, 0x55);
+    apu.write_reg(0xFF14, 0x85); // 0x555 + 0x2AA = 0x7FF
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x01);
+}
+
+#[test]
+fn sweep_negate_exit_disables_channel() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF26, 0x80); // master enable
+    apu.write_reg(0xFF12, 0xF0); // DAC on
+    apu.write_reg(0xFF13, 0x00);
+
+    // No negated calculation yet: leaving negate mode is harmless.
+    apu.write_reg(0xFF10, 0x18); // period=1, negate, shift=0
+    apu.write_reg(0xFF14, 0x84); // trigger
+    apu.write_reg(0xFF10, 0x10);
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x01);
+
+    // The trigger calculates in negate mode; clearing negate disables ch1.
+    apu.write_reg(0xFF10, 0x19); // period=1, negate, shift=1
+    apu.write_reg(0xFF14, 0x84);
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x01);
+    apu.write_reg(0xFF10, 0x11);
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x00);
+
+    // A new trigger forgets the earlier calculation.
+    apu.write_reg(0xFF14, 0x84);
+    apu.write_reg(0xFF10, 0x10);
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x01);
+}
+
+#[test]
+fn trigger_with_dac_off_stays_disabled() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF26, 0x80); // enable
+    apu.write_reg(0xFF12, 0x00); // DAC off
+    apu.write_reg(0xFF14, 0x80); // trigger channel 1
+    assert_eq!(apu.read_reg(0xFF26) & 0x01, 0x00);
+
+    apu.write_reg(0xFF1A, 0x00); // wave DAC off
+    apu.write_reg(0xFF1E, 0x80); // trigger channel 3
+    assert_eq!(apu.read_reg(0xFF26) & 0x04, 0x00);
+
+    apu.write_reg(0xFF21, 0x00); // noise DAC off
+    apu.write_reg(0xFF23, 0x80); // trigger channel 4
+    assert_eq!(apu.read_reg(0xFF26) & 0x08, 0x00);
+}
+
+#[test]
+fn paused_apu_outputs_silence() {
+    let mut apu = Apu::new();
+    apu.step(1000);
+    let buffered = apu.buffered_samples();
+    assert!(buffered > 0);
+
+    apu.set_paused(true);
+    apu.step(1000);
+    assert_eq!(apu.buffered_samples(), buffered);
+    assert_eq!(apu.pop_sample(), Some(0));
+    assert_eq!(apu.buffered_samples(), buffered);
+
+    apu.set_paused(false);
+    apu.step(1000);
+    assert!(apu.buffered_samples() > buffered);
+}
+
+/// Registers needed to drive one channel's length counter in the
+/// extra-length-clock tests: (DAC register, DAC-on value, NRx1, NRx4,
+/// NR52 status bit, full length).
+const LENGTH_CHANNELS: [(u16, u8, u16, u16, u8, u32); 4] = [
+    (0xFF12, 0xF0, 0xFF11, 0xFF14, 0x01, 64),
+    (0xFF17, 0xF0, 0xFF16, 0xFF19, 0x02, 64),
+    (0xFF1A, 0x80, 0xFF1B, 0xFF1E, 0x04, 256),
+    (0xFF21, 0xF0, 0xFF20, 0xFF23, 0x08, 64),
+];
+
+/// Write NRx1 so the channel's length counter holds `remaining`.
+fn set_length(apu: &mut Apu, nrx1: u16, full: u32, remaining: u32) {
+    apu.write_reg(nrx1, (full - remaining) as u8);
+}
+
+#[test]
+fn length_enable_extra_clock_on_odd_step() {
+    for (dac, dac_on, nrx1, nrx4, bit, full) in LENGTH_CHANNELS {
+        // Next step clocks length: enabling it does not decrement.
+        let mut apu = Apu::new();
+        apu.write_reg(dac, dac_on);
+        set_length(&mut apu, nrx1, full, 1);
+        apu.write_reg(nrx4, 0x80);
+        assert_eq!(apu.sequencer_step() & 1, 0);
+        apu.write_reg(nrx4, 0x40);
+        assert_ne!(apu.read_reg(0xFF26) & bit, 0, "NR52 bit {bit:02X}");
+
+        // Next step skips length: enabling it clocks once immediately.
+        let mut apu = Apu::new();
+        apu.step(8192);
+        assert_eq!(apu.sequencer_step() & 1, 1);
+        apu.write_reg(dac, dac_on);
+        set_length(&mut apu, nrx1, full, 1);
+        apu.write_reg(nrx4, 0x80);
+        assert_ne!(apu.read_reg(0xFF26) & bit, 0, "NR52 bit {bit:02X}");
+        apu.write_reg(nrx4, 0x40);
+        assert_eq!(apu.read_reg(0xFF26) & bit, 0, "NR52 bit {bit:02X}");
+
+        // Enabling length and triggering together keeps the channel on and
+        // reloads the now-empty counter.
+        apu.write_reg(nrx4, 0x80);
+        set_length(&mut apu, nrx1, full, 1);
+        apu.write_reg(nrx4, 0x00);
+        apu.write_reg(nrx4, 0xC0);
+        assert_ne!(apu.read_reg(0xFF26) & bit, 0, "NR52 bit {bit:02X}");
+    }
+}
+
+#[test]
+fn trigger_reload_on_odd_step_loads_one_less() {
+    for (dac, dac_on, nrx1, nrx4, bit, full) in LENGTH_CHANNELS {
+        let mut apu = Apu::new();
+        apu.step(8192);
+        apu.write_reg(dac, dac_on);
+        set_length(&mut apu, nrx1, full, 1);
+        apu.write_reg(nrx4, 0x40); // clocks the counter down to 0
+        apu.write_reg(nrx4, 0xC0); // reload to full - 1
+
+        // Length is clocked every 16384 cycles from here on.
+        for _ in 0..full - 2 {
+            apu.step(16384);
+        }
+        assert_ne!(apu.read_reg(0xFF26) & bit, 0, "NR52 bit {bit:02X}");
+        apu.step(16384);
+        assert_eq!(apu.read_reg(0xFF26) & bit, 0, "NR52 bit {bit:02X}");
+    }
+}
+
+/// Collect (left, right) samples with only `channel` (1-4) audible, either
+/// playing or with its DAC off, routed according to `nr51`.
+fn routed_samples(channel: u8, nr51: u8, playing: bool) -> Vec<(i16, i16)> {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF24, 0x77);
+    apu.write_reg(0xFF25, nr51);
+    let dac = if playing { 0xF0 } else { 0x00 };
+    match channel {
+        1 => {
+            apu.write_reg(0xFF11, 0x80);
+            apu.write_reg(0xFF12, dac);
+            apu.write_reg(0xFF13, 0x00);
+            apu.write_reg(0xFF14, 0x87);
+        }
+        2 => {
+            apu.write_reg(0xFF16, 0x80);
+            apu.write_reg(0xFF17, dac);
+            apu.write_reg(0xFF18, 0x00);
+            apu.write_reg(0xFF19, 0x87);
+        }
+        3 => {
+            for addr in 0xFF30..=0xFF3F {
+                apu.write_reg(addr, 0xF0);
+            }
+            apu.write_reg(0xFF1A, dac & 0x80);
+            apu.write_reg(0xFF1C, 0x20);
+            apu.write_reg(0xFF1D, 0x00);
+            apu.write_reg(0xFF1E, 0x87);
+        }
+        _ => {
+            apu.write_reg(0xFF21, dac);
+            apu.write_reg(0xFF22, 0x00);
+            apu.write_reg(0xFF23, 0x80);
+        }
+    }
+    for _ in 0..200 {
+        apu.step(100);
+    }
+    let mut out = Vec::new();
+    while let (Some(l), Some(r)) = (apu.pop_sample(), apu.pop_sample()) {
+        out.push((l, r));
+    }
+    out
+}
+
+#[test]
+fn nr51_routing_matrix() {
+    // (channel, NR51, expected (left, right) routing)
+    let cases = [
+        (1, 0x01, (false, true)),
+        (1, 0x10, (true, false)),
+        (2, 0x02, (false, true)),
+        (2, 0x20, (true, false)),
+        (3, 0x04, (false, true)),
+        (3, 0x40, (true, false)),
+        (4, 0x08, (false, true)),
+        (4, 0x80, (true, false)),
+        (1, 0x11, (true, true)),
+        (3, 0x44, (true, true)),
+        (2, 0x00, (false, false)),
+    ];
+    for (channel, nr51, (left, right)) in cases {
+        let playing = routed_samples(channel, nr51, true);
+        let silent = routed_samples(channel, nr51, false);
+        let sides = [("left", left, 0), ("right", right, 1)];
+        for (side, routed, i) in sides {
+            let get =
+                |s: &[(i16, i16)]| -> Vec<i16> { s.iter().map(|&(l, r)| [l, r][i]).collect() };
+            if routed {
+                assert_ne!(
+                    get(&playing),
+                    get(&silent),
+                    "ch{channel} not routed {side} with NR51={nr51:02X}"
+                );
+            } else {
+                assert!(
+                    get(&playing).iter().all(|&v| v == 0),
+                    "ch{channel} leaked {side} with NR51={nr51:02X}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn wave_length_256_steps() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF1A, 0x80); // DAC on
+    apu.write_reg(0xFF1B, 0x00); // length 256
+    apu.write_reg(0xFF1E, 0xC0); // trigger with length enabled
+    assert_ne!(apu.read_reg(0xFF26) & 0x04, 0);
+
+    // Length is clocked every 16384 cycles.
+    for _ in 0..255 {
+        apu.step(16384);
+    }
+    assert_ne!(apu.read_reg(0xFF26) & 0x04, 0);
+    apu.step(16384);
+    assert_eq!(apu.read_reg(0xFF26) & 0x04, 0);
+}
+
+#[test]
+fn square_scope_shows_duty_pattern() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF11, 0x80); // 50% duty
+    apu.write_reg(0xFF12, 0xF0); // volume 15, no envelope
+    apu.write_reg(0xFF13, 0x00);
+    apu.write_reg(0xFF14, 0x87); // frequency 0x700, trigger
+    for _ in 0..400 {
+        apu.step(100);
+    }
+
+    let scope = apu.channel_scope(1);
+    assert!(!scope.is_empty() && scope.len() <= SCOPE_LEN);
+    assert!(scope.iter().all(|&v| v == 0 || v == 15));
+    // 50% duty: about half of the samples are high.
+    let high = scope.iter().filter(|&&v| v == 15).count();
+    let ratio = high as f32 / scope.len() as f32;
+    assert!((0.4..=0.6).contains(&ratio), "high ratio {ratio}");
+    // Channel 2 was never triggered.
+    assert!(apu.channel_scope(2).iter().all(|&v| v == 0));
+    assert!(apu.channel_scope(0).is_empty());
+}
+
+#[test]
+fn nr52_status_bits_read_only() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF26, 0x00);
+    apu.write_reg(0xFF26, 0x80);
+    assert_eq!(apu.read_reg(0xFF26), 0xF0);
+
+    // Only bit 7 is writable; the channel status bits stay clear.
+    apu.write_reg(0xFF26, 0x8F);
+    assert_eq!(apu.read_reg(0xFF26), 0xF0);
+
+    // Without bit 7 the write powers the APU off and still enables nothing.
+    apu.write_reg(0xFF26, 0x0F);
+    assert_eq!(apu.read_reg(0xFF26), 0x70);
+}
+
+/// Left samples from one second of an APU whose channels are all silent,
+/// which leaves a constant DC offset in the mix.
+fn dc_samples(high_pass: bool) -> Vec<i16> {
+    let mut apu = Apu::new();
+    apu.set_high_pass(high_pass);
+    apu.write_reg(0xFF12, 0x00);
+    apu.write_reg(0xFF24, 0x77);
+    apu.write_reg(0xFF25, 0xFF);
+    let mut out = Vec::new();
+    for _ in 0..4_194_304 / 4096 {
+        apu.step(4096);
+        while let (Some(l), Some(_)) = (apu.pop_sample(), apu.pop_sample()) {
+            out.push(l);
+        }
+    }
+    out
+}
+
+#[test]
+fn high_pass_removes_dc_offset() {
+    let raw = dc_samples(false);
+    assert!(raw[0] != 0 && raw.iter().all(|&s| s == raw[0]));
+
+    let filtered = dc_samples(true);
+    assert_eq!(filtered[0], raw[0]);
+    assert!(filtered.windows(2).all(|w| w[1].abs() <= w[0].abs()));
+    assert_eq!(*filtered.last().unwrap(), 0);
+}
+
+#[test]
+fn muted_channel_is_left_out_of_the_mix() {
+    let mut apu = Apu::new();
+    apu.set_high_pass(false);
+    apu.set_channel_enabled(1, false);
+    assert!(!apu.channel_enabled(1));
+    assert!(apu.channel_enabled(2));
+    assert!(!apu.channel_enabled(5));
+    apu.write_reg(0xFF24, 0x77);
+    apu.write_reg(0xFF25, 0x21); // ch1 right, ch2 left
+    for (nrx1, nrx2, nrx3, nrx4) in [
+        (0xFF11, 0xFF12, 0xFF13, 0xFF14),
+        (0xFF16, 0xFF17, 0xFF18, 0xFF19),
+    ] {
+        apu.write_reg(nrx1, 0x80);
+        apu.write_reg(nrx2, 0xF0);
+        apu.write_reg(nrx3, 0x00);
+        apu.write_reg(nrx4, 0x87);
+    }
+    for _ in 0..200 {
+        apu.step(100);
+    }
+    // Channel 1 still runs, it is just not heard.
+    assert_eq!(apu.read_reg(0xFF26) & 0x03, 0x03);
+    let mut left = Vec::new();
+    while let (Some(l), Some(r)) = (apu.pop_sample(), apu.pop_sample()) {
+        assert_eq!(r, 0);
+        left.push(l);
+    }
+    assert!(left.iter().any(|&l| l != left[0]));
+}
+
+#[test]
+fn power_on_wave_ram_depends_on_model() {
+    let wave_ram = |apu: &Apu| -> Vec<u8> { (0xFF30..=0xFF3F).map(|a| apu.read_reg(a)).collect() };
+    let dmg = Apu::new_with_mode(false);
+    let cgb = Apu::new_with_mode(true);
+    assert_eq!(wave_ram(&dmg), DMG_WAVE_RAM);
+    assert_eq!(wave_ram(&cgb), CGB_WAVE_RAM);
+    assert_eq!(wave_ram(&Apu::default()), DMG_WAVE_RAM);
+
+    let mut apu = Apu::new_with_mode(true);
+    apu.write_reg(0xFF30, 0x12);
+    apu.reset();
+    assert_eq!(wave_ram(&apu), CGB_WAVE_RAM);
+}
+
+/// Fill wave RAM with 0x00, 0x11, ... 0xFF and start channel 3 with a
+/// 32-cycle sample period.
+fn play_wave(cgb: bool) -> Apu {
+    let mut apu = Apu::new_with_mode(cgb);
+    for i in 0..0x10 {
+        apu.write_reg(0xFF30 + i, i as u8 * 0x11);
+    }
+    apu.write_reg(0xFF1A, 0x80);
+    apu.write_reg(0xFF1C, 0x20);
+    apu.write_reg(0xFF1D, 0xF0); // frequency 0x7F0
+    apu.write_reg(0xFF1E, 0x87);
+    apu
+}
+
+#[test]
+fn dmg_wave_ram_only_reachable_when_fetched() {
+    let mut apu = play_wave(false);
+    assert_eq!(apu.read_reg(0xFF30), 0xFF);
+
+    // The first fetch reads byte 0; any address reaches it in that cycle.
+    apu.step(32);
+    assert_eq!(apu.read_reg(0xFF35), 0x00);
+    apu.step(2);
+    assert_eq!(apu.read_reg(0xFF35), 0xFF);
+
+    // Writes land in the fetched byte only.
+    apu.step(30);
+    apu.write_reg(0xFF3F, 0xAB);
+    apu.step(2);
+    apu.write_reg(0xFF3F, 0xCD);
+
+    apu.write_reg(0xFF1A, 0x00);
+    assert_eq!(apu.read_reg(0xFF31), 0xAB);
+    assert_eq!(apu.read_reg(0xFF3F), 0xFF);
+}
+
+#[test]
+fn cgb_wave_ram_reaches_playing_byte() {
+    let mut apu = play_wave(true);
+    for _ in 0..6 {
+        apu.step(32);
+    }
+    apu.step(10);
+    // Position 6 is in byte 3.
+    assert_eq!(apu.read_reg(0xFF30), 0x33);
+    apu.write_reg(0xFF3A, 0x99);
+    apu.write_reg(0xFF1A, 0x00);
+    assert_eq!(apu.read_reg(0xFF33), 0x99);
+    assert_eq!(apu.read_reg(0xFF3A), 0xAA);
+}
+
+#[test]
+fn dmg_retrigger_while_fetching_corrupts_wave_ram() {
+    let wave_after_retrigger = |cgb: bool| -> Vec<u8> {
+        let mut apu = play_wave(cgb);
+        for _ in 0..8 {
+            apu.step(32);
+        }
+        // Two cycles before fetching position 9, in byte 4.
+        apu.step(30);
+        apu.write_reg(0xFF1E, 0x87);
+        apu.write_reg(0xFF1A, 0x00);
+        (0xFF30..=0xFF3F).map(|a| apu.read_reg(a)).collect()
+    };
+
+    let dmg = wave_after_retrigger(false);
+    assert_eq!(dmg[..4], [0x44, 0x55, 0x66, 0x77]);
+    assert_eq!(dmg[4..8], [0x44, 0x55, 0x66, 0x77]);
+    assert_eq!(dmg[8], 0x88);
+
+    let cgb = wave_after_retrigger(true);
+    let untouched: Vec<u8> = (0..0x10).map(|i| i * 0x11).collect();
+    assert_eq!(cgb, untouched);
+}
+
+/// Cycles until the channel turns off after being triggered with length
+/// enabled and `remaining` length clocks, measured in 2048-cycle slices.
+fn length_lifetime(odd_step: bool, remaining: u32) -> Vec<u32> {
+    LENGTH_CHANNELS
+        .iter()
+        .map(|&(dac, dac_on, nrx1, nrx4, bit, full)| {
+            let mut apu = Apu::new();
+            if odd_step {
+                apu.step(8192);
+            }
+            apu.write_reg(dac, dac_on);
+            set_length(&mut apu, nrx1, full, remaining);
+            apu.write_reg(nrx4, 0xC0);
+            let mut cycles = 0;
+            while apu.read_reg(0xFF26) & bit != 0 && cycles < 1 << 23 {
+                apu.step(2048);
+                cycles += 2048;
+            }
+            cycles
+        })
+        .collect()
+}
+
+#[test]
+fn length_counter_sustains_exact_clocks() {
+    // Length is clocked every other 8192-cycle step. Triggered before a
+    // clocking step, four clocks end the note after 8192 + 3 * 16384 cycles.
+    assert_eq!(length_lifetime(false, 4), vec![57_344; 4]);
+    // Triggered before a skipped step, enabling length clocks once at once,
+    // leaving three clocks at 16384-cycle intervals.
+    assert_eq!(length_lifetime(true, 4), vec![49_152; 4]);
+    // Zero length reloads to the maximum; on an odd step one less.
+    assert_eq!(
+        length_lifetime(true, 0),
+        vec![63 * 16_384, 63 * 16_384, 255 * 16_384, 63 * 16_384]
+    );
+}
+
+#[test]
+fn sample_rate_is_exact_over_one_second() {
+    for rate in [48_000, 44_100, 32_768] {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(rate);
+        assert_eq!(apu.sample_rate(), rate);
+        let mut frames = 0;
+        for _ in 0..4_194_304 / 4 {
+            apu.step(4);
+            frames += apu.drain_samples().len() / 2;
+        }
+        assert!(frames.abs_diff(rate as usize) <= 1, "{frames} at {rate} Hz");
+    }
+}
+
+#[test]
+fn unconsumed_samples_are_capped_at_one_second() {
+    let mut apu = Apu::new();
+    apu.set_sample_rate(8_000);
+    for _ in 0..2 * 4_194_304 / 4 {
+        apu.step(4);
+    }
+    assert_eq!(apu.buffered_samples(), 2 * 8_000);
 }
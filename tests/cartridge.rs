@@ -1,6 +1,11 @@
 use std::fs;
 use tempfile::tempdir;
-use vibeEmu::cartridge::{Cartridge, MbcType};
+use vibeEmu::{
+    cartridge::{Cartridge, CartridgeHeader, MbcType, NINTENDO_LOGO},
+    huc3::HUC3_SAVE_LEN,
+    mmu::Mmu,
+    rtc::{RTC_CYCLES_PER_SECOND, RTC_SAVE_LEN, Rtc},
+};
 
 #[test]
 fn battery_ram_saved_to_disk() {
@@ -30,3 +35,492 @@ fn mbc30_header_detection() {
     let cart = Cartridge::load(rom);
     assert_eq!(cart.mbc, MbcType::Mbc30);
 }
+
+#[test]
+fn pocket_camera_banking_and_registers() {
+    let mut rom = vec![0u8; 64 * 0x4000];
+    rom[0x0147] = 0xFC; // Pocket Camera
+    rom[0x0149] = 0x04; // 128KB RAM
+    for i in 0..64 {
+        rom[i * 0x4000 + 1] = i as u8;
+    }
+    let mut cart = Cartridge::load(rom);
+    assert_eq!(cart.mbc, MbcType::PocketCamera);
+    assert_eq!(cart.ram.len(), 0x20000);
+
+    // ROM banking behaves like MBC3
+    assert_eq!(cart.read(0x4001), 1);
+    cart.write(0x2000, 0x21);
+    assert_eq!(cart.read(0x4001), 0x21);
+    assert_eq!(cart.read(0x0001), 0);
+    // Bank 0 maps to bank 1 at 0x4000-0x7FFF.
+    cart.write(0x2000, 0x00);
+    assert_eq!(cart.read(0x4001), 1);
+
+    // Camera RAM bank 2
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, 0x02);
+    cart.write(0xA000, 0x5A);
+    assert_eq!(cart.read(0xA000), 0x5A);
+    assert_eq!(cart.ram[2 * 0x2000], 0x5A);
+
+    // Camera registers
+    cart.write(0x4000, 0x10);
+    cart.write(0xA001, 0x33);
+    assert_eq!(cart.read(0xA001), 0x00); // write-only
+    cart.write(0xA000, 0x04);
+    assert_eq!(cart.read(0xA000), 0x04);
+    cart.write(0xA000, 0x01); // start capture
+    assert_eq!(cart.read(0xA000) & 0x01, 0x00);
+    assert_ne!(cart.ram[0x0100..0x0100 + 0xE00], [0u8; 0xE00]);
+
+    // RAM bank 2 is untouched by register writes
+    cart.write(0x4000, 0x02);
+    assert_eq!(cart.read(0xA000), 0x5A);
+}
+
+#[test]
+fn ram_enable_requires_low_nibble_a() {
+    for cart_type in [0x02, 0x12, 0x1A] {
+        // MBC1, MBC3 and MBC5, each with 8KB RAM
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0147] = cart_type;
+        rom[0x0149] = 0x02;
+        let mut cart = Cartridge::load(rom);
+
+        cart.write(0x0000, 0x0A);
+        cart.write(0xA000, 0x42);
+        assert_eq!(cart.read(0xA000), 0x42, "type {cart_type:02X}");
+
+        for val in [0x1A, 0x2A, 0xFA] {
+            cart.write(0x0000, 0x00);
+            cart.write(0x0000, val);
+            assert_eq!(cart.read(0xA000), 0x42, "type {cart_type:02X} {val:02X}");
+        }
+
+        for val in [0x0B, 0x00] {
+            cart.write(0x0000, 0x0A);
+            cart.write(0x0000, val);
+            assert_eq!(cart.read(0xA000), 0xFF, "type {cart_type:02X} {val:02X}");
+            cart.write(0xA000, 0x99);
+        }
+
+        // Writes while disabled were dropped.
+        cart.write(0x0000, 0x0A);
+        assert_eq!(cart.read(0xA000), 0x42, "type {cart_type:02X}");
+    }
+}
+
+#[test]
+fn header_ram_size_codes() {
+    let expected = [
+        (0x00, 0),
+        (0x01, 0x800),
+        (0x02, 0x2000),
+        (0x03, 0x8000),
+        (0x04, 0x20000),
+        (0x05, 0x10000),
+        (0x06, 0x2000), // reserved, falls back to 8KB
+    ];
+    for (code, size) in expected {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0147] = 0x1B; // MBC5 + RAM + Battery
+        rom[0x0149] = code;
+        let cart = Cartridge::load(rom);
+        assert_eq!(cart.ram.len(), size, "RAM size code {code:02X}");
+    }
+}
+
+#[test]
+fn logo_verification() {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+    assert!(Cartridge::load(rom.clone()).logo_matches());
+
+    rom[0x0120] ^= 0x01;
+    assert!(!Cartridge::load(rom).logo_matches());
+
+    assert!(!Cartridge::load(vec![0u8; 0x100]).logo_matches());
+}
+
+#[test]
+fn unsupported_mbc_warning() {
+    let header = |cart_type: u8| {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0147] = cart_type;
+        Cartridge::load(rom)
+    };
+
+    let warning = header(0x20).mbc_warning().expect("MBC6 should warn");
+    assert!(warning.contains("MBC6"), "{warning}");
+    assert!(header(0x42).mbc_warning().unwrap().contains("42"));
+    let warning = header(0xFE).mbc_warning().expect("HuC3 should warn");
+    assert!(warning.contains("partial: no infrared link"), "{warning}");
+
+    for fully_supported in [0x00, 0x03, 0x10, 0x13, 0x1B, 0x1C, 0x22] {
+        assert_eq!(header(fully_supported).mbc_warning(), None);
+    }
+}
+
+fn banked_rom(banks: usize, cart_type: u8) -> Vec<u8> {
+    let mut rom = vec![0u8; banks * 0x4000];
+    for bank in 0..banks {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    rom[0x0147] = cart_type;
+    rom[0x0148] = (banks / 2).trailing_zeros() as u8;
+    rom
+}
+
+#[test]
+fn detect_mbc_cross_checks_rom_size() {
+    // A ROM-only header on a 128 KB ROM needs a mapper.
+    assert_eq!(
+        Cartridge::load(banked_rom(8, 0x00)).detect_mbc(),
+        MbcType::Mbc1
+    );
+    // MBC1 cannot address 4 MB.
+    assert_eq!(
+        Cartridge::load(banked_rom(256, 0x01)).detect_mbc(),
+        MbcType::Mbc5
+    );
+    assert_eq!(
+        Cartridge::load(banked_rom(8, 0x01)).detect_mbc(),
+        MbcType::Mbc1
+    );
+    assert_eq!(
+        Cartridge::load(banked_rom(2, 0x00)).detect_mbc(),
+        MbcType::NoMbc
+    );
+}
+
+#[test]
+fn force_mbc5_overrides_mbc1_header() {
+    let mut cart = Cartridge::load(banked_rom(4, 0x01));
+    assert_eq!(cart.mbc, MbcType::Mbc1);
+    cart.force_mbc("mbc5".parse().unwrap());
+    assert_eq!(cart.mbc, MbcType::Mbc5);
+
+    let mut mmu = Mmu::new();
+    mmu.load_cart(cart);
+    // MBC5 can map bank 0 into the switchable area; MBC1 would give bank 1.
+    mmu.write_byte(0x2000, 0x00);
+    assert_eq!(mmu.read_byte(0x4000), 0);
+    mmu.write_byte(0x2000, 0x03);
+    assert_eq!(mmu.read_byte(0x4000), 3);
+
+    assert!("mbc9".parse::<MbcType>().is_err());
+}
+
+/// MBC3+TIMER+RAM+BATTERY cartridge with the RTC register `reg` selected.
+fn rtc_cart(reg: u8) -> Cartridge {
+    let mut rom = banked_rom(2, 0x10);
+    rom[0x0149] = 0x02;
+    let mut cart = Cartridge::load(rom);
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, reg);
+    cart
+}
+
+fn latch(cart: &mut Cartridge) {
+    cart.write(0x6000, 0x00);
+    cart.write(0x6000, 0x01);
+}
+
+#[test]
+fn rtc_ticks_and_latches() {
+    let mut cart = rtc_cart(0x08);
+    cart.tick_rtc(RTC_CYCLES_PER_SECOND * 61);
+    // Reads come from the latch, which has not been updated yet.
+    assert_eq!(cart.read(0xA000), 0);
+
+    latch(&mut cart);
+    assert_eq!(cart.read(0xA000), 1);
+    cart.write(0x4000, 0x09);
+    assert_eq!(cart.read(0xA000), 1);
+
+    // Partial seconds accumulate across ticks.
+    cart.tick_rtc(RTC_CYCLES_PER_SECOND / 2);
+    cart.tick_rtc(RTC_CYCLES_PER_SECOND / 2);
+    assert_eq!(cart.rtc().unwrap().registers()[0], 2);
+
+    // RAM banks are still reachable.
+    cart.write(0x4000, 0x00);
+    cart.write(0xA000, 0x5A);
+    assert_eq!(cart.read(0xA000), 0x5A);
+}
+
+#[test]
+fn rtc_halt_freezes_clock() {
+    let mut cart = rtc_cart(0x0C);
+    cart.write(0xA000, 0x40);
+    cart.tick_rtc(RTC_CYCLES_PER_SECOND * 10);
+    assert_eq!(cart.rtc().unwrap().registers()[0], 0);
+
+    cart.write(0xA000, 0x00);
+    cart.tick_rtc(RTC_CYCLES_PER_SECOND * 10);
+    assert_eq!(cart.rtc().unwrap().registers()[0], 10);
+}
+
+#[test]
+fn rtc_day_counter_carry() {
+    let mut cart = rtc_cart(0x08);
+    for (reg, val) in [
+        (0x08, 59),
+        (0x09, 59),
+        (0x0A, 23),
+        (0x0B, 0xFF),
+        (0x0C, 0x01),
+    ] {
+        cart.write(0x4000, reg);
+        cart.write(0xA000, val);
+    }
+    cart.tick_rtc(RTC_CYCLES_PER_SECOND);
+    latch(&mut cart);
+
+    assert_eq!(cart.rtc().unwrap().day(), 0);
+    cart.write(0x4000, 0x0C);
+    assert_eq!(cart.read(0xA000), 0x80);
+    cart.write(0x4000, 0x0A);
+    assert_eq!(cart.read(0xA000), 0);
+}
+
+#[test]
+fn rtc_saved_and_advanced_on_reload() {
+    let dir = tempdir().unwrap();
+    let rom_path = dir.path().join("clock.gb");
+    let mut rom = banked_rom(2, 0x10);
+    rom[0x0149] = 0x02;
+    fs::write(&rom_path, &rom).unwrap();
+
+    let mut cart = Cartridge::from_file(&rom_path).unwrap();
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, 0x0A);
+    cart.write(0xA000, 5); // hours
+    cart.save_ram().unwrap();
+
+    let rtc_path = rom_path.with_extension("rtc");
+    let mut data = fs::read(&rtc_path).unwrap();
+    assert_eq!(data.len(), RTC_SAVE_LEN);
+    assert_eq!(data[8..12], [5, 0, 0, 0]);
+
+    // Pretend the save was made three days ago.
+    let saved = u64::from_le_bytes(data[40..48].try_into().unwrap());
+    data[40..48].copy_from_slice(&(saved - 3 * 86_400).to_le_bytes());
+    fs::write(&rtc_path, &data).unwrap();
+
+    let cart = Cartridge::from_file(&rom_path).unwrap();
+    let rtc = cart.rtc().unwrap();
+    assert_eq!(rtc.day(), 3);
+    assert_eq!(rtc.registers()[2], 5);
+}
+
+#[test]
+fn huc3_clock_saved_and_advanced_on_reload() {
+    let dir = tempdir().unwrap();
+    let rom_path = dir.path().join("huc3.gb");
+    let mut rom = banked_rom(2, 0xFE);
+    rom[0x0149] = 0x02;
+    fs::write(&rom_path, &rom).unwrap();
+
+    let command = |mmu: &mut Mmu, cmd: u8| {
+        mmu.write_byte(0x0000, 0x0B);
+        mmu.write_byte(0xA000, cmd);
+        mmu.write_byte(0x0000, 0x0D);
+        mmu.write_byte(0xA000, 0xFE);
+        mmu.write_byte(0x0000, 0x0C);
+        mmu.read_byte(0xA000)
+    };
+
+    // Set the clock to day 1, minute 0x010.
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::from_file(&rom_path).unwrap());
+    command(&mut mmu, 0x40);
+    command(&mut mmu, 0x50);
+    for nibble in [0x0, 0x1, 0x0, 0x1, 0x0, 0x0, 0x0] {
+        command(&mut mmu, 0x30 | nibble);
+    }
+    command(&mut mmu, 0x61);
+    mmu.cart.as_ref().unwrap().save_ram().unwrap();
+
+    let rtc_path = rom_path.with_extension("rtc");
+    let mut data = fs::read(&rtc_path).unwrap();
+    assert_eq!(data.len(), HUC3_SAVE_LEN);
+
+    // Pretend the save was made two days and 90 seconds ago.
+    let at = HUC3_SAVE_LEN - 8;
+    let saved = u64::from_le_bytes(data[at..].try_into().unwrap());
+    data[at..].copy_from_slice(&(saved - 2 * 86_400 - 90).to_le_bytes());
+    fs::write(&rtc_path, &data).unwrap();
+
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::from_file(&rom_path).unwrap());
+    command(&mut mmu, 0x60);
+    command(&mut mmu, 0x40);
+    command(&mut mmu, 0x50);
+    let nibbles: Vec<u8> = (0..7).map(|_| command(&mut mmu, 0x10) & 0x0F).collect();
+    assert_eq!(nibbles, [0x1, 0x1, 0x0, 0x3, 0x0, 0x0, 0x0]);
+}
+
+#[test]
+fn rtc_save_formats() {
+    let mut rtc = Rtc::new();
+    rtc.write(0x0B, 0xFF);
+    rtc.write(0x0C, 0x01); // day 511
+    rtc.write(0x0A, 23);
+    let data = rtc.to_save_bytes(1_000);
+
+    // One day later the counter wraps and sets the carry bit.
+    let later = Rtc::from_save_bytes(&data, 1_000 + 86_400).unwrap();
+    assert_eq!(later.day(), 0);
+    assert_eq!(later.registers()[2], 23);
+    assert_eq!(later.registers()[4], 0x80);
+
+    // The old five-byte layout still loads, without elapsed time.
+    let legacy = Rtc::from_save_bytes(&[7, 6, 5, 4, 0], 1_000).unwrap();
+    assert_eq!(legacy.registers(), [7, 6, 5, 4, 0]);
+    assert!(Rtc::from_save_bytes(&[0; 12], 0).is_none());
+}
+
+/// MBC7 cartridge with both RAM enables set.
+fn mbc7_cart() -> Cartridge {
+    let mut cart = Cartridge::load(banked_rom(2, 0x22));
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, 0x40);
+    cart
+}
+
+/// Clock `count` bits of `value`, MSB first, into the MBC7 EEPROM.
+fn eeprom_send(cart: &mut Cartridge, value: u16, count: u32) {
+    for i in (0..count).rev() {
+        let di = ((value >> i) as u8 & 1) << 1;
+        cart.write(0xA080, 0x80 | di);
+        cart.write(0xA080, 0xC0 | di);
+    }
+}
+
+fn eeprom_read_word(cart: &mut Cartridge, addr: u8) -> u16 {
+    // Start bit, READ opcode, address; then a dummy 0 and 16 data bits.
+    eeprom_send(cart, 0b110 << 8 | addr as u16, 11);
+    assert_eq!(cart.read(0xA080) & 0x01, 0);
+    let mut word = 0;
+    for _ in 0..16 {
+        cart.write(0xA080, 0x80);
+        cart.write(0xA080, 0xC0);
+        word = word << 1 | (cart.read(0xA080) & 0x01) as u16;
+    }
+    cart.write(0xA080, 0x00);
+    word
+}
+
+#[test]
+fn mbc7_eeprom_write_and_read() {
+    let mut cart = mbc7_cart();
+    assert_eq!(cart.mbc, MbcType::Mbc7);
+    assert_eq!(cart.mbc_warning(), None);
+
+    // WRITE is ignored until EWEN.
+    eeprom_send(&mut cart, 0b101 << 8 | 0x05, 11);
+    eeprom_send(&mut cart, 0x1234, 16);
+    cart.write(0xA080, 0x00);
+    assert_eq!(eeprom_read_word(&mut cart, 0x05), 0x0000);
+
+    eeprom_send(&mut cart, 0b100 << 8 | 0xC0, 11); // EWEN
+    cart.write(0xA080, 0x00);
+    eeprom_send(&mut cart, 0b101 << 8 | 0x05, 11);
+    eeprom_send(&mut cart, 0xBEEF, 16);
+    cart.write(0xA080, 0x00);
+    assert_eq!(eeprom_read_word(&mut cart, 0x05), 0xBEEF);
+    assert_eq!(&cart.ram[0x0A..0x0C], &[0xEF, 0xBE]);
+
+    eeprom_send(&mut cart, 0b111 << 8 | 0x05, 11); // ERASE
+    cart.write(0xA080, 0x00);
+    assert_eq!(eeprom_read_word(&mut cart, 0x05), 0xFFFF);
+}
+
+#[test]
+fn mbc7_accelerometer_latch() {
+    let mut cart = mbc7_cart();
+    cart.set_tilt(0x0123, -0x0010);
+
+    // The latch only updates after an erase.
+    cart.write(0xA010, 0xAA);
+    assert_eq!(cart.read(0xA020), 0x00);
+    assert_eq!(cart.read(0xA030), 0x80);
+
+    cart.write(0xA000, 0x55);
+    cart.write(0xA010, 0xAA);
+    assert_eq!(cart.read(0xA020), 0x23);
+    assert_eq!(cart.read(0xA030), 0x81);
+    assert_eq!(cart.read(0xA040), 0xF0);
+    assert_eq!(cart.read(0xA050), 0x7F);
+
+    // Registers are hidden until both enables are written.
+    cart.write(0x4000, 0x00);
+    assert_eq!(cart.read(0xA020), 0xFF);
+}
+
+#[test]
+fn mbc5_rumble_motor() {
+    let mut rom = banked_rom(2, 0x1C);
+    rom[0x0149] = 0x03;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(rom));
+    assert!(mmu.cart.as_ref().unwrap().has_rumble());
+    assert!(!mmu.is_rumbling());
+
+    mmu.write_byte(0x4000, 0x08);
+    assert!(mmu.cart.as_ref().unwrap().rumble_state());
+    assert!(mmu.is_rumbling());
+
+    // Bit 3 is not part of the RAM bank on rumble carts.
+    mmu.write_byte(0x0000, 0x0A);
+    mmu.write_byte(0x4000, 0x09);
+    mmu.write_byte(0xA000, 0x42);
+    mmu.write_byte(0x4000, 0x01);
+    assert!(!mmu.is_rumbling());
+    assert_eq!(mmu.read_byte(0xA000), 0x42);
+
+    // Without a motor, bit 3 selects RAM banks as usual.
+    let mut plain = Cartridge::load(banked_rom(2, 0x1B));
+    plain.write(0x4000, 0x08);
+    assert!(!plain.rumble_state());
+}
+
+#[test]
+fn header_fields_and_checksum() {
+    let mut rom = banked_rom(4, 0x1B);
+    rom[0x0134..0x0139].copy_from_slice(b"HELLO");
+    rom[0x0143] = 0x80;
+    rom[0x0144..0x0146].copy_from_slice(b"01");
+    rom[0x0146] = 0x03;
+    rom[0x0149] = 0x03;
+    rom[0x014B] = 0x33;
+    // Checksum of the header above, as computed by the boot ROM.
+    rom[0x014D] = 0x3D;
+    rom[0x014E] = 0xBE;
+    rom[0x014F] = 0xEF;
+
+    let header = Cartridge::load(rom.clone()).header();
+    assert_eq!(
+        header,
+        CartridgeHeader {
+            title: "HELLO".to_string(),
+            cgb_flag: 0x80,
+            sgb_flag: true,
+            cartridge_type: 0x1B,
+            mbc: MbcType::Mbc5,
+            rom_size: 0x10000,
+            ram_size: 0x8000,
+            licensee: "01".to_string(),
+            header_checksum_valid: true,
+            global_checksum: 0xBEEF,
+        }
+    );
+
+    rom[0x0140] ^= 0x01;
+    rom[0x014B] = 0x01;
+    let corrupt = Cartridge::load(rom).header();
+    assert!(!corrupt.header_checksum_valid);
+    assert_eq!(corrupt.licensee, "01");
+}
@@ -1,4 +1,8 @@
-use vibeEmu::{cartridge::Cartridge, cpu::Cpu, mmu::Mmu};
+use vibeEmu::{
+    cartridge::Cartridge,
+    cpu::{Cpu, CpuState, StepResult},
+    mmu::Mmu,
+};
 
 #[test]
 fn simple_program() {
@@ -207,6 +211,27 @@ fn halt_bug() {
     assert_eq!(cpu.pc, 3);
 }
 
+#[test]
+fn halt_bug_repeats_single_byte_instruction() {
+    // DI; HALT; INC B; NOP
+    let program = vec![0xF3, 0x76, 0x04, 0x00];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.b = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.if_reg = 0x04;
+    mmu.ie_reg = 0x04;
+
+    cpu.step(&mut mmu); // DI
+    cpu.step(&mut mmu); // HALT does not halt
+    cpu.step(&mut mmu); // INC B, PC not advanced
+    cpu.step(&mut mmu); // INC B again
+
+    assert_eq!(cpu.b, 2);
+    assert_eq!(cpu.pc, 3);
+}
+
 #[test]
 fn stop_speed_switch() {
     // STOP 0x00 ; NOP
@@ -242,3 +267,519 @@ fn double_speed_timer_scaling() {
     // In double speed, hardware advances half the cycles (2) for a NOP
     assert_eq!(mmu.timer.div.wrapping_sub(div_before), 2);
 }
+
+#[test]
+fn flags_low_nibble_always_zero() {
+    let program = vec![
+        0x3E, 0x8F, // LD A,0x8F
+        0x06, 0x71, // LD B,0x71
+        0x80, // ADD A,B
+        0x88, // ADC A,B
+        0x90, // SUB B
+        0x98, // SBC A,B
+        0xA0, // AND B
+        0xA8, // XOR B
+        0xB0, // OR B
+        0xB8, // CP B
+        0x04, // INC B
+        0x05, // DEC B
+        0x27, // DAA
+        0x2F, // CPL
+        0x37, // SCF
+        0x3F, // CCF
+        0x07, // RLCA
+        0x17, // RLA
+        0x0F, // RRCA
+        0x1F, // RRA
+        0x09, // ADD HL,BC
+        0x29, // ADD HL,HL
+        0xC6, 0xFF, // ADD A,0xFF
+        0xCE, 0x01, // ADC A,0x01
+        0xD6, 0x10, // SUB 0x10
+        0xDE, 0x01, // SBC A,0x01
+        0xE6, 0xF0, // AND 0xF0
+        0xEE, 0x0F, // XOR 0x0F
+        0xF6, 0x01, // OR 0x01
+        0xFE, 0x80, // CP 0x80
+        0xE8, 0x01, // ADD SP,1
+        0xF8, 0xFF, // LD HL,SP-1
+        0xCB, 0x00, // RLC B
+        0xCB, 0x37, // SWAP A
+        0xCB, 0x7F, // BIT 7,A
+    ];
+    let alu_steps = 33;
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+
+    cpu.step(&mut mmu); // LD A
+    cpu.step(&mut mmu); // LD B
+    for _ in 0..alu_steps {
+        cpu.f |= 0x0F;
+        cpu.step(&mut mmu);
+        assert_eq!(cpu.f & 0x0F, 0, "low nibble set after PC {:04X}", cpu.pc);
+    }
+}
+
+#[test]
+fn mid_instruction_ppu_read() {
+    let program = vec![0xF0, 0x44]; // LDH A,(0x44)
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+
+    // Align the PPU to the start of a scanline, then stop 8 cycles before
+    // the next LY increment.
+    let ly = mmu.read_byte(0xFF44);
+    while mmu.read_byte(0xFF44) == ly {
+        mmu.ppu.step(4, &mut mmu.if_reg);
+    }
+    mmu.ppu.step(456 - 8, &mut mmu.if_reg);
+    let ly = mmu.read_byte(0xFF44);
+
+    // The read happens on the third M-cycle, after LY has advanced.
+    cpu.step(&mut mmu);
+    assert_eq!(cpu.a, ly + 1);
+    assert_eq!(cpu.cycles, 12);
+}
+
+#[test]
+fn rlc_hl_read_modify_write() {
+    let program = vec![0xCB, 0x06]; // RLC (HL)
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.h = 0xC0;
+    cpu.l = 0x00;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.write_byte(0xC000, 0x85);
+
+    cpu.step(&mut mmu);
+
+    assert_eq!(mmu.read_byte(0xC000), 0x0B);
+    assert_eq!(cpu.f, 0x10);
+    assert_eq!(cpu.pc, 2);
+    assert_eq!(cpu.cycles, 16);
+}
+
+#[test]
+fn cb_prefix_cycles_not_double_counted() {
+    let cases: [(u8, u64); 3] = [
+        (0x00, 8),  // RLC B
+        (0x06, 16), // RLC (HL)
+        (0x46, 12), // BIT 0,(HL)
+    ];
+    for (op, expected) in cases {
+        let mut cpu = Cpu::new();
+        cpu.pc = 0;
+        cpu.h = 0xC0;
+        cpu.l = 0x00;
+        let mut mmu = Mmu::new();
+        mmu.load_cart(Cartridge::load(vec![0xCB, op]));
+        cpu.step(&mut mmu);
+        assert_eq!(cpu.cycles, expected, "CB {op:02X}");
+    }
+}
+
+#[test]
+fn halt_wakes_on_vblank() {
+    let program = vec![0x76, 0x00]; // HALT, NOP
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.write_byte(0xFF40, 0x91);
+    mmu.if_reg = 0xE0;
+    mmu.ie_reg = 0x01;
+
+    cpu.step(&mut mmu);
+    assert!(cpu.halted);
+
+    // The post-boot PPU starts in mode 1, so VBlank arrives within two
+    // frames (2 * 70224 cycles, 4 cycles per halted step).
+    let mut steps = 0;
+    while cpu.halted && steps < 2 * 70_224 / 4 {
+        cpu.step(&mut mmu);
+        steps += 1;
+    }
+
+    assert!(!cpu.halted);
+    assert_eq!(mmu.read_byte(0xFF44), 144);
+    assert_eq!(mmu.if_reg & 0x01, 0x01);
+}
+
+#[test]
+fn halt_wakes_on_joypad() {
+    let program = vec![0x76, 0x00]; // HALT, NOP
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.write_byte(0xFF00, 0x10);
+    mmu.if_reg = 0xE0;
+    mmu.ie_reg = 0x10;
+
+    cpu.step(&mut mmu);
+    assert!(cpu.halted);
+    cpu.step(&mut mmu);
+    assert!(cpu.halted);
+
+    mmu.set_joypad_state(0xEF);
+    cpu.step(&mut mmu);
+    assert!(!cpu.halted);
+}
+
+#[test]
+fn stop_waits_for_joypad() {
+    let program = vec![0x10, 0x00, 0x00]; // STOP, NOP
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.write_byte(0xFF00, 0x20); // select the directions
+
+    cpu.step(&mut mmu);
+    assert!(cpu.stopped);
+    assert_eq!(cpu.pc, 2);
+
+    // Buttons on the unselected line do not wake the CPU.
+    mmu.set_joypad_state(0xEF);
+    cpu.step(&mut mmu);
+    assert!(cpu.stopped);
+    assert_eq!(cpu.pc, 2);
+
+    mmu.set_joypad_state(0xEE);
+    cpu.step(&mut mmu);
+    assert!(!cpu.stopped);
+    cpu.step(&mut mmu);
+    assert_eq!(cpu.pc, 3);
+}
+
+#[test]
+fn ld_c_high_page_addressing() {
+    let program = vec![
+        0xE2, // LD (C),A
+        0xE2, // LD (C),A
+        0xF2, // LD A,(C)
+    ];
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+
+    // C=0x80 targets the first HRAM byte.
+    cpu.a = 0x42;
+    cpu.c = 0x80;
+    cpu.step(&mut mmu);
+    assert_eq!(mmu.hram[0], 0x42);
+    assert_eq!(cpu.pc, 1);
+    assert_eq!(cpu.cycles, 8);
+
+    // C=0x00 targets the joypad register, which only keeps the select bits.
+    cpu.a = 0x20;
+    cpu.c = 0x00;
+    cpu.step(&mut mmu);
+    assert_eq!(mmu.read_byte(0xFF00) & 0x30, 0x20);
+    assert_eq!(cpu.pc, 2);
+    assert_eq!(cpu.cycles, 16);
+
+    // C=0xFE reads the last HRAM byte rather than wrapping elsewhere.
+    mmu.hram[0x7E] = 0x99;
+    cpu.c = 0xFE;
+    cpu.step(&mut mmu);
+    assert_eq!(cpu.a, 0x99);
+    assert_eq!(cpu.pc, 3);
+    assert_eq!(cpu.cycles, 24);
+}
+
+#[test]
+fn step_instruction_leaves_hardware_to_caller() {
+    let program = vec![0x00, 0xCB, 0x06]; // NOP, RLC (HL)
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.h = 0xC0;
+    cpu.l = 0x00;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    let div = mmu.timer.internal_div();
+
+    assert_eq!(cpu.step_instruction(&mut mmu), 4);
+    assert_eq!(cpu.step_instruction(&mut mmu), 16);
+    assert_eq!(mmu.timer.internal_div(), div);
+    assert_eq!(cpu.cycles, 20);
+
+    mmu.timer.step(20, &mut mmu.if_reg);
+    assert_eq!(mmu.timer.internal_div(), div.wrapping_add(20));
+}
+
+#[test]
+fn inc_dec_hl_flags_match_register_variants() {
+    // (HL opcode, register opcode, operand, expected result, expected F)
+    let cases = [
+        (0x34, 0x04, 0x0F, 0x10, 0x20), // INC: half-carry
+        (0x34, 0x04, 0xFF, 0x00, 0xA0), // INC: zero and half-carry
+        (0x35, 0x05, 0x10, 0x0F, 0x60), // DEC: half-borrow
+        (0x35, 0x05, 0x01, 0x00, 0xC0), // DEC: zero
+    ];
+    for (hl_op, reg_op, operand, result, flags) in cases {
+        let mut cpu = Cpu::new();
+        cpu.pc = 0;
+        cpu.f = 0x10; // carry must be preserved
+        cpu.h = 0xC0;
+        cpu.l = 0x00;
+        let mut mmu = Mmu::new();
+        mmu.load_cart(Cartridge::load(vec![hl_op]));
+        mmu.write_byte(0xC000, operand);
+        cpu.step(&mut mmu);
+        assert_eq!(mmu.read_byte(0xC000), result, "opcode {hl_op:02X}");
+        assert_eq!(cpu.f, flags | 0x10, "opcode {hl_op:02X}");
+        let hl_flags = cpu.f;
+
+        let mut cpu = Cpu::new();
+        cpu.pc = 0;
+        cpu.f = 0x10;
+        cpu.b = operand;
+        let mut mmu = Mmu::new();
+        mmu.load_cart(Cartridge::load(vec![reg_op]));
+        cpu.step(&mut mmu);
+        assert_eq!(cpu.b, result, "opcode {reg_op:02X}");
+        assert_eq!(cpu.f, hl_flags, "opcode {reg_op:02X}");
+    }
+}
+
+#[test]
+fn alu_hl_operand_matches_register_operand() {
+    // Each (HL) ALU opcode is the B variant plus 6.
+    for hl_op in [0x86u8, 0x8E, 0x96, 0x9E, 0xA6, 0xAE, 0xB6, 0xBE] {
+        for (a, operand, f) in [(0x3A, 0xC6, 0x10), (0x0F, 0x01, 0x00), (0x00, 0x00, 0x10)] {
+            let mut cpu = Cpu::new();
+            cpu.pc = 0;
+            cpu.a = a;
+            cpu.f = f;
+            cpu.h = 0xC0;
+            cpu.l = 0x00;
+            let mut mmu = Mmu::new();
+            mmu.load_cart(Cartridge::load(vec![hl_op]));
+            mmu.write_byte(0xC000, operand);
+            cpu.step(&mut mmu);
+            assert_eq!(cpu.cycles, 8, "opcode {hl_op:02X}");
+            let hl_result = (cpu.a, cpu.f);
+
+            let reg_op = hl_op - 6;
+            let mut cpu = Cpu::new();
+            cpu.pc = 0;
+            cpu.a = a;
+            cpu.f = f;
+            cpu.b = operand;
+            let mut mmu = Mmu::new();
+            mmu.load_cart(Cartridge::load(vec![reg_op]));
+            cpu.step(&mut mmu);
+            assert_eq!(
+                hl_result,
+                (cpu.a, cpu.f),
+                "opcode {hl_op:02X} vs {reg_op:02X} with A={a:02X} operand={operand:02X}"
+            );
+        }
+    }
+}
+
+#[test]
+fn illegal_opcode_lenient_and_strict() {
+    let program = vec![0xD3, 0x00];
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.lenient = true;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program.clone()));
+    cpu.step(&mut mmu);
+    assert_eq!(cpu.pc, 1);
+    assert_eq!(cpu.cycles, 4);
+    assert!(!cpu.is_locked_up());
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    cpu.step(&mut mmu);
+    assert!(cpu.is_locked_up());
+    assert_eq!(cpu.pc, 0);
+}
+
+#[test]
+fn ld_a16_sp_stores_little_endian() {
+    // LD (0xC000),SP
+    let program = vec![0x08, 0x00, 0xC0];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.sp = 0xABCD;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+
+    let start = cpu.cycles;
+    cpu.step(&mut mmu);
+
+    assert_eq!(mmu.read_byte(0xC000), 0xCD);
+    assert_eq!(mmu.read_byte(0xC001), 0xAB);
+    assert_eq!(cpu.pc, 3);
+    assert_eq!(cpu.cycles - start, 20);
+}
+
+#[test]
+fn illegal_opcode_dd_locks_cpu_but_keeps_ticking() {
+    let program = vec![0xDD, 0x00];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+
+    cpu.step(&mut mmu);
+    assert!(cpu.is_locked_up());
+
+    let cycles = cpu.cycles;
+    let div = mmu.read_byte(0xFF04);
+    for _ in 0..100 {
+        cpu.step(&mut mmu);
+    }
+    assert!(cpu.is_locked_up());
+    assert_eq!(cpu.pc, 0);
+    assert_eq!(cpu.cycles - cycles, 400);
+    assert_ne!(mmu.read_byte(0xFF04), div);
+}
+
+#[test]
+fn cpu_state_round_trips_through_json() {
+    // EI; NOP; NOP
+    let program = vec![0xFB, 0x00, 0x00];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program.clone()));
+    mmu.if_reg = 0xE0;
+    cpu.step(&mut mmu); // EI, IME still off
+
+    cpu.a = 0x12;
+    cpu.f = 0xB0;
+    cpu.b = 0x34;
+    cpu.c = 0x56;
+    cpu.d = 0x78;
+    cpu.e = 0x9A;
+    cpu.h = 0xBC;
+    cpu.l = 0xDE;
+    cpu.sp = 0xFFF0;
+
+    let json = serde_json::to_string(&cpu.save_state()).unwrap();
+    let state: CpuState = serde_json::from_str(&json).unwrap();
+    let mut restored = Cpu::new();
+    restored.load_state(&state);
+    assert_eq!(restored.save_state(), cpu.save_state());
+
+    // The pending EI survives the round trip.
+    assert!(!restored.ime);
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    restored.step(&mut mmu);
+    assert!(restored.ime);
+}
+
+#[test]
+fn breakpoint_reported_once() {
+    // NOP; NOP; JR -4 (back to 0x0000)
+    let program = vec![0x00, 0x00, 0x18, 0xFC];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    cpu.add_breakpoint(0x0001);
+
+    assert_eq!(cpu.step_checked(&mut mmu), StepResult::Normal);
+    assert_eq!(
+        cpu.step_checked(&mut mmu),
+        StepResult::BreakpointHit(0x0001)
+    );
+    assert_eq!(cpu.pc, 0x0001);
+    assert_eq!(cpu.cycles, 4);
+
+    // Resuming executes the instruction at the breakpoint.
+    assert_eq!(cpu.step_checked(&mut mmu), StepResult::Normal);
+    assert_eq!(cpu.pc, 0x0002);
+    assert_eq!(cpu.step_checked(&mut mmu), StepResult::Normal); // JR
+    assert_eq!(cpu.step_checked(&mut mmu), StepResult::Normal); // NOP at 0
+
+    // Next time around the loop it is hit again.
+    assert_eq!(
+        cpu.step_checked(&mut mmu),
+        StepResult::BreakpointHit(0x0001)
+    );
+
+    cpu.remove_breakpoint(0x0001);
+    assert_eq!(cpu.step_checked(&mut mmu), StepResult::Normal);
+    assert_eq!(cpu.pc, 0x0002);
+}
+
+#[test]
+fn step_returns_cycles() {
+    // JP 0x0004; (pad); JR NZ,+0; NOP
+    let program = vec![0xC3, 0x04, 0x00, 0x00, 0x20, 0x00, 0x00];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.f = 0x00; // Z clear so JR NZ is taken
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.if_reg = 0xE0;
+
+    assert_eq!(cpu.step(&mut mmu), 16); // JP a16
+    assert_eq!(cpu.step(&mut mmu), 12); // JR NZ taken
+
+    // NOP followed by interrupt dispatch
+    cpu.ime = true;
+    mmu.ie_reg = 0x01;
+    mmu.if_reg |= 0x01;
+    assert_eq!(cpu.step(&mut mmu), 24);
+    assert_eq!(cpu.pc, 0x0040);
+}
+
+#[test]
+fn ei_then_halt_services_pending_interrupt() {
+    let mut program = vec![
+        0xF3, // DI
+        0x3E, 0x01, // LD A,0x01
+        0xE0, 0xFF, // LDH (IE),A
+        0xE0, 0x0F, // LDH (IF),A
+        0xFB, // EI
+        0x76, // HALT
+        0x00, // NOP
+    ];
+    program.resize(0x40, 0x00);
+    program.extend([0x04, 0xD9]); // INC B; RETI
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.b = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+
+    for _ in 0..6 {
+        cpu.step(&mut mmu); // up to and including HALT
+    }
+    assert!(!cpu.halted);
+    assert_eq!(cpu.pc, 0x0040);
+    assert_eq!(mmu.if_reg & 0x01, 0);
+
+    cpu.step(&mut mmu); // INC B
+    cpu.step(&mut mmu); // RETI
+    assert_eq!(cpu.b, 1);
+    // The HALT runs again, now with nothing pending.
+    assert_eq!(cpu.pc, 0x0008);
+    cpu.step(&mut mmu);
+    assert!(cpu.halted);
+}
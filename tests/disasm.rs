@@ -0,0 +1,37 @@
+use vibeEmu::disasm::disassemble;
+
+#[test]
+fn decodes_common_instructions() {
+    let cases: [(u16, [u8; 3], &str, u16); 14] = [
+        (0x0100, [0x00, 0, 0], "NOP", 1),
+        (0x0100, [0xC3, 0x50, 0x01], "JP $0150", 3),
+        (0x0100, [0x18, 0xFE, 0], "JR $0100", 2),
+        (0x0100, [0x20, 0x03, 0], "JR NZ,$0105", 2),
+        (0x0000, [0x3E, 0x12, 0], "LD A,$12", 2),
+        (0x0000, [0x21, 0x00, 0xC0], "LD HL,$C000", 3),
+        (0x0000, [0x2A, 0, 0], "LD A,(HL+)", 1),
+        (0x0000, [0x77, 0, 0], "LD (HL),A", 1),
+        (0x0000, [0x76, 0, 0], "HALT", 1),
+        (0x0000, [0x8E, 0, 0], "ADC A,(HL)", 1),
+        (0x0000, [0xE0, 0x44, 0], "LDH ($44),A", 2),
+        (0x0000, [0xF8, 0xFE, 0], "LD HL,SP-2", 2),
+        (0x0000, [0xCB, 0x7C, 0], "BIT 7,H", 2),
+        (0x0000, [0xDF, 0, 0], "RST $18", 1),
+    ];
+    for (pc, bytes, text, len) in cases {
+        assert_eq!(
+            disassemble(pc, bytes),
+            (text.to_string(), len),
+            "{bytes:02X?}"
+        );
+    }
+}
+
+#[test]
+fn illegal_opcodes_are_single_bytes() {
+    for op in [
+        0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+    ] {
+        assert_eq!(disassemble(0, [op, 0, 0]), (format!("DB ${op:02X}"), 1));
+    }
+}
@@ -0,0 +1,44 @@
+use vibeEmu::filter::{Filter, upscale};
+
+#[test]
+fn none_is_nearest_neighbor() {
+    let src = [0x00FF0000, 0x0000FF00, 0x000000FF, 0x00FFFFFF];
+    let mut dst = [0u32; 16];
+    upscale(&src, 2, 2, 2, Filter::None, &mut dst);
+    assert_eq!(
+        &dst[0..4],
+        &[0x00FF0000, 0x00FF0000, 0x0000FF00, 0x0000FF00]
+    );
+    assert_eq!(&dst[4..8], &dst[0..4]);
+    assert_eq!(
+        &dst[8..12],
+        &[0x000000FF, 0x000000FF, 0x00FFFFFF, 0x00FFFFFF]
+    );
+}
+
+#[test]
+fn scanlines_darken_every_other_row() {
+    let src = [0x00FFFFFF; 160 * 144];
+    let scale = 2;
+    let mut dst = vec![0u32; src.len() * scale * scale];
+    upscale(&src, 160, 144, scale, Filter::Scanlines, &mut dst);
+    for (y, row) in dst.chunks_exact(160 * scale).enumerate() {
+        for &px in row {
+            if y % 2 == 0 {
+                assert_eq!(px, 0x00FFFFFF);
+            } else {
+                assert!(px & 0xFF < 0xFF, "row {y} not darkened");
+            }
+        }
+    }
+}
+
+#[test]
+fn lcd_grid_darkens_cell_borders() {
+    let src = [0x00FFFFFF; 4];
+    let mut dst = [0u32; 36];
+    upscale(&src, 2, 2, 3, Filter::LcdGrid, &mut dst);
+    assert_eq!(dst[0], 0x00FFFFFF);
+    assert_ne!(dst[2], 0x00FFFFFF);
+    assert_ne!(dst[2 * 6], 0x00FFFFFF);
+}
@@ -0,0 +1,23 @@
+use std::time::{Duration, Instant};
+use vibeEmu::fps::{FpsCounter, GB_FRAME_RATE};
+
+#[test]
+fn rolling_average() {
+    let mut counter = FpsCounter::new(Duration::from_secs(1));
+    assert_eq!(counter.fps(), 0.0);
+
+    let start = Instant::now();
+    // 30 frames per second for 2 seconds, then 60 for 1 second.
+    for i in 0..=60 {
+        counter.record(start + Duration::from_millis(i * 1000 / 30));
+    }
+    assert!((counter.fps() - 30.0).abs() < 1.0, "{}", counter.fps());
+
+    let base = start + Duration::from_secs(2);
+    for i in 1..=60 {
+        counter.record(base + Duration::from_micros(i * 1_000_000 / 60));
+    }
+    assert!((counter.fps() - 60.0).abs() < 1.0, "{}", counter.fps());
+    let speed = counter.speed_percent();
+    assert!((speed - 6000.0 / GB_FRAME_RATE).abs() < 2.0, "{speed}");
+}
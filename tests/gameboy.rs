@@ -0,0 +1,391 @@
+use vibeEmu::{
+    cartridge::{Cartridge, NINTENDO_LOGO},
+    gameboy::{BootRomError, CGB_BOOT_ROM_SIZE, DMG_BOOT_ROM_SIZE, GameBoy, SaveStateError},
+    screenshot::frame_hash,
+};
+
+fn boot_rom(size: usize, logo_offset: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    data[logo_offset..logo_offset + 48].copy_from_slice(&NINTENDO_LOGO);
+    data
+}
+
+#[test]
+fn boot_rom_validation() {
+    let mut gb = GameBoy::new_with_mode(false);
+    assert_eq!(
+        gb.load_boot_rom_bytes(boot_rom(CGB_BOOT_ROM_SIZE, 0xA8)),
+        Err(BootRomError::WrongSize {
+            expected: DMG_BOOT_ROM_SIZE,
+            actual: CGB_BOOT_ROM_SIZE,
+        })
+    );
+    assert_eq!(
+        gb.load_boot_rom_bytes(vec![0; DMG_BOOT_ROM_SIZE]),
+        Err(BootRomError::MissingLogo)
+    );
+    assert!(!gb.mmu.boot_mapped);
+
+    assert_eq!(
+        gb.load_boot_rom_bytes(boot_rom(DMG_BOOT_ROM_SIZE, 0xA8)),
+        Ok(())
+    );
+    assert!(gb.mmu.boot_mapped);
+    assert_eq!(gb.mmu.read_byte(0x00A8), NINTENDO_LOGO[0]);
+}
+
+#[test]
+fn cgb_boot_rom_maps_around_header_gap() {
+    let mut gb = GameBoy::new_with_mode(true);
+    assert!(
+        gb.load_boot_rom_bytes(boot_rom(DMG_BOOT_ROM_SIZE, 0xA8))
+            .is_err()
+    );
+
+    let mut data = boot_rom(CGB_BOOT_ROM_SIZE, 0x300);
+    data[0x0150] = 0x11;
+    data[0x0200] = 0x22;
+    assert_eq!(gb.load_boot_rom_bytes(data), Ok(()));
+
+    assert_eq!(gb.mmu.read_byte(0x0150), 0xFF); // header gap, no cartridge
+    assert_eq!(gb.mmu.read_byte(0x0200), 0x22);
+    assert_eq!(gb.mmu.read_byte(0x0300), NINTENDO_LOGO[0]);
+}
+
+#[test]
+fn audio_samples_track_video_frames() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(vec![0x18, 0xFE])); // JR $
+    gb.cpu.pc = 0;
+    let expected = gb.audio_samples_per_frame();
+    assert!((expected - 738.4).abs() < 1.0, "{expected}");
+
+    let drain = |gb: &mut GameBoy| {
+        let mut apu = gb.mmu.apu.lock().unwrap();
+        let n = apu.buffered_samples();
+        for _ in 0..n {
+            apu.pop_sample();
+        }
+        n / 2
+    };
+    let run_frame = |gb: &mut GameBoy| {
+        while !gb.mmu.ppu.frame_ready() {
+            gb.cpu.step(&mut gb.mmu);
+        }
+        gb.mmu.ppu.clear_frame_flag();
+    };
+
+    // The first frame after power-on is short; start counting after it.
+    run_frame(&mut gb);
+    drain(&mut gb);
+    let frames = 120;
+    let mut samples = 0;
+    for _ in 0..frames {
+        run_frame(&mut gb);
+        samples += drain(&mut gb);
+    }
+
+    let per_frame = samples as f64 / frames as f64;
+    assert!(
+        (per_frame - expected).abs() / expected < 0.01,
+        "{per_frame} samples per frame, expected {expected}"
+    );
+}
+
+/// INC A; LDH (BGP),A; LD (C000),A; JR back to the start.
+const BGP_CYCLER: [u8; 8] = [0x3C, 0xE0, 0x47, 0xEA, 0x00, 0xC0, 0x18, 0xF8];
+
+fn run_frames(gb: &mut GameBoy, frames: usize) {
+    for _ in 0..frames {
+        gb.run_frame();
+    }
+}
+
+#[test]
+fn savestate_round_trip_is_deterministic() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(BGP_CYCLER.to_vec()));
+    gb.cpu.pc = 0;
+    run_frames(&mut gb, 10);
+
+    let state = gb.save_state();
+    run_frames(&mut gb, 5);
+    let frame = *gb.mmu.ppu.framebuffer();
+    let cpu = gb.cpu.save_state();
+    let wram = gb.mmu.read_byte(0xC000);
+
+    gb.load_state(&state).unwrap();
+    run_frames(&mut gb, 5);
+    assert!(*gb.mmu.ppu.framebuffer() == frame);
+    assert_eq!(gb.cpu.save_state(), cpu);
+    assert_eq!(gb.mmu.read_byte(0xC000), wram);
+}
+
+#[test]
+fn savestate_rejects_other_games_and_garbage() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(BGP_CYCLER.to_vec()));
+    let state = gb.save_state();
+
+    let mut other = GameBoy::new();
+    let mut rom = vec![0; 0x8000];
+    rom[0x014D] = 0x42;
+    other.mmu.load_cart(Cartridge::load(rom));
+    assert_eq!(other.load_state(&state), Err(SaveStateError::WrongGame));
+    assert_eq!(
+        GameBoy::new_with_mode(true).load_state(&state),
+        Err(SaveStateError::WrongGame)
+    );
+
+    assert_eq!(gb.load_state(b"nope"), Err(SaveStateError::NotASaveState));
+    let mut newer = state.clone();
+    newer[4] = 99;
+    assert_eq!(
+        gb.load_state(&newer),
+        Err(SaveStateError::UnsupportedVersion(99))
+    );
+    assert!(matches!(
+        gb.load_state(&state[..state.len() / 2]),
+        Err(SaveStateError::Corrupt(_))
+    ));
+}
+
+#[test]
+fn savestate_rejects_ppu_timing_out_of_range() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(vec![0x18, 0xFE])); // JR $
+    gb.cpu.pc = 0;
+    gb.mmu.write_byte(0xFF42, 0x5A); // SCY
+    gb.mmu.write_byte(0xFF43, 0xA5); // SCX
+    gb.mmu.write_byte(0xFF45, 0x77); // LYC
+    let state = gb.save_state();
+
+    // LY sits between SCX and LYC in the saved registers.
+    let at = state
+        .windows(3)
+        .position(|w| w[0] == 0xA5 && w[2] == 0x77)
+        .expect("PPU registers not found")
+        + 1;
+    let mut bad = state.clone();
+    bad[at] = 200;
+    assert!(matches!(
+        gb.load_state(&bad),
+        Err(SaveStateError::Corrupt(e)) if e.contains("LY")
+    ));
+    assert_eq!(gb.load_state(&state), Ok(()));
+}
+
+#[test]
+fn savestate_matches_roms_shorter_than_the_header() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(vec![0x00, 0x18, 0xFD]));
+    let state = gb.save_state();
+
+    let mut other = GameBoy::new();
+    other.mmu.load_cart(Cartridge::load(vec![0x00, 0x18, 0xFE]));
+    assert_eq!(other.load_state(&state), Err(SaveStateError::WrongGame));
+    assert_eq!(gb.load_state(&state), Ok(()));
+}
+
+#[test]
+fn savestate_rejects_malformed_camera_registers() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0147] = 0xFC; // Pocket Camera
+    rom[0x0149] = 0x04; // 128 KiB
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(rom));
+    let mut state = gb.save_state();
+
+    // The 0x36 camera registers follow their little-endian u64 length.
+    let mut regs = vec![0x36, 0, 0, 0, 0, 0, 0, 0];
+    regs.resize(8 + 0x36, 0);
+    let at = state
+        .windows(regs.len())
+        .position(|w| w == regs)
+        .expect("camera registers not found");
+    state[at] = 0x35;
+    state.remove(at + 8);
+    assert!(matches!(
+        gb.load_state(&state),
+        Err(SaveStateError::Corrupt(e)) if e.contains("camera")
+    ));
+}
+
+#[test]
+fn reset_clears_memory_but_keeps_cart_ram() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0147] = 0x03; // MBC1+RAM+BATTERY
+    rom[0x0149] = 0x02; // 8 KiB
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(rom));
+    gb.mmu.write_byte(0x0000, 0x0A);
+    gb.mmu.write_byte(0xA000, 0x5A);
+    gb.mmu.write_byte(0xC123, 0x77);
+    gb.mmu.write_byte(0xFF80, 0x66);
+    gb.cpu.pc = 0x1234;
+
+    gb.reset();
+
+    assert_eq!(gb.cpu.pc, 0x0100);
+    assert_eq!(gb.mmu.read_byte(0xC123), 0x00);
+    assert_eq!(gb.mmu.read_byte(0xFF80), 0x00);
+    // The mapper is back to power-on, so RAM must be enabled again.
+    assert_eq!(gb.mmu.read_byte(0xA000), 0xFF);
+    gb.mmu.write_byte(0x0000, 0x0A);
+    assert_eq!(gb.mmu.read_byte(0xA000), 0x5A);
+}
+
+#[test]
+fn reset_runs_the_boot_rom_again() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(vec![0; 0x8000]));
+    let mut boot = boot_rom(DMG_BOOT_ROM_SIZE, 0xA8);
+    boot[0] = 0x31; // LD SP,d16
+    gb.load_boot_rom_bytes(boot).unwrap();
+    assert_eq!((gb.cpu.pc, gb.cpu.sp, gb.cpu.a), (0x0000, 0x0000, 0x00));
+
+    // Leave the boot ROM as it would at its end.
+    gb.mmu.write_byte(0xFF50, 0x01);
+    gb.cpu.pc = 0x0150;
+    gb.reset();
+
+    assert_eq!((gb.cpu.pc, gb.cpu.sp, gb.cpu.a), (0x0000, 0x0000, 0x00));
+    assert!(gb.mmu.boot_mapped);
+    assert_eq!(gb.mmu.read_byte(0x0000), 0x31);
+}
+
+#[test]
+fn run_frame_advances_one_frame() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(vec![0x18, 0xFE])); // JR $
+    gb.cpu.pc = 0;
+    // The first frame after power-on is short.
+    gb.run_frame();
+
+    let start = gb.cpu.cycles;
+    gb.run_frame();
+    assert!(!gb.mmu.ppu.frame_ready());
+    let elapsed = gb.cpu.cycles - start;
+    assert!(elapsed.abs_diff(70_224) <= 12, "{elapsed} cycles");
+}
+
+#[test]
+fn rewind_returns_to_earlier_snapshots() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(BGP_CYCLER.to_vec()));
+    gb.cpu.pc = 0;
+    gb.enable_rewind(1, 60);
+    let mut states = Vec::new();
+    for _ in 0..10 {
+        gb.run_frame();
+        states.push(gb.save_state());
+    }
+    assert_eq!(gb.rewind_buffer().unwrap().len(), 10);
+
+    for _ in 0..3 {
+        assert!(gb.rewind_one());
+    }
+    // Back at the end of frame 7.
+    assert!(gb.save_state() == states[6]);
+    assert_eq!(gb.rewind_buffer().unwrap().len(), 7);
+
+    // Running on records new snapshots from there.
+    gb.run_frame();
+    assert!(gb.save_state() == states[7]);
+}
+
+#[test]
+fn rewind_depth_is_capped() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(BGP_CYCLER.to_vec()));
+    gb.cpu.pc = 0;
+    gb.enable_rewind(2, 3);
+    for _ in 0..20 {
+        gb.run_frame();
+    }
+    let buffer = gb.rewind_buffer().unwrap();
+    assert_eq!(buffer.len(), 3);
+    // Older snapshots are stored as small deltas.
+    assert!(buffer.memory_used() < buffer.peek().unwrap().len() * 2);
+
+    assert!(gb.rewind_one());
+    assert!(gb.rewind_one());
+    assert!(!gb.rewind_one());
+}
+
+#[test]
+fn run_frames_skips_drawing_all_but_the_last() {
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(BGP_CYCLER.to_vec()));
+    gb.cpu.pc = 0;
+    gb.run_frame();
+    gb.mmu.ppu.framebuffer.fill(0x123456);
+
+    let start = gb.cpu.cycles;
+    gb.mmu.ppu.set_render_enabled(false);
+    gb.run_frame();
+    assert!(gb.mmu.ppu.framebuffer().iter().all(|&c| c == 0x123456));
+    assert!(gb.cpu.cycles - start >= 70_000);
+
+    gb.run_frames(3);
+    assert!(gb.mmu.ppu.framebuffer().iter().all(|&c| c != 0x123456));
+    assert!(gb.cpu.cycles - start >= 4 * 70_000);
+}
+
+/// LD A,$10; LDH (P1),A; LDH A,(P1); LDH (BGP),A; JR back to the start.
+const BUTTONS_TO_BGP: [u8; 10] = [0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0xE0, 0x47, 0x18, 0xF6];
+
+#[test]
+fn input_recording_replays_deterministically() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run.inputs");
+    // The first frame after power-on is short; the rest show A and B.
+    let script = [0xFF, 0xFF, 0xEF, 0xDF, 0xCF];
+    let new_gb = || {
+        let mut gb = GameBoy::new();
+        gb.mmu.load_cart(Cartridge::load(BUTTONS_TO_BGP.to_vec()));
+        gb.cpu.pc = 0;
+        gb
+    };
+
+    let mut gb = new_gb();
+    gb.start_recording();
+    let mut recorded = Vec::new();
+    for state in script {
+        gb.mmu.set_joypad_state(state);
+        gb.run_frame();
+        recorded.push(frame_hash(gb.mmu.ppu.framebuffer()));
+    }
+    gb.save_recording(&path).unwrap();
+
+    let mut gb = new_gb();
+    gb.play_recording(&path).unwrap();
+    let mut replayed = Vec::new();
+    for _ in script {
+        assert!(gb.is_playing_recording());
+        // Live input is overridden by the recording.
+        gb.mmu.set_joypad_state(0xFE);
+        gb.run_frame();
+        replayed.push(frame_hash(gb.mmu.ppu.framebuffer()));
+    }
+    assert_eq!(replayed, recorded);
+    let mut shades = recorded[1..].to_vec();
+    shades.dedup();
+    assert_eq!(shades.len(), 4);
+
+    gb.run_frame();
+    assert!(!gb.is_playing_recording());
+}
+
+#[test]
+fn input_recording_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run.inputs");
+    let mut gb = GameBoy::new();
+    assert!(gb.save_recording(&path).is_err());
+    std::fs::write(&path, b"garbage").unwrap();
+    assert_eq!(
+        gb.play_recording(&path).unwrap_err().kind(),
+        std::io::ErrorKind::InvalidData
+    );
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "gui")]
+
+use vibeEmu::{cartridge::Cartridge, gameboy::GameBoy, gui::DebuggerState};
+
+fn state() -> DebuggerState {
+    let mut gb = GameBoy::new();
+    // NOP; NOP; JR -4
+    gb.mmu
+        .load_cart(Cartridge::load(vec![0x00, 0x00, 0x18, 0xFC]));
+    gb.cpu.pc = 0;
+    DebuggerState::new(gb)
+}
+
+#[test]
+fn steps_one_frame() {
+    let mut state = state();
+    assert!(!state.paused);
+    let before = state.gb.cpu.cycles;
+    assert!(state.run_frame());
+    assert!(state.gb.cpu.cycles > before);
+    assert!(!state.gb.mmu.ppu.frame_ready());
+
+    let lines = state.disassembly(3);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(state.screen_image().size, [160, 144]);
+    assert_eq!(state.tile_image().size, [128, 192]);
+}
+
+#[test]
+fn stops_on_breakpoint() {
+    let mut state = state();
+    state.toggle_breakpoint(0x0002);
+    assert!(!state.run_frame());
+    assert_eq!(state.hit_breakpoint, Some(0x0002));
+    assert_eq!(state.gb.cpu.pc, 0x0002);
+    assert!(state.paused);
+
+    // Resuming steps past the breakpoint before checking it again.
+    state.toggle_breakpoint(0x0002);
+    assert!(!state.gb.cpu.has_breakpoint(0x0002));
+    state.step_instruction();
+    assert_eq!(state.gb.cpu.pc, 0x0000);
+}
+
+#[test]
+fn frame_returns_with_lcd_off() {
+    let mut state = state();
+    state.gb.mmu.write_byte(0xFF40, 0x00);
+    assert!(state.run_frame());
+    assert!(!state.paused);
+}
+
+#[test]
+fn resumes_and_stops_on_the_cpu_breakpoint_again() {
+    let mut state = state();
+    state.gb.cpu.add_breakpoint(0x0002);
+    assert!(!state.run_frame());
+    let cycles = state.gb.cpu.cycles;
+
+    // The loop comes back around to the same breakpoint.
+    assert!(!state.run_frame());
+    assert_eq!(state.hit_breakpoint, Some(0x0002));
+    assert!(state.gb.cpu.cycles > cycles);
+
+    state.step_instruction();
+    assert_eq!(state.gb.cpu.pc, 0x0000);
+    assert!(state.gb.cpu.has_breakpoint(0x0002));
+}
@@ -0,0 +1,181 @@
+use std::process::Command;
+use vibeEmu::cartridge::NINTENDO_LOGO;
+
+fn tiny_rom() -> Vec<u8> {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x0100] = 0x18; // JR $
+    rom[0x0101] = 0xFE;
+    rom
+}
+
+#[test]
+fn headless_without_audio_exits_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom = dir.path().join("tiny.gb");
+    std::fs::write(&rom, tiny_rom()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .args(["--headless", "--no-audio"])
+        .arg(&rom)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn missing_rom_reports_failure() {
+    let status = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .args(["--headless", "--no-audio"])
+        .output()
+        .unwrap()
+        .status;
+
+    assert!(!status.success());
+}
+
+#[test]
+fn strict_logo_check_refuses_bad_rom() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom = dir.path().join("tiny.gb");
+    std::fs::write(&rom, tiny_rom()).unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+            .args(["--headless", "--no-audio", "--verify-logo"])
+            .args(extra)
+            .arg(&rom)
+            .output()
+            .unwrap()
+            .status
+    };
+
+    assert!(run(&[]).success());
+    assert!(!run(&["--strict"]).success());
+}
+
+#[test]
+fn list_mbc_exits_without_rom() {
+    let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .arg("--list-mbc")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("MBC1"));
+}
+
+#[test]
+fn illegal_opcode_reports_lockup() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom_path = dir.path().join("illegal.gb");
+    let mut rom = tiny_rom();
+    rom[0x0100] = 0xDD;
+    std::fs::write(&rom_path, rom).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .args(["--headless", "--no-audio"])
+        .arg(&rom_path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("locked up on illegal opcode DD at 0100")
+    );
+}
+
+#[test]
+fn frame_hashes_are_reproducible() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom = dir.path().join("tiny.gb");
+    std::fs::write(&rom, tiny_rom()).unwrap();
+
+    let run = || {
+        let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+            .args(["--headless", "--no-audio", "--frame-hash"])
+            .arg(&rom)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|l| l.starts_with("frame "))
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+
+    let first = run();
+    assert_eq!(first.len(), 10);
+    assert_eq!(first, run());
+}
+
+#[test]
+fn lcd_off_rom_still_finishes() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom_path = dir.path().join("lcd_off.gb");
+    let mut rom = tiny_rom();
+    // XOR A; LDH (LCDC),A; JR $
+    rom[0x0100..0x0106].copy_from_slice(&[0xAF, 0xE0, 0x40, 0x18, 0xFE, 0x00]);
+    std::fs::write(&rom_path, rom).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .args(["--headless", "--no-audio", "--frame-hash"])
+        .arg(&rom_path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let frames = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| l.starts_with("frame "))
+        .count();
+    assert_eq!(frames, 10);
+}
+
+#[test]
+fn enforce_logo_locks_up_with_boot_rom() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom = dir.path().join("tiny.gb");
+    std::fs::write(&rom, tiny_rom()).unwrap();
+    // JR $ followed by the logo the boot ROM compares against.
+    let mut boot = vec![0u8; 0x100];
+    boot[0..2].copy_from_slice(&[0x18, 0xFE]);
+    boot[0xA8..0xC0].copy_from_slice(&NINTENDO_LOGO[..0x18]);
+    let boot_path = dir.path().join("dmg_boot.bin");
+    std::fs::write(&boot_path, boot).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .args(["--headless", "--no-audio", "--enforce-logo", "--bootrom"])
+        .arg(&boot_path)
+        .arg(&rom)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("logo mismatch"));
+}
+
+#[test]
+fn invalid_boot_rom_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let rom = dir.path().join("tiny.gb");
+    std::fs::write(&rom, tiny_rom()).unwrap();
+    let boot_path = dir.path().join("dmg_boot.bin");
+    std::fs::write(&boot_path, [0u8; 0x80]).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_vibeEmu"))
+        .args(["--headless", "--no-audio", "--bootrom"])
+        .arg(&boot_path)
+        .arg(&rom)
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid boot ROM"));
+}
@@ -0,0 +1,144 @@
+use std::{cell::RefCell, rc::Rc};
+use vibeEmu::{
+    cartridge::Cartridge,
+    cpu::Cpu,
+    interrupts::{InterruptType, Interrupts},
+    mmu::Mmu,
+};
+
+#[test]
+fn interrupt_bits() {
+    assert_eq!(InterruptType::VBlank.bit(), 0x01);
+    assert_eq!(InterruptType::LcdStat.bit(), 0x02);
+    assert_eq!(InterruptType::Timer.bit(), 0x04);
+    assert_eq!(InterruptType::Serial.bit(), 0x08);
+    assert_eq!(InterruptType::Joypad.bit(), 0x10);
+}
+
+#[test]
+fn pending_returns_highest_priority() {
+    let mut if_reg = 0xE0;
+    Interrupts::request(&mut if_reg, InterruptType::Joypad);
+    Interrupts::request(&mut if_reg, InterruptType::Timer);
+    Interrupts::request(&mut if_reg, InterruptType::VBlank);
+
+    assert_eq!(
+        Interrupts::pending(if_reg, 0x1F),
+        Some(InterruptType::VBlank)
+    );
+    assert_eq!(
+        Interrupts::pending(if_reg, 0x1E),
+        Some(InterruptType::Timer)
+    );
+    assert_eq!(Interrupts::pending(if_reg, 0xE0), None);
+
+    Interrupts::acknowledge(&mut if_reg, InterruptType::VBlank);
+    assert_eq!(
+        Interrupts::pending(if_reg, 0x1F),
+        Some(InterruptType::Timer)
+    );
+    assert_eq!(InterruptType::Timer.vector(), 0x50);
+}
+
+#[test]
+fn serial_interrupt_dispatch() {
+    let program = vec![0x00]; // NOP
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.sp = 0xC100;
+    cpu.ime = true;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    mmu.if_reg = 0xE0;
+    mmu.ie_reg = 0x08;
+
+    mmu.request_interrupt(InterruptType::Serial);
+    assert_eq!(mmu.if_reg & 0x08, 0x08);
+
+    cpu.step(&mut mmu);
+
+    assert_eq!(cpu.pc, 0x0058);
+    assert_eq!(mmu.if_reg & 0x08, 0);
+}
+
+#[test]
+fn serial_transfer_routes_interrupt() {
+    let mut mmu = Mmu::new();
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF01, 0x42);
+    mmu.write_byte(0xFF02, 0x81);
+    mmu.serial.step(8 * 512, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x08, 0x08);
+}
+
+#[test]
+fn joypad_press_routes_interrupt() {
+    let mut mmu = Mmu::new();
+    mmu.if_reg = 0xE0;
+    mmu.input.update_state(0xFE, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x10, 0x10);
+}
+
+#[test]
+fn joypad_state_through_bus_requests_interrupt() {
+    let mut mmu = Mmu::new();
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF00, 0x10); // select the action buttons
+    mmu.set_joypad_state(0xEF); // press A
+    assert_eq!(mmu.if_reg & 0x10, 0x10);
+    assert_eq!(mmu.read_byte(0xFF00) & 0x0F, 0x0E);
+}
+
+#[test]
+fn joypad_interrupt_only_on_selected_line() {
+    let mut mmu = Mmu::new();
+    mmu.if_reg = 0xE0;
+
+    // Directions selected: pressing A (bit 4 of the state) is not visible.
+    mmu.write_byte(0xFF00, 0x20);
+    mmu.input.update_state(0xEF, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x10, 0);
+    mmu.input.update_state(0xFF, &mut mmu.if_reg);
+
+    // Actions selected with A held: pressing B as well fires for B's bit.
+    mmu.write_byte(0xFF00, 0x10);
+    mmu.input.update_state(0xEF, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x10, 0x10);
+    mmu.if_reg = 0xE0;
+    mmu.input.update_state(0xCF, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x10, 0x10);
+    assert_eq!(mmu.read_byte(0xFF00) & 0x0F, 0x0C);
+
+    // Releasing never fires.
+    mmu.if_reg = 0xE0;
+    mmu.input.update_state(0xFF, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x10, 0);
+}
+
+#[test]
+fn interrupt_callback_sees_vblank() {
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(vec![0x18, 0xFE])); // JR -2
+    mmu.write_byte(0xFF40, 0x91);
+    mmu.if_reg = 0xE0;
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&seen);
+    mmu.set_interrupt_callback(Box::new(move |i| sink.borrow_mut().push(i)));
+
+    while mmu.if_reg & InterruptType::VBlank.bit() == 0 {
+        cpu.step(&mut mmu);
+    }
+    assert!(seen.borrow().contains(&InterruptType::VBlank));
+    assert_eq!(mmu.interrupt_counts()[0], 1);
+
+    // A request for a bit that is already set is not a new request.
+    mmu.request_interrupt(InterruptType::VBlank);
+    assert_eq!(mmu.interrupt_counts()[0], 1);
+    mmu.request_interrupt(InterruptType::Joypad);
+    assert_eq!(mmu.interrupt_counts()[4], 1);
+    assert_eq!(seen.borrow().last(), Some(&InterruptType::Joypad));
+}
@@ -0,0 +1,57 @@
+use minifb::Key;
+use vibeEmu::{
+    input::JoypadButton,
+    keymap::{KeyMap, KeyMapError},
+};
+
+#[test]
+fn default_bindings() {
+    let map = KeyMap::default();
+    assert_eq!(map.button_for(Key::S), Some(JoypadButton::A));
+    assert_eq!(map.button_for(Key::Enter), Some(JoypadButton::Start));
+    assert_eq!(map.button_for(Key::X), None);
+}
+
+#[test]
+fn config_remaps_buttons() {
+    let map = KeyMap::from_toml("[keys]\na = \"x\"\nselect = [\"Q\", \"Tab\"]\n").unwrap();
+    assert_eq!(map.button_for(Key::X), Some(JoypadButton::A));
+    assert_eq!(map.button_for(Key::S), None);
+    assert_eq!(map.button_for(Key::Q), Some(JoypadButton::Select));
+    assert_eq!(map.button_for(Key::Tab), Some(JoypadButton::Select));
+    assert_eq!(map.button_for(Key::LeftShift), None);
+    // Unlisted buttons keep their defaults.
+    assert_eq!(map.button_for(Key::A), Some(JoypadButton::B));
+}
+
+#[test]
+fn joypad_state_clears_held_buttons() {
+    let map = KeyMap::from_toml("[keys]\nstart = \"Tab\"\n").unwrap();
+    let state = map.joypad_state(|key| matches!(key, Key::Tab | Key::Left));
+    let held = JoypadButton::Start.mask() | JoypadButton::Left.mask();
+    assert_eq!(state, !held);
+}
+
+#[test]
+fn bad_configs_are_rejected() {
+    assert_eq!(
+        KeyMap::from_toml("[keys]\na = \"NoSuchKey\"\n"),
+        Err(KeyMapError::UnknownKey("NoSuchKey".into()))
+    );
+    assert_eq!(
+        KeyMap::from_toml("[keys]\nstart = \"space\"\n"),
+        Err(KeyMapError::ReservedKey("space".into()))
+    );
+    assert_eq!(
+        KeyMap::from_toml("[keys]\na = [\"X\", \"Key2\"]\n"),
+        Err(KeyMapError::ReservedKey("Key2".into()))
+    );
+    assert!(matches!(
+        KeyMap::from_toml("[keys]\nturbo = \"T\"\n"),
+        Err(KeyMapError::Parse(_))
+    ));
+    assert!(matches!(
+        KeyMap::from_toml("[buttons]\n"),
+        Err(KeyMapError::Parse(_))
+    ));
+}
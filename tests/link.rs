@@ -0,0 +1,69 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+use vibeEmu::{link::LinkCable, mmu::Mmu, serial::LinkPort};
+
+#[test]
+fn link_cable_trades_bytes_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // The listening side arms an external-clock transfer and waits.
+    let partner = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut mmu = Mmu::new();
+        mmu.serial
+            .connect(Box::new(LinkCable::from_stream(stream).unwrap()));
+        mmu.if_reg = 0xE0;
+        mmu.write_byte(0xFF01, 0x22);
+        mmu.write_byte(0xFF02, 0x80);
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while mmu.if_reg & 0x08 == 0 && std::time::Instant::now() < deadline {
+            mmu.serial.step(512, &mut mmu.if_reg);
+        }
+        (
+            mmu.read_byte(0xFF01),
+            mmu.if_reg & 0x08,
+            mmu.read_byte(0xFF02) & 0x80,
+        )
+    });
+
+    let mut mmu = Mmu::new();
+    mmu.serial
+        .connect(Box::new(LinkCable::connect(&addr.to_string()).unwrap()));
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF01, 0x11);
+    mmu.write_byte(0xFF02, 0x81);
+    mmu.serial.step(8 * 512, &mut mmu.if_reg);
+
+    assert_eq!(mmu.read_byte(0xFF01), 0x22);
+    assert_eq!(mmu.if_reg & 0x08, 0x08);
+    assert_eq!(partner.join().unwrap(), (0x11, 0x08, 0x00));
+}
+
+#[test]
+fn link_cable_drops_stale_replies() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A slow partner answers the first transfer only after it timed out.
+    let partner = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut msg = [0; 3];
+        stream.read_exact(&mut msg).unwrap();
+        assert_eq!(msg[0], 0x01);
+        let first = msg[1];
+        stream.read_exact(&mut msg).unwrap();
+        stream.write_all(&[0x02, first, 0xAA]).unwrap();
+        stream.write_all(&[0x02, msg[1], 0xBB]).unwrap();
+    });
+
+    let mut cable = LinkCable::from_stream(TcpStream::connect(addr).unwrap()).unwrap();
+    cable.set_timeout(Duration::from_millis(50));
+    assert_eq!(cable.transfer(0x11), 0xFF);
+    cable.set_timeout(Duration::from_secs(5));
+    assert_eq!(cable.transfer(0x22), 0xBB);
+    partner.join().unwrap();
+}
@@ -0,0 +1,47 @@
+use vibeEmu::{cartridge::Cartridge, cpu::Cpu, loop_detector::LoopDetector, mmu::Mmu};
+
+#[test]
+fn detects_jr_to_self() {
+    let program = vec![
+        0x00, // NOP
+        0x3C, // INC A
+        0x18, 0xFE, // JR $
+    ];
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    let mut detector = LoopDetector::new(100);
+
+    let mut steps = 0;
+    loop {
+        let pc = cpu.pc;
+        cpu.step(&mut mmu);
+        steps += 1;
+        if detector.observe(pc, &cpu) {
+            break;
+        }
+        assert!(steps < 1000, "loop not detected");
+    }
+
+    assert_eq!(detector.pc(), 0x0002);
+    assert_eq!(steps, 102);
+}
+
+#[test]
+fn ignores_halt() {
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(vec![0x76])); // HALT
+    mmu.ie_reg = 0;
+    let mut detector = LoopDetector::new(10);
+
+    for _ in 0..100 {
+        let pc = cpu.pc;
+        cpu.step(&mut mmu);
+        assert!(!detector.observe(pc, &cpu));
+    }
+    assert!(cpu.halted);
+}
@@ -1,8 +1,12 @@
-use vibeEmu::{cartridge::Cartridge, mmu::Mmu};
+use vibeEmu::{
+    cartridge::{Cartridge, MbcType},
+    cpu::Cpu,
+    mmu::{MAX_WATCH_HITS, Mmu, WatchHit, io_reg_name},
+};
 
 #[test]
 fn wram_echo_and_bank_switch() {
-    let mut mmu = Mmu::new();
+    let mut mmu = Mmu::new_with_mode(true);
     mmu.write_byte(0xC000, 0xAA);
     assert_eq!(mmu.read_byte(0xC000), 0xAA);
     mmu.write_byte(0xE000, 0xBB);
@@ -19,6 +23,21 @@ fn wram_echo_and_bank_switch() {
 
     mmu.write_byte(0xFF70, 0x02);
     assert_eq!(mmu.read_byte(0xD000), 0xCC);
+    assert_eq!(mmu.read_byte(0xFF70), 0xFA);
+}
+
+#[test]
+fn svbk_ignored_in_dmg_mode() {
+    let mut mmu = Mmu::new();
+    mmu.write_byte(0xD000, 0x11);
+
+    mmu.write_byte(0xFF70, 0x03);
+    assert_eq!(mmu.read_byte(0xFF70), 0xFF);
+    assert_eq!(mmu.read_byte(0xD000), 0x11);
+    mmu.write_byte(0xD000, 0x22);
+
+    mmu.write_byte(0xFF70, 0x01);
+    assert_eq!(mmu.read_byte(0xD000), 0x22);
 }
 
 #[test]
@@ -104,6 +123,129 @@ fn mbc1_ram_enable() {
     assert_eq!(mmu.read_byte(0xA000), 0xFF);
 }
 
+#[test]
+fn huc1_rom_and_ram_banking() {
+    let mut rom = vec![0u8; 8 * 0x4000];
+    rom[0x0147] = 0xFF; // HuC1 + RAM + Battery
+    for i in 0..8 {
+        rom[i * 0x4000] = i as u8;
+    }
+    let cart = Cartridge::from_bytes_with_ram(rom, 0x8000);
+    assert_eq!(cart.mbc, MbcType::HuC1);
+
+    let mut mmu = Mmu::new();
+    mmu.load_cart(cart);
+
+    assert_eq!(mmu.read_byte(0x4000), 1);
+    mmu.write_byte(0x2000, 0x05);
+    assert_eq!(mmu.read_byte(0x4000), 5);
+
+    // RAM needs no enable and is banked in 8KB steps.
+    mmu.write_byte(0xA000, 0x11);
+    mmu.write_byte(0x4000, 0x02);
+    mmu.write_byte(0xA000, 0x22);
+    assert_eq!(mmu.read_byte(0xA000), 0x22);
+    mmu.write_byte(0x4000, 0x00);
+    assert_eq!(mmu.read_byte(0xA000), 0x11);
+
+    // 0x0E maps the IR port, which sees no light.
+    mmu.write_byte(0x0000, 0x0E);
+    assert_eq!(mmu.read_byte(0xA000), 0xC0);
+    mmu.write_byte(0xA000, 0x01);
+    mmu.write_byte(0x0000, 0x00);
+    assert_eq!(mmu.read_byte(0xA000), 0x11);
+}
+
+#[test]
+fn huc3_rom_and_ram_banking() {
+    let mut rom = vec![0u8; 8 * 0x4000];
+    rom[0x0147] = 0xFE; // HuC3
+    for i in 0..8 {
+        rom[i * 0x4000] = i as u8;
+    }
+    let cart = Cartridge::from_bytes_with_ram(rom, 0x8000);
+    assert_eq!(cart.mbc, MbcType::HuC3);
+
+    let mut mmu = Mmu::new();
+    mmu.load_cart(cart);
+
+    mmu.write_byte(0x2000, 0x07);
+    assert_eq!(mmu.read_byte(0x4000), 7);
+
+    // Mode 0 maps RAM read-only; 0x0A makes it writable.
+    mmu.write_byte(0xA000, 0x55);
+    assert_eq!(mmu.read_byte(0xA000), 0x00);
+    mmu.write_byte(0x0000, 0x0A);
+    mmu.write_byte(0x4000, 0x01);
+    mmu.write_byte(0xA000, 0x55);
+    assert_eq!(mmu.read_byte(0xA000), 0x55);
+    mmu.write_byte(0x4000, 0x00);
+    assert_eq!(mmu.read_byte(0xA000), 0x00);
+}
+
+#[test]
+fn huc3_rtc_commands() {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x0147] = 0xFE;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(rom));
+
+    let command = |mmu: &mut Mmu, cmd: u8| {
+        mmu.write_byte(0x0000, 0x0B);
+        mmu.write_byte(0xA000, cmd);
+        mmu.write_byte(0x0000, 0x0D);
+        mmu.write_byte(0xA000, 0xFE);
+        mmu.write_byte(0x0000, 0x0C);
+        mmu.read_byte(0xA000)
+    };
+
+    // Set the clock to 2 days, 0x123 minutes.
+    command(&mut mmu, 0x40);
+    command(&mut mmu, 0x50);
+    for nibble in [0x3, 0x2, 0x1, 0x2, 0x0, 0x0, 0x0] {
+        command(&mut mmu, 0x30 | nibble);
+    }
+    command(&mut mmu, 0x61);
+
+    // One minute passes, then read the time back.
+    mmu.cart.as_mut().unwrap().tick_rtc(4_194_304 * 60);
+    command(&mut mmu, 0x60);
+    command(&mut mmu, 0x40);
+    let nibbles: Vec<u8> = (0..7).map(|_| command(&mut mmu, 0x10) & 0x0F).collect();
+    assert_eq!(nibbles, [0x4, 0x2, 0x1, 0x2, 0x0, 0x0, 0x0]);
+    assert_eq!(command(&mut mmu, 0x10), 0x90);
+}
+
+#[test]
+fn vbk_selects_attribute_bank() {
+    // The PPU starts in VBlank, so VRAM is accessible until it is stepped.
+    let mut mmu = Mmu::new_with_mode(true);
+    assert_eq!(mmu.read_byte(0xFF4F), 0xFE);
+
+    // Bank 0: tile 0 has only the leftmost pixel set to color 1.
+    mmu.write_byte(0x8000, 0x80);
+    mmu.write_byte(0x9800, 0x00);
+    // Bank 1: attributes for map entry 0 select palette 3 and H-flip.
+    mmu.write_byte(0xFF4F, 0x01);
+    assert_eq!(mmu.read_byte(0xFF4F), 0xFF);
+    mmu.write_byte(0x9800, 0x23);
+    assert_eq!(mmu.read_byte(0x8000), 0x00);
+    mmu.write_byte(0xFF4F, 0x00);
+    assert_eq!(mmu.read_byte(0x9800), 0x00);
+
+    // Palette 3 color 1 is pure blue.
+    mmu.write_byte(0xFF68, 0x80 | 0x1A);
+    mmu.write_byte(0xFF69, 0x00);
+    mmu.write_byte(0xFF69, 0x7C);
+
+    // Finish VBlank and draw line 0.
+    for _ in 0..155 {
+        mmu.ppu.step(456, &mut mmu.if_reg);
+    }
+    assert_eq!(mmu.ppu.framebuffer[7], 0x000000FF);
+    assert_ne!(mmu.ppu.framebuffer[0], 0x000000FF);
+}
+
 #[test]
 fn oam_dma_transfer() {
     let mut mmu = Mmu::new();
@@ -116,6 +258,48 @@ fn oam_dma_transfer() {
     assert_eq!(mmu.ppu.oam[0x9F], 0x9F);
 }
 
+#[test]
+fn oam_dma_from_echo_reads_wram() {
+    let mut mmu = Mmu::new();
+    for i in 0..0xA0u16 {
+        mmu.write_byte(0xC000 + i, i as u8 ^ 0x5A);
+        mmu.write_byte(0xDE00 + i, i as u8);
+    }
+
+    mmu.write_byte(0xFF46, 0xE0); // echo of 0xC000
+    mmu.dma_step(640);
+    for i in 0..0xA0 {
+        assert_eq!(mmu.ppu.oam[i], i as u8 ^ 0x5A);
+    }
+
+    // 0xFE00 would be OAM itself for the CPU; DMA reads WRAM at 0xDE00.
+    mmu.write_byte(0xFF46, 0xFE);
+    mmu.dma_step(640);
+    for i in 0..0xA0 {
+        assert_eq!(mmu.ppu.oam[i], i as u8);
+    }
+}
+
+#[test]
+fn oam_read_during_dma_returns_ff() {
+    let mut mmu = Mmu::new();
+    for i in 0..0xA0u16 {
+        mmu.write_byte(0xC000 + i, 0x40 | i as u8);
+    }
+
+    mmu.write_byte(0xFF46, 0xC0);
+    mmu.dma_step(4 * 0x10);
+    assert!(mmu.dma_active());
+    assert_eq!(mmu.read_byte(0xFE00), 0xFF);
+    // The PPU sees what has been copied so far.
+    assert_eq!(mmu.ppu.oam[0x0F], 0x4F);
+    assert_eq!(mmu.ppu.oam[0x10], 0x00);
+
+    mmu.dma_step(640);
+    assert!(!mmu.dma_active());
+    assert_eq!(mmu.read_byte(0xFE00), 0x40);
+}
+
 #[test]
 fn vram_oam_access_blocking() {
     let mut mmu = Mmu::new();
@@ -133,3 +317,311 @@ fn vram_oam_access_blocking() {
     mmu.write_byte(0xFE00, 0x56);
     assert_eq!(mmu.read_byte(0xFE00), 0x56);
 }
+
+#[test]
+fn echo_ram_bounds() {
+    let mut mmu = Mmu::new();
+    mmu.write_byte(0xC000, 0x11);
+    assert_eq!(mmu.read_byte(0xE000), 0x11);
+
+    // The last echo byte mirrors 0xDDFF, in both directions.
+    mmu.write_byte(0xDDFF, 0x22);
+    assert_eq!(mmu.read_byte(0xFDFF), 0x22);
+    mmu.write_byte(0xFDFF, 0x33);
+    assert_eq!(mmu.read_byte(0xDDFF), 0x33);
+
+    // 0xDE00 has no echo; 0xFE00 is OAM.
+    assert_eq!(mmu.ppu.mode, 1);
+    mmu.write_byte(0xFE00, 0x44);
+    mmu.write_byte(0xDE00, 0x55);
+    assert_eq!(mmu.read_byte(0xFE00), 0x44);
+    assert_eq!(mmu.ppu.oam[0], 0x44);
+    assert_eq!(mmu.read_byte(0xDE00), 0x55);
+}
+
+/// Advance the PPU until it enters HBlank once more, copying HDMA blocks.
+fn run_to_next_hblank(mmu: &mut Mmu) {
+    while mmu.ppu.mode == 0 {
+        mmu.ppu.step(4, &mut mmu.if_reg);
+        mmu.hdma_step();
+    }
+    while mmu.ppu.mode != 0 {
+        mmu.ppu.step(4, &mut mmu.if_reg);
+        mmu.hdma_step();
+    }
+}
+
+fn setup_hdma(mmu: &mut Mmu) {
+    for i in 0..0x40u16 {
+        mmu.write_byte(0xC000 + i, i as u8 + 1);
+    }
+    mmu.write_byte(0xFF51, 0xC0);
+    mmu.write_byte(0xFF52, 0x00);
+    mmu.write_byte(0xFF53, 0x00);
+    mmu.write_byte(0xFF54, 0x00);
+}
+
+#[test]
+fn hblank_dma_readback() {
+    let mut mmu = Mmu::new_with_mode(true);
+    setup_hdma(&mut mmu);
+    assert_eq!(mmu.read_byte(0xFF55), 0xFF);
+
+    mmu.write_byte(0xFF55, 0x83); // 4 blocks, HBlank mode
+    assert!(mmu.hdma_in_progress());
+    assert_eq!(mmu.read_byte(0xFF55), 0x03);
+
+    for remaining in [0x02, 0x01, 0x00] {
+        run_to_next_hblank(&mut mmu);
+        assert_eq!(mmu.read_byte(0xFF55), remaining);
+    }
+    run_to_next_hblank(&mut mmu);
+    assert!(!mmu.hdma_in_progress());
+    assert_eq!(mmu.read_byte(0xFF55), 0xFF);
+    assert_eq!(mmu.ppu.vram[0][0x00], 0x01);
+    assert_eq!(mmu.ppu.vram[0][0x3F], 0x40);
+}
+
+#[test]
+fn hblank_dma_stop_and_general_dma() {
+    let mut mmu = Mmu::new_with_mode(true);
+    setup_hdma(&mut mmu);
+    mmu.write_byte(0xFF55, 0x83);
+    run_to_next_hblank(&mut mmu);
+    mmu.write_byte(0xFF55, 0x00); // stop with 3 blocks left
+    assert!(!mmu.hdma_in_progress());
+    assert_eq!(mmu.read_byte(0xFF55), 0x82);
+    run_to_next_hblank(&mut mmu);
+    assert_eq!(mmu.ppu.vram[0][0x10], 0x00);
+
+    // General-purpose DMA copies everything immediately.
+    let mut mmu = Mmu::new_with_mode(true);
+    setup_hdma(&mut mmu);
+    mmu.write_byte(0xFF55, 0x03);
+    assert_eq!(mmu.read_byte(0xFF55), 0xFF);
+    assert_eq!(mmu.ppu.vram[0][0x3F], 0x40);
+}
+
+#[test]
+fn io_register_names() {
+    assert_eq!(io_reg_name(0xFF40), Some("LCDC"));
+    assert_eq!(io_reg_name(0xFF26), Some("NR52"));
+    assert_eq!(io_reg_name(0xFFFF), Some("IE"));
+    assert_eq!(io_reg_name(0xFF03), None);
+    assert_eq!(io_reg_name(0xC000), None);
+}
+
+#[test]
+fn general_dma_reads_vram_during_mode3() {
+    let mut mmu = Mmu::new_with_mode(true);
+    for i in 0..0x10u16 {
+        mmu.write_byte(0x8000 + i, 0xA0 | i as u8);
+    }
+
+    mmu.ppu.mode = 3;
+    assert_eq!(mmu.read_byte(0x8000), 0xFF);
+
+    mmu.write_byte(0xFF51, 0x80);
+    mmu.write_byte(0xFF52, 0x00);
+    mmu.write_byte(0xFF53, 0x08);
+    mmu.write_byte(0xFF54, 0x00);
+    mmu.write_byte(0xFF55, 0x00); // one block, general purpose
+
+    for i in 0..0x10 {
+        assert_eq!(mmu.ppu.vram[0][0x800 + i], 0xA0 | i as u8);
+    }
+}
+
+#[test]
+fn key0_selects_dmg_compat_rendering() {
+    fn render_pixel(mmu: &mut Mmu) -> u32 {
+        let mut if_reg = 0;
+        for _ in 0..=154 {
+            mmu.ppu.step(456, &mut if_reg);
+        }
+        mmu.ppu.framebuffer[0]
+    }
+
+    let mut mmu = Mmu::new_with_mode(true);
+    mmu.load_boot_rom(vec![0; 0x900]);
+
+    // Tile 0 is solid color 1 and its BG attribute selects palette 1.
+    for i in 0..8 {
+        mmu.ppu.vram[0][i * 2] = 0xFF;
+    }
+    mmu.ppu.vram[1][0x1800] = 0x01;
+    // BG palette 0: white, red, green, blue. Palette 1: all green.
+    mmu.write_byte(0xFF68, 0x80);
+    for color in [
+        0x7FFFu16, 0x001F, 0x03E0, 0x7C00, 0x03E0, 0x03E0, 0x03E0, 0x03E0,
+    ] {
+        let [lo, hi] = color.to_le_bytes();
+        mmu.write_byte(0xFF69, lo);
+        mmu.write_byte(0xFF69, hi);
+    }
+    mmu.write_byte(0xFF47, 0xEC); // color 1 -> shade 3
+
+    assert_eq!(render_pixel(&mut mmu), 0x0000FF00);
+
+    mmu.write_byte(0xFF4C, 0x04);
+    assert!(mmu.ppu.dmg_compat());
+    // BGP now maps the color and the attribute map is ignored.
+    assert_eq!(render_pixel(&mut mmu), 0x000000FF);
+    // CGB-only registers are masked.
+    assert_eq!(mmu.read_byte(0xFF70), 0xFF);
+
+    // KEY0 is locked once the boot ROM is unmapped.
+    mmu.write_byte(0xFF50, 0x01);
+    mmu.write_byte(0xFF4C, 0x00);
+    assert!(mmu.ppu.dmg_compat());
+}
+
+#[test]
+fn boot_rom_loaded_after_dmg_cart_keeps_cgb_registers() {
+    let mut mmu = Mmu::new_with_mode(true);
+    mmu.load_cart(Cartridge::load(vec![0; 0x8000]));
+    assert!(mmu.ppu.dmg_compat());
+    mmu.load_boot_rom(vec![0; 0x900]);
+
+    assert!(!mmu.ppu.dmg_compat());
+    mmu.write_byte(0xFF70, 0x03);
+    assert_eq!(mmu.read_byte(0xFF70) & 0x07, 0x03);
+
+    mmu.write_byte(0xFF4C, 0x04);
+    assert_eq!(mmu.read_byte(0xFF70), 0xFF);
+}
+
+#[test]
+fn write_watch_records_value_and_pc() {
+    // LD A,0x42; LD (0xC000),A
+    let mut rom = vec![0x3E, 0x42, 0xEA, 0x00, 0xC0];
+    rom.resize(0x200, 0);
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::from_bytes_with_ram(rom, 0x2000));
+    for addr in [0xC000, 0x8000, 0xA000] {
+        mmu.add_write_watch(addr);
+    }
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    cpu.step(&mut mmu);
+    cpu.step(&mut mmu);
+    mmu.write_byte(0xC001, 0x01); // not watched
+    mmu.write_byte(0x8000, 0x11);
+    mmu.write_byte(0xA000, 0x22);
+
+    let hits = mmu.take_watch_hits();
+    assert_eq!(
+        hits,
+        [
+            WatchHit {
+                addr: 0xC000,
+                value: 0x42,
+                pc: 0x0002
+            },
+            WatchHit {
+                addr: 0x8000,
+                value: 0x11,
+                pc: 0x0002
+            },
+            WatchHit {
+                addr: 0xA000,
+                value: 0x22,
+                pc: 0x0002
+            },
+        ]
+    );
+    assert!(mmu.take_watch_hits().is_empty());
+
+    mmu.remove_write_watch(0xC000);
+    mmu.write_byte(0xC000, 0x00);
+    assert!(mmu.take_watch_hits().is_empty());
+}
+
+#[test]
+fn write_watch_keeps_only_the_newest_hits() {
+    let mut mmu = Mmu::new();
+    mmu.add_write_watch(0xC000);
+    for i in 0..MAX_WATCH_HITS + 10 {
+        mmu.write_byte(0xC000, i as u8);
+    }
+
+    let hits = mmu.take_watch_hits();
+    assert_eq!(hits.len(), MAX_WATCH_HITS);
+    assert_eq!(hits[0].value, 10);
+    assert_eq!(hits[MAX_WATCH_HITS - 1].value, (MAX_WATCH_HITS + 9) as u8);
+}
+
+#[test]
+fn pcm_registers_show_channel_outputs() {
+    let mut mmu = Mmu::new_with_mode(true);
+    assert_eq!(mmu.read_byte(0xFF76), 0x00);
+    assert_eq!(mmu.read_byte(0xFF77), 0x00);
+
+    for addr in 0xFF30..=0xFF3F {
+        mmu.write_byte(addr, 0xFF);
+    }
+    // Channels 1 and 2 at 75% duty, channel 3 at full volume and channel 4
+    // with its envelope at 15.
+    for (nrx1, nrx2, nrx4) in [(0xFF11, 0xFF12, 0xFF14), (0xFF16, 0xFF17, 0xFF19)] {
+        mmu.write_byte(nrx1, 0xC0);
+        mmu.write_byte(nrx2, 0xF0);
+        mmu.write_byte(nrx4, 0x80);
+    }
+    mmu.write_byte(0xFF1A, 0x80);
+    mmu.write_byte(0xFF1C, 0x20);
+    mmu.write_byte(0xFF1E, 0x80);
+    mmu.write_byte(0xFF21, 0xF0);
+    mmu.write_byte(0xFF23, 0x80);
+
+    let (mut pcm12, mut pcm34) = (0, 0);
+    for _ in 0..1000 {
+        mmu.apu.lock().unwrap().step(4);
+        pcm12 |= mmu.read_byte(0xFF76);
+        pcm34 |= mmu.read_byte(0xFF77);
+    }
+    assert_eq!(pcm12, 0xFF);
+    assert_eq!(pcm34, 0xFF);
+
+    let mut dmg = Mmu::new();
+    dmg.write_byte(0xFF12, 0xF0);
+    dmg.write_byte(0xFF14, 0x80);
+    assert_eq!(dmg.read_byte(0xFF76), 0xFF);
+    assert_eq!(dmg.read_byte(0xFF77), 0xFF);
+}
+
+#[test]
+fn vram_dma_stalls_the_cpu() {
+    for (double_speed, per_block) in [(false, 32), (true, 64)] {
+        let mut rom = vec![0x00; 0x8000];
+        rom[0x0143] = 0x80; // CGB cartridge
+        let mut mmu = Mmu::new_with_mode(true);
+        mmu.load_cart(Cartridge::load(rom));
+        if double_speed {
+            mmu.key1 = 0xFE;
+        }
+        setup_hdma(&mut mmu);
+        mmu.write_byte(0xFF55, 0x03); // general-purpose DMA of 4 blocks
+        assert_eq!(mmu.vram_dma_stall, 4 * per_block);
+
+        let mut cpu = Cpu::new_with_mode(true);
+        cpu.double_speed = double_speed;
+        cpu.pc = 0x0150;
+        let mut stolen = 0;
+        while mmu.cpu_stalled() {
+            stolen += cpu.step(&mut mmu);
+        }
+        assert_eq!(stolen, 4 * per_block as u32);
+        assert_eq!(cpu.pc, 0x0150);
+        cpu.step(&mut mmu);
+        assert_eq!(cpu.pc, 0x0151);
+    }
+
+    // HBlank DMA charges one block per HBlank.
+    let mut mmu = Mmu::new_with_mode(true);
+    setup_hdma(&mut mmu);
+    mmu.write_byte(0xFF55, 0x83);
+    assert_eq!(mmu.vram_dma_stall, 0);
+    run_to_next_hblank(&mut mmu);
+    assert_eq!(mmu.vram_dma_stall, 32);
+}
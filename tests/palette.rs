@@ -0,0 +1,68 @@
+use vibeEmu::palette::{DmgPalette, PaletteError, PalettePreset};
+use vibeEmu::ppu::Ppu;
+
+#[test]
+fn parse_jasc_pal() {
+    let text = "JASC-PAL\r\n0100\r\n4\r\n224 248 208\r\n136 192 112\r\n52 104 86\r\n8 24 32\r\n";
+    let pal = DmgPalette::parse(text).unwrap();
+    let shades = [0x00E0F8D0, 0x0088C070, 0x00346856, 0x00081820];
+    assert_eq!(pal, DmgPalette::uniform(shades));
+}
+
+#[test]
+fn parse_ini_and_hex_lists() {
+    let ini = "[Palette]\nBG0=FFFFFF\nBG1=#AAAAAA\nBG2=555555\nBG3=000000\n\
+               OBJ00=FF0000\nOBJ01=AA0000\nOBJ02=550000\nOBJ03=000000\n\
+               OBJ10=0000FF\nOBJ11=0000AA\nOBJ12=000055\nOBJ13=000000\n";
+    let pal = DmgPalette::parse(ini).unwrap();
+    assert_eq!(pal.bg, [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000]);
+    assert_eq!(pal.obj0, [0xFF0000, 0xAA0000, 0x550000, 0x000000]);
+    assert_eq!(pal.obj1, [0x0000FF, 0x0000AA, 0x000055, 0x000000]);
+
+    let hex = "; greys\n#FFFFFF\n#AAAAAA\n#555555\n#000000\n";
+    assert_eq!(
+        DmgPalette::parse(hex).unwrap(),
+        DmgPalette::uniform([0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000])
+    );
+
+    assert_eq!(
+        DmgPalette::parse("FFFFFF\n000000\n"),
+        Err(PaletteError::WrongCount(2))
+    );
+    assert!(matches!(
+        DmgPalette::parse("FFFFFF\nnope\n"),
+        Err(PaletteError::InvalidColor { line: 2, .. })
+    ));
+}
+
+#[test]
+fn ppu_renders_with_custom_palette() {
+    let mut ppu = Ppu::new();
+    let mut if_reg = 0u8;
+    ppu.set_dmg_palette(DmgPalette::uniform([
+        0x112233, 0x445566, 0x778899, 0xAABBCC,
+    ]));
+    ppu.write_reg(0xFF47, 0xE5); // color 0 uses shade 1
+    ppu.write_reg(0xFF40, 0x80); // LCD on, background off
+
+    ppu.step(456, &mut if_reg);
+    assert_eq!(ppu.framebuffer()[0], 0x445566);
+}
+
+#[test]
+fn presets_map_shade_zero() {
+    for (preset, lightest) in [
+        (PalettePreset::Green, 0x009BBC0F),
+        (PalettePreset::Greyscale, 0x00FFFFFF),
+        (PalettePreset::Amber, 0x00FFC040),
+    ] {
+        let mut ppu = Ppu::new();
+        let mut if_reg = 0u8;
+        ppu.set_dmg_palette(preset.into());
+        ppu.write_reg(0xFF47, 0xE4);
+        ppu.write_reg(0xFF40, 0x80);
+
+        ppu.step(456, &mut if_reg);
+        assert_eq!(ppu.framebuffer()[0], lightest, "{preset:?}");
+    }
+}
@@ -1,4 +1,4 @@
-use vibeEmu::ppu::Ppu;
+use vibeEmu::{palette::DmgPalette, ppu::Ppu};
 
 #[test]
 fn register_access() {
@@ -70,6 +70,35 @@ fn render_window_scanline() {
     assert_eq!(ppu.framebuffer[0], 0x008BAC0F);
 }
 
+#[test]
+fn window_left_edge_and_wy_latch() {
+    let mut ppu = Ppu::new();
+    ppu.write_reg(0xFF40, 0xF1); // LCD on, window on with map 0x9C00
+    ppu.write_reg(0xFF47, 0xE4);
+    ppu.write_reg(0xFF4A, 0);
+    ppu.write_reg(0xFF4B, 3); // window starts 4 pixels left of the screen
+    // Tile 1: left half color 3, right half color 0.
+    for i in 0..8 {
+        ppu.vram[0][16 + i * 2] = 0xF0;
+        ppu.vram[0][16 + i * 2 + 1] = 0xF0;
+    }
+    ppu.vram[0][0x1C00] = 0x01;
+    ppu.vram[0][0x1C01] = 0x01;
+
+    let mut if_reg = 0u8;
+    ppu.step(456, &mut if_reg);
+    // Screen x 0-3 show window x 4-7 (color 0), then tile 2 begins.
+    assert_eq!(ppu.framebuffer[0], 0x009BBC0F);
+    assert_eq!(ppu.framebuffer[3], 0x009BBC0F);
+    assert_eq!(ppu.framebuffer[4], 0x000F380F);
+
+    // Once triggered the window stays visible even if WY moves below LY.
+    ppu.write_reg(0xFF4A, 100);
+    ppu.step(456, &mut if_reg);
+    assert_eq!(ppu.framebuffer[160 + 4], 0x000F380F);
+    assert_eq!(ppu.window_line_counter(), 2);
+}
+
 #[test]
 fn render_sprite_scanline() {
     let mut ppu = Ppu::new();
@@ -138,6 +167,49 @@ fn sprite_x_priority() {
     assert_eq!(ppu.framebuffer[1], 0x008BAC0F);
 }
 
+#[test]
+fn ten_sprites_per_line() {
+    let mut ppu = Ppu::new();
+    ppu.write_reg(0xFF40, 0x82);
+    ppu.write_reg(0xFF48, 0xE4);
+    ppu.vram[0][0] = 0xFF;
+    // Eleven sprites in OAM order from right to left; the eleventh in OAM
+    // is dropped even though it has the lowest X.
+    for i in 0..11 {
+        ppu.oam[i * 4] = 16;
+        ppu.oam[i * 4 + 1] = (8 + (10 - i) * 8) as u8;
+    }
+    let mut if_reg = 0u8;
+    ppu.step(456, &mut if_reg);
+    assert_eq!(ppu.framebuffer[0], 0x009BBC0F);
+    assert_eq!(ppu.framebuffer[8], 0x008BAC0F);
+    assert_eq!(ppu.framebuffer[80], 0x008BAC0F);
+}
+
+#[test]
+fn hidden_sprite_masks_lower_priority_sprite() {
+    let mut ppu = Ppu::new();
+    ppu.write_reg(0xFF40, 0x93); // LCD on, BG and OBJ, tile data at 0x8000
+    ppu.write_reg(0xFF47, 0xE4);
+    ppu.write_reg(0xFF48, 0xE4);
+    // BG tile 2 is color 1 everywhere.
+    ppu.vram[0][32] = 0xFF;
+    ppu.vram[0][0x1800] = 0x02;
+    // Sprite tile 0 is color 2, tile 1 color 3.
+    ppu.vram[0][1] = 0xFF;
+    ppu.vram[0][16] = 0xFF;
+    ppu.vram[0][17] = 0xFF;
+    // Sprite 0 wins by X but sits behind the BG.
+    ppu.oam[0..4].copy_from_slice(&[16, 8, 0, 0x80]);
+    ppu.oam[4..8].copy_from_slice(&[16, 9, 1, 0x00]);
+    let mut if_reg = 0u8;
+    ppu.step(456, &mut if_reg);
+    // Where sprite 0 is opaque the BG shows, not sprite 1.
+    assert_eq!(ppu.framebuffer[1], 0x008BAC0F);
+    // Past sprite 0, sprite 1 is drawn.
+    assert_eq!(ppu.framebuffer[8], 0x000F380F);
+}
+
 #[test]
 fn cgb_obj_priority_mode_cgb() {
     let mut ppu = Ppu::new_with_mode(true);
@@ -367,6 +439,36 @@ fn cgb_bg_palette_autoinc_read() {
     assert_eq!(ppu.read_reg(0xFF68) & 0x3F, 2);
 }
 
+#[test]
+fn cgb_palette_locked_in_mode3() {
+    let mut ppu = Ppu::new_with_mode(true);
+    ppu.write_reg(0xFF40, 0x91);
+    ppu.write_reg(0xFF68, 0x80);
+    assert_eq!(ppu.read_reg(0xFF68), 0xC0);
+
+    let mut if_reg = 0u8;
+    ppu.step(80, &mut if_reg); // into mode 3
+    assert_eq!(ppu.read_reg(0xFF41) & 0x03, 3);
+    ppu.write_reg(0xFF69, 0x1F);
+    assert_eq!(ppu.read_reg(0xFF68) & 0x3F, 1);
+    assert_eq!(ppu.read_reg(0xFF69), 0xFF);
+
+    ppu.step(172, &mut if_reg); // HBlank
+    ppu.write_reg(0xFF68, 0x80);
+    assert_eq!(ppu.read_reg(0xFF69), 0x00);
+    ppu.write_reg(0xFF68, 0x80);
+    ppu.write_reg(0xFF69, 0x1F);
+    ppu.write_reg(0xFF69, 0x00);
+    ppu.write_reg(0xFF68, 0x00);
+    assert_eq!(ppu.read_reg(0xFF69), 0x1F);
+
+    // BG tile 0 is color 0, which palette 0 now maps to pure red.
+    for _ in 0..154 {
+        ppu.step(456, &mut if_reg);
+    }
+    assert_eq!(ppu.framebuffer[0], 0x00FF0000);
+}
+
 #[test]
 fn bg_disable_yields_color0() {
     let mut ppu = Ppu::new();
@@ -419,3 +521,254 @@ fn window_internal_line_counter() {
     println!("counter2 {}", cnt2);
     assert_eq!(cnt1 + 1, cnt2);
 }
+
+#[test]
+fn scroll_log_tracks_mid_frame_writes() {
+    let mut ppu = Ppu::new();
+    let mut if_reg = 0u8;
+    ppu.write_reg(0xFF40, 0x91);
+
+    let mut written = false;
+    while !ppu.frame_ready() {
+        if !written && ppu.read_reg(0xFF44) == 72 {
+            ppu.write_reg(0xFF43, 0x30); // SCX
+            ppu.write_reg(0xFF42, 0x12); // SCY
+            ppu.write_reg(0xFF4B, 0x57); // WX
+            ppu.write_reg(0xFF4A, 0x40); // WY
+            written = true;
+        }
+        ppu.step(4, &mut if_reg);
+    }
+
+    for (ly, s) in ppu.line_scroll().iter().enumerate() {
+        if ly < 72 {
+            assert_eq!((s.scx, s.scy, s.wx, s.wy), (0, 0, 0, 0), "line {ly}");
+        } else {
+            assert_eq!(
+                (s.scx, s.scy, s.wx, s.wy),
+                (0x30, 0x12, 0x57, 0x40),
+                "line {ly}"
+            );
+        }
+    }
+
+    let csv = ppu.scroll_log_csv();
+    let rows: Vec<&str> = csv.lines().skip(1).collect();
+    assert_eq!(rows.len(), 144);
+    assert_eq!(rows[71], "71,0,0,0,0");
+    assert_eq!(rows[72], "72,48,18,87,64");
+}
+
+#[test]
+fn dmg_sprite_palette_select() {
+    let mut ppu = Ppu::new();
+    ppu.set_dmg_palette(DmgPalette {
+        bg: [0x000000, 0x000001, 0x000002, 0x000003],
+        obj0: [0x000010, 0x000011, 0x000012, 0x000013],
+        obj1: [0x000020, 0x000021, 0x000022, 0x000023],
+    });
+    ppu.write_reg(0xFF40, 0x82); // LCD on, sprites enabled, BG off
+    ppu.write_reg(0xFF48, 0xE4); // OBP0: identity
+    ppu.write_reg(0xFF49, 0x1B); // OBP1: inverted
+    // tile 0 row 0 -> colors 3,2,1,0,3,2,1,0
+    ppu.vram[0][0] = 0xAA;
+    ppu.vram[0][1] = 0xCC;
+    ppu.oam[0] = 16;
+    ppu.oam[1] = 8; // screen x 0, OBP0
+    ppu.oam[2] = 0;
+    ppu.oam[3] = 0x00;
+    ppu.oam[4] = 16;
+    ppu.oam[5] = 24; // screen x 16, OBP1
+    ppu.oam[6] = 0;
+    ppu.oam[7] = 0x10;
+    let mut if_reg = 0u8;
+    ppu.step(456, &mut if_reg);
+
+    let bg = ppu.framebuffer[100];
+    assert_eq!(&ppu.framebuffer[0..4], &[0x13, 0x12, 0x11, bg]);
+    assert_eq!(&ppu.framebuffer[16..20], &[0x20, 0x21, 0x22, bg]);
+}
+
+/// Step to the start of the next full frame, past the shortened first line
+/// after the LCD is switched on.
+fn skip_to_next_frame(ppu: &mut Ppu) {
+    let mut if_reg = 0u8;
+    while ppu.read_reg(0xFF44) != 153 {
+        ppu.step(4, &mut if_reg);
+    }
+    while ppu.read_reg(0xFF44) != 0 {
+        ppu.step(4, &mut if_reg);
+    }
+}
+
+/// Run `frames` whole frames four dots at a time and count STAT interrupts.
+fn count_stat_irqs(ppu: &mut Ppu, frames: usize) -> usize {
+    let mut count = 0;
+    for _ in 0..frames * 154 * 456 / 4 {
+        let mut if_reg = 0u8;
+        ppu.step(4, &mut if_reg);
+        if if_reg & 0x02 != 0 {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[test]
+fn lyc_interrupt_once_per_frame() {
+    let mut ppu = Ppu::new();
+    ppu.write_reg(0xFF40, 0x80);
+    ppu.write_reg(0xFF45, 10);
+    ppu.write_reg(0xFF41, 0x40);
+    skip_to_next_frame(&mut ppu);
+    assert_eq!(count_stat_irqs(&mut ppu, 2), 2);
+
+    let mut if_reg = 0u8;
+    while ppu.read_reg(0xFF44) != 10 {
+        ppu.step(4, &mut if_reg);
+    }
+    assert_eq!(ppu.read_reg(0xFF41) & 0x84, 0x84);
+}
+
+#[test]
+fn stat_blocking_merges_sources() {
+    let mut ppu = Ppu::new();
+    ppu.write_reg(0xFF40, 0x80);
+    ppu.write_reg(0xFF45, 10);
+    // LYC and HBlank sources. Line 9's HBlank holds the STAT line high
+    // straight into the LY=LYC match on line 10, which therefore never
+    // fires, and line 10's own HBlank is masked by the match.
+    ppu.write_reg(0xFF41, 0x48);
+    skip_to_next_frame(&mut ppu);
+    assert_eq!(count_stat_irqs(&mut ppu, 1), 143);
+}
+
+#[test]
+fn lcd_off_resets_ly_and_blanks_screen() {
+    let mut ppu = Ppu::new();
+    ppu.write_reg(0xFF40, 0x91);
+    ppu.write_reg(0xFF47, 0xFF); // everything black
+    let mut if_reg = 0u8;
+    for _ in 0..20 {
+        ppu.step(456, &mut if_reg);
+    }
+    assert_eq!(ppu.read_reg(0xFF44), 20);
+    assert_eq!(ppu.framebuffer[0], 0x000F380F);
+
+    ppu.write_reg(0xFF40, 0x11);
+    assert_eq!(ppu.read_reg(0xFF44), 0);
+    assert_eq!(ppu.read_reg(0xFF41) & 0x03, 0);
+    assert!(ppu.framebuffer.iter().all(|&p| p == 0x009BBC0F));
+    ppu.step(456 * 10, &mut if_reg);
+    assert_eq!(ppu.read_reg(0xFF44), 0);
+
+    // Switching back on restarts at line 0 without an OAM scan.
+    ppu.write_reg(0xFF40, 0x91);
+    ppu.step(76, &mut if_reg);
+    assert_eq!(ppu.read_reg(0xFF41) & 0x03, 0);
+    ppu.step(4, &mut if_reg);
+    assert_eq!(ppu.read_reg(0xFF41) & 0x03, 3);
+    ppu.step(172, &mut if_reg);
+    assert_eq!(ppu.read_reg(0xFF41) & 0x03, 0);
+    assert_eq!(ppu.framebuffer[0], 0x000F380F);
+    ppu.step(204, &mut if_reg);
+    assert_eq!(ppu.read_reg(0xFF44), 1);
+    assert_eq!(ppu.read_reg(0xFF41) & 0x03, 2);
+}
+
+/// Step one scanline a dot at a time and return the distinct modes seen in
+/// order and the dot at which mode 0 began.
+fn trace_line(ppu: &mut Ppu) -> (Vec<u8>, u16) {
+    let mut if_reg = 0u8;
+    let mut modes = vec![ppu.current_mode()];
+    let mut hblank_start = 0;
+    for _ in 0..456 {
+        ppu.step(1, &mut if_reg);
+        let mode = ppu.current_mode();
+        if mode != *modes.last().unwrap() {
+            if mode == 0 {
+                hblank_start = ppu.dots_this_line();
+            }
+            modes.push(mode);
+        }
+    }
+    (modes, hblank_start)
+}
+
+#[test]
+fn scanline_mode_timing() {
+    let mut ppu = Ppu::new();
+    ppu.apply_boot_state();
+    skip_to_next_frame(&mut ppu);
+    assert_eq!(ppu.dots_this_line(), 0);
+
+    let (modes, hblank_start) = trace_line(&mut ppu);
+    // The last transition is the next line's OAM scan.
+    assert_eq!(modes, vec![2, 3, 0, 2]);
+    assert_eq!(hblank_start, 80 + 172);
+    assert_eq!(ppu.read_reg(0xFF44), 1);
+    assert_eq!(ppu.dots_this_line(), 0);
+}
+
+#[test]
+fn mode3_extended_by_fine_scroll_and_sprites() {
+    let mut ppu = Ppu::new();
+    ppu.apply_boot_state();
+    ppu.write_reg(0xFF40, 0x93);
+    ppu.write_reg(0xFF43, 0x03);
+    skip_to_next_frame(&mut ppu);
+    // Line 1: fine scroll only.
+    trace_line(&mut ppu);
+    let (_, hblank_start) = trace_line(&mut ppu);
+    assert_eq!(hblank_start, 80 + 172 + 3);
+
+    // A sprite at OAM X 0 costs the full 11 dots.
+    ppu.oam[0] = 16 + 2;
+    ppu.oam[1] = 0;
+    let (_, hblank_start) = trace_line(&mut ppu);
+    assert_eq!(hblank_start, 80 + 172 + 3 + 11);
+}
+
+#[test]
+fn framebuffer_as_rgba_bytes_and_png() {
+    let mut ppu = Ppu::new();
+    ppu.framebuffer.fill(0x00123456);
+
+    let rgba = ppu.framebuffer_rgba8();
+    assert_eq!(rgba.len(), 160 * 144 * 4);
+    assert_eq!(
+        &rgba[..8],
+        &[0x12, 0x34, 0x56, 0xFF, 0x12, 0x34, 0x56, 0xFF]
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("frame.png");
+    ppu.save_png(&path).unwrap();
+    let img = image::open(&path).unwrap().to_rgb8();
+    assert_eq!(img.dimensions(), (160, 144));
+    assert_eq!(img.get_pixel(159, 143).0, [0x12, 0x34, 0x56]);
+}
+
+#[test]
+fn render_disabled_keeps_timing_but_not_pixels() {
+    let mut ppu = Ppu::new();
+    ppu.apply_boot_state();
+    ppu.write_reg(0xFF47, 0xE4);
+    skip_to_next_frame(&mut ppu);
+    ppu.framebuffer.fill(0x123456);
+    ppu.set_render_enabled(false);
+
+    let mut vblanks = 0;
+    for _ in 0..154 * 456 / 4 {
+        let mut if_reg = 0u8;
+        ppu.step(4, &mut if_reg);
+        vblanks += (if_reg & 0x01) as usize;
+    }
+    assert_eq!(vblanks, 1);
+    assert!(ppu.framebuffer.iter().all(|&c| c == 0x123456));
+
+    ppu.set_render_enabled(true);
+    skip_to_next_frame(&mut ppu);
+    skip_to_next_frame(&mut ppu);
+    assert!(ppu.framebuffer.iter().all(|&c| c != 0x123456));
+}
@@ -0,0 +1,51 @@
+use vibeEmu::{
+    cartridge::{Cartridge, MbcType},
+    gameboy::GameBoy,
+    profile::{ProfileError, ProfileSet, rom_hash},
+};
+
+fn rom(cart_type: u8) -> Vec<u8> {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x0147] = cart_type;
+    rom
+}
+
+#[test]
+fn matching_profile_enables_quirks() {
+    let data = rom(0x01);
+    let text = format!(
+        "; accuracy hacks\n[{:08X}]\nforce_mbc = mbc5\nlenient = true\nmode = dmg\n\n[DEADBEEF]\nlenient = false\n",
+        rom_hash(&data)
+    );
+    let set = ProfileSet::parse(&text).unwrap();
+
+    let mut gb = GameBoy::new();
+    gb.mmu.load_cart(Cartridge::load(data.clone()));
+    let quirks = *set.lookup(&data).unwrap();
+    assert_eq!(quirks.cgb, Some(false));
+    quirks.apply(&mut gb);
+
+    assert!(gb.cpu.lenient);
+    assert_eq!(gb.mmu.cart.as_ref().unwrap().mbc, MbcType::Mbc5);
+    assert!(set.lookup(&rom(0x00)).is_none());
+}
+
+#[test]
+fn profile_parse_errors() {
+    assert_eq!(
+        ProfileSet::parse("lenient = true").unwrap_err(),
+        ProfileError::MissingSection { line: 1 }
+    );
+    assert!(matches!(
+        ProfileSet::parse("[xyz]"),
+        Err(ProfileError::InvalidHash { line: 1, .. })
+    ));
+    assert!(matches!(
+        ProfileSet::parse("[00000000]\noam_bug = true"),
+        Err(ProfileError::UnknownKey { line: 2, .. })
+    ));
+    assert!(matches!(
+        ProfileSet::parse("[00000000]\nforce_mbc = mbc9"),
+        Err(ProfileError::InvalidValue { line: 2, .. })
+    ));
+}
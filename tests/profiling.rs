@@ -0,0 +1,60 @@
+#![cfg(feature = "profiling")]
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use vibeEmu::{cartridge::Cartridge, cpu::Cpu, mmu::Mmu};
+
+/// Subscriber that only remembers the names of spans created.
+struct SpanNames {
+    names: Arc<Mutex<HashSet<&'static str>>>,
+    next_id: AtomicU64,
+}
+
+impl Subscriber for SpanNames {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        self.names.lock().unwrap().insert(span.metadata().name());
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn spans_emitted_for_one_frame() {
+    let names = Arc::new(Mutex::new(HashSet::new()));
+    let subscriber = SpanNames {
+        names: Arc::clone(&names),
+        next_id: AtomicU64::new(0),
+    };
+
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(vec![0x18, 0xFE])); // JR $
+
+    tracing::subscriber::with_default(subscriber, || {
+        while !mmu.ppu.frame_ready() {
+            cpu.step(&mut mmu);
+        }
+    });
+
+    let names = names.lock().unwrap();
+    for name in ["Cpu::step", "Ppu::step", "Apu::step"] {
+        assert!(names.contains(name), "missing span {name}");
+    }
+}
@@ -0,0 +1,50 @@
+use vibeEmu::screenshot::{ScreenshotRecorder, frame_hash};
+
+#[test]
+fn writes_every_nth_frame() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut rec = ScreenshotRecorder::new(dir.path(), 5).unwrap();
+    let mut pixels = vec![0u32; 160 * 144];
+
+    for frame in 0..20u32 {
+        pixels[0] = frame;
+        rec.frame(&pixels, 160, 144).unwrap();
+    }
+
+    assert_eq!(rec.written(), 4);
+    let mut names: Vec<String> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["00000.png", "00001.png", "00002.png", "00003.png"]);
+
+    // Frame 10 (index 9) is the second capture.
+    let img = image::open(dir.path().join("00001.png")).unwrap().to_rgb8();
+    assert_eq!(img.dimensions(), (160, 144));
+    assert_eq!(img.get_pixel(0, 0).0, [0, 0, 9]);
+}
+
+#[test]
+fn stops_at_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut rec = ScreenshotRecorder::new(dir.path(), 1)
+        .unwrap()
+        .with_limit(2);
+    let pixels = vec![0u32; 4];
+    for _ in 0..5 {
+        rec.frame(&pixels, 2, 2).unwrap();
+    }
+    assert_eq!(rec.written(), 2);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[test]
+fn frame_hash_depends_on_pixels() {
+    let mut pixels = vec![0x00FF_FFFFu32; 160 * 144];
+    let white = frame_hash(&pixels);
+    assert_eq!(frame_hash(&pixels), white);
+
+    pixels[160 * 72 + 80] = 0;
+    assert_ne!(frame_hash(&pixels), white);
+}
@@ -0,0 +1,126 @@
+use vibeEmu::{
+    mmu::Mmu,
+    serial::{LinkPort, Printer},
+};
+
+#[test]
+fn transfer_completes_after_eight_bits() {
+    let mut mmu = Mmu::new();
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF01, 0x42);
+    mmu.write_byte(0xFF02, 0x81);
+
+    mmu.serial.step(8 * 512 - 1, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x08, 0);
+    assert_eq!(mmu.read_byte(0xFF02) & 0x80, 0x80);
+
+    mmu.serial.step(1, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x08, 0x08);
+    assert_eq!(mmu.read_byte(0xFF02) & 0x80, 0);
+    assert_eq!(mmu.read_byte(0xFF01), 0xFF);
+    assert_eq!(mmu.take_serial(), [0x42]);
+}
+
+#[test]
+fn clearing_sc_bit7_aborts_transfer() {
+    let mut mmu = Mmu::new();
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF01, 0x42);
+    mmu.write_byte(0xFF02, 0x81);
+    mmu.serial.step(4 * 512, &mut mmu.if_reg);
+    assert!(mmu.serial.transfer_active());
+
+    mmu.write_byte(0xFF02, 0x01);
+    mmu.serial.step(8 * 512, &mut mmu.if_reg);
+
+    assert!(!mmu.serial.transfer_active());
+    assert_eq!(mmu.if_reg & 0x08, 0);
+    assert_eq!(mmu.read_byte(0xFF01), 0x42);
+    assert!(mmu.take_serial().is_empty());
+}
+
+#[test]
+fn cgb_fast_clock() {
+    let mut mmu = Mmu::new_with_mode(true);
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF02, 0x83);
+    mmu.serial.step(8 * 16, &mut mmu.if_reg);
+    assert_eq!(mmu.if_reg & 0x08, 0x08);
+}
+
+/// Send one printer packet and return the two reply bytes.
+fn printer_packet(printer: &mut Printer, command: u8, data: &[u8]) -> [u8; 2] {
+    let mut packet = vec![command, 0x00, data.len() as u8, (data.len() >> 8) as u8];
+    packet.extend_from_slice(data);
+    let checksum = packet
+        .iter()
+        .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+    packet.extend_from_slice(&checksum.to_le_bytes());
+    for byte in [0x88, 0x33].into_iter().chain(packet) {
+        assert_eq!(printer.transfer(byte), 0x00);
+    }
+    [printer.transfer(0x00), printer.transfer(0x00)]
+}
+
+#[test]
+fn printer_prints_received_tiles() {
+    let mut printer = Printer::new(None);
+    assert_eq!(printer_packet(&mut printer, 0x01, &[]), [0x81, 0x00]);
+
+    // Two rows of tiles, all pixels colour 3.
+    let [_, status] = printer_packet(&mut printer, 0x04, &[0xFF; 0x280]);
+    assert_eq!(status & 0x08, 0x08);
+    printer_packet(&mut printer, 0x04, &[]);
+    assert!(printer.image().is_none());
+
+    // One sheet, no margins, palette E4.
+    let [_, status] = printer_packet(&mut printer, 0x02, &[0x01, 0x00, 0xE4, 0x40]);
+    assert_eq!(status, 0x00);
+    let image = printer.image().expect("PRINT should produce an image");
+    assert_eq!(image.dimensions(), (160, 16));
+    assert_eq!(image.get_pixel(0, 0).0, [0x00]);
+    assert_eq!(printer.prints(), 1);
+}
+
+#[test]
+fn printer_sends_prints_to_its_output() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut printer = Printer::new(Some(tx));
+    printer_packet(&mut printer, 0x04, &[0xFF; 16]);
+    assert!(rx.try_recv().is_err());
+    printer_packet(&mut printer, 0x02, &[0x01, 0x00, 0xE4, 0x40]);
+    let image = rx.try_recv().expect("PRINT should send the image");
+    assert_eq!(Some(&image), printer.image());
+}
+
+#[test]
+fn printer_flags_bad_checksum() {
+    let mut printer = Printer::new(None);
+    for byte in [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00] {
+        printer.transfer(byte);
+    }
+    assert_eq!(printer.transfer(0x00), 0x81);
+    assert_eq!(printer.transfer(0x00) & 0x01, 0x01);
+}
+
+#[test]
+fn serial_peer_exchanges_bytes() {
+    let sent = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let log = sent.clone();
+    let mut mmu = Mmu::new();
+    mmu.set_serial_peer(Box::new(move |byte| {
+        log.borrow_mut().push(byte);
+        byte ^ 0xFF
+    }));
+    mmu.if_reg = 0xE0;
+    mmu.write_byte(0xFF01, 0x3C);
+    mmu.write_byte(0xFF02, 0x81);
+
+    // The peer only sees the byte once all eight bits have shifted.
+    mmu.serial.step(7 * 512, &mut mmu.if_reg);
+    assert!(sent.borrow().is_empty());
+    mmu.serial.step(512, &mut mmu.if_reg);
+    assert_eq!(*sent.borrow(), [0x3C]);
+    assert_eq!(mmu.read_byte(0xFF01), 0xC3);
+    assert_eq!(mmu.if_reg & 0x08, 0x08);
+}
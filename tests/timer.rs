@@ -12,23 +12,19 @@ fn div_increment() {
 #[test]
 fn div_reset_edge_tick() {
     let mut t = Timer::new();
-    let mut if_reg = 0u8;
     t.div = 0x0200; // timer bit high
-    t.write(0xFF07, 0x04, &mut if_reg); // enable, freq 4096Hz (bit9)
-    t.write(0xFF04, 0, &mut if_reg); // reset DIV causes falling edge
+    t.write(0xFF07, 0x04); // enable, freq 4096Hz (bit9)
+    t.write(0xFF04, 0); // reset DIV causes falling edge
     assert_eq!(t.tima, 1);
-    assert_eq!(if_reg, 0);
 }
 
 #[test]
 fn tac_disable_edge_tick() {
     let mut t = Timer::new();
-    let mut if_reg = 0u8;
     t.div = 0x0200; // bit9 high
-    t.write(0xFF07, 0x04, &mut if_reg); // enable
-    t.write(0xFF07, 0x00, &mut if_reg); // disable -> falling edge
+    t.write(0xFF07, 0x04); // enable
+    t.write(0xFF07, 0x00); // disable -> falling edge
     assert_eq!(t.tima, 1);
-    assert_eq!(if_reg, 0);
 }
 
 #[test]
@@ -36,7 +32,7 @@ fn tima_increment_and_overflow() {
     let mut t = Timer::new();
     let mut if_reg = 0u8;
     // enable timer, freq 00 (4096 Hz -> bit 9)
-    t.write(0xFF07, 0x04, &mut if_reg); // enable
+    t.write(0xFF07, 0x04); // enable
     t.step(1024, &mut if_reg);
     assert_eq!(t.tima, 1);
     assert_eq!(if_reg, 0);
@@ -44,6 +40,119 @@ fn tima_increment_and_overflow() {
     t.tima = 0xFF;
     t.tma = 0xAB;
     t.step(1024, &mut if_reg);
+    t.step(4, &mut if_reg);
     assert_eq!(t.tima, 0xAB);
     assert_eq!(if_reg & 0x04, 0x04);
 }
+
+/// A timer at 262144 Hz whose next increment, 4 cycles away, overflows.
+fn about_to_overflow() -> (Timer, u8) {
+    let mut t = Timer::new();
+    let mut if_reg = 0u8;
+    t.write(0xFF07, 0x05);
+    t.set_internal_div(0x000C);
+    t.tima = 0xFF;
+    t.tma = 0xAB;
+    t.step(4, &mut if_reg);
+    (t, if_reg)
+}
+
+#[test]
+fn tima_reads_zero_for_one_cycle_after_overflow() {
+    let (mut t, mut if_reg) = about_to_overflow();
+    assert_eq!(t.read(0xFF05), 0x00);
+    assert_eq!(if_reg, 0);
+    t.step(3, &mut if_reg);
+    assert_eq!(t.read(0xFF05), 0x00);
+    assert_eq!(if_reg, 0);
+    t.step(1, &mut if_reg);
+    assert_eq!(t.read(0xFF05), 0xAB);
+    assert_eq!(if_reg & 0x04, 0x04);
+}
+
+#[test]
+fn tima_write_during_reload() {
+    // Before the reload the write wins and cancels the interrupt.
+    let (mut t, mut if_reg) = about_to_overflow();
+    t.write(0xFF05, 0x12);
+    t.step(8, &mut if_reg);
+    assert_eq!(t.read(0xFF05), 0x12);
+    assert_eq!(if_reg, 0);
+
+    // In the reload cycle it is dropped.
+    let (mut t, mut if_reg) = about_to_overflow();
+    t.step(4, &mut if_reg);
+    t.write(0xFF05, 0x12);
+    t.step(4, &mut if_reg);
+    assert_eq!(t.read(0xFF05), 0xAB);
+    assert_eq!(if_reg & 0x04, 0x04);
+
+    // A TMA write in the reload cycle reaches TIMA as well.
+    let (mut t, mut if_reg) = about_to_overflow();
+    t.step(4, &mut if_reg);
+    t.write(0xFF06, 0x34);
+    assert_eq!(t.read(0xFF05), 0x34);
+    t.step(4, &mut if_reg);
+    assert_eq!(t.read(0xFF05), 0x34);
+    assert_eq!(t.read(0xFF06), 0x34);
+}
+
+#[test]
+fn internal_div_positions_falling_edge() {
+    let mut t = Timer::new();
+    let mut if_reg = 0u8;
+    t.write(0xFF07, 0x05); // enable, 262144Hz (bit3)
+
+    // Bit 3 falls when the divider goes from 0x000F to 0x0010.
+    t.set_internal_div(0x000C);
+    assert_eq!(t.internal_div(), 0x000C);
+    assert_eq!(t.tima, 0);
+    t.step(3, &mut if_reg);
+    assert_eq!(t.tima, 0);
+    t.step(1, &mut if_reg);
+    assert_eq!(t.internal_div(), 0x0010);
+    assert_eq!(t.tima, 1);
+
+    // Jumping across an edge does not tick.
+    t.set_internal_div(0x0008);
+    t.set_internal_div(0x0000);
+    assert_eq!(t.tima, 1);
+}
+
+/// (TAC, T-cycles per TIMA increment) for each clock select.
+const TAC_PERIODS: [(u8, u32); 4] = [(0x04, 1024), (0x05, 16), (0x06, 64), (0x07, 256)];
+
+#[test]
+fn tac_frequencies_follow_divider_bits() {
+    for (tac, period) in TAC_PERIODS {
+        let mut t = Timer::new();
+        let mut if_reg = 0u8;
+        t.write(0xFF07, tac);
+        t.step(period as u16 - 1, &mut if_reg);
+        assert_eq!(t.tima, 0, "TAC {tac:02X}");
+        t.step(1, &mut if_reg);
+        assert_eq!(t.tima, 1, "TAC {tac:02X}");
+        for _ in 0..200 {
+            t.step(period as u16, &mut if_reg);
+        }
+        assert_eq!(t.tima, 201, "TAC {tac:02X}");
+    }
+}
+
+#[test]
+fn div_write_glitch_per_frequency() {
+    for (tac, period) in TAC_PERIODS {
+        // Selected bit high: resetting DIV is a falling edge.
+        let mut t = Timer::new();
+        t.write(0xFF07, tac);
+        t.set_internal_div(period as u16 / 2);
+        t.write(0xFF04, 0x12);
+        assert_eq!(t.tima, 1, "TAC {tac:02X}");
+        assert_eq!(t.read(0xFF04), 0);
+
+        // Selected bit low: no extra tick.
+        t.set_internal_div(period as u16 / 2 - 1);
+        t.write(0xFF04, 0x12);
+        assert_eq!(t.tima, 1, "TAC {tac:02X}");
+    }
+}
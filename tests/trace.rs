@@ -0,0 +1,71 @@
+use vibeEmu::{
+    cartridge::Cartridge,
+    cpu::Cpu,
+    mmu::Mmu,
+    trace::{TraceComparator, TraceEntry},
+};
+
+fn setup() -> (Cpu, Mmu) {
+    let program = vec![
+        0x06, 0x12, // LD B,0x12
+        0x0E, 0x34, // LD C,0x34
+        0x3E, 0x56, // LD A,0x56
+        0x00, // NOP
+    ];
+    let mut cpu = Cpu::new();
+    cpu.pc = 0;
+    let mut mmu = Mmu::new();
+    mmu.load_cart(Cartridge::load(program));
+    (cpu, mmu)
+}
+
+/// Run the program against `reference`, returning the divergent line if any.
+fn run(reference: &str) -> Option<usize> {
+    let (mut cpu, mut mmu) = setup();
+    let mut tracer = TraceComparator::from_text(reference);
+    for _ in 0..4 {
+        if let Err(d) = tracer.check(&cpu, &mut mmu) {
+            return Some(d.line);
+        }
+        cpu.step(&mut mmu);
+    }
+    assert!(tracer.finished());
+    None
+}
+
+#[test]
+fn parse_single_register_format() {
+    let e = TraceEntry::parse(
+        "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02",
+    )
+    .unwrap();
+    assert_eq!(e.af, Some(0x01B0));
+    assert_eq!(e.bc, Some(0x0013));
+    assert_eq!(e.hl, Some(0x014D));
+    assert_eq!(e.pc, Some(0x0100));
+    assert_eq!(e.opcode, Some(0x00));
+    assert!(TraceEntry::parse("# comment").is_none());
+}
+
+#[test]
+fn matching_trace() {
+    let reference = "\
+PC:0000 OP:06 AF:01B0 BC:0013 DE:00D8 HL:014D SP:FFFE
+PC:0002 OP:0E AF:01B0 BC:1213 DE:00D8 HL:014D SP:FFFE
+PC:0004 OP:3E AF:01B0 BC:1234 DE:00D8 HL:014D SP:FFFE
+PC:0006 OP:00 AF:56B0 BC:1234 DE:00D8 HL:014D SP:FFFE
+";
+    assert_eq!(run(reference), None);
+}
+
+#[test]
+fn divergent_trace() {
+    let reference = "\
+; header line is ignored
+PC:0000 OP:06 AF:01B0 BC:0013 DE:00D8 HL:014D SP:FFFE
+PC:0002 OP:0E AF:01B0 BC:1213 DE:00D8 HL:014D SP:FFFE
+PC:0004 OP:3E AF:01B0 BC:1299 DE:00D8 HL:014D SP:FFFE
+PC:0006 OP:00 AF:56B0 BC:1234 DE:00D8 HL:014D SP:FFFE
+";
+    assert_eq!(run(reference), Some(4));
+}
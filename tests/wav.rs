@@ -0,0 +1,30 @@
+use vibeEmu::{apu::Apu, wav::WavRecorder};
+
+#[test]
+fn drained_samples_make_a_valid_wav() {
+    let mut apu = Apu::new();
+    apu.write_reg(0xFF16, 0x80);
+    apu.write_reg(0xFF17, 0xF0);
+    apu.write_reg(0xFF19, 0x80);
+    for _ in 0..1000 {
+        apu.step(100);
+    }
+    let samples = apu.drain_samples();
+    assert!(!samples.is_empty());
+    assert_eq!(samples.len() % 2, 0);
+    assert_eq!(apu.buffered_samples(), 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("out.wav");
+    let mut wav = WavRecorder::create(&path, apu.sample_rate()).unwrap();
+    wav.write(&samples).unwrap();
+    wav.finish().unwrap();
+
+    let reader = hound::WavReader::open(&path).unwrap();
+    let spec = reader.spec();
+    assert_eq!(spec.sample_rate, apu.sample_rate());
+    assert_eq!(spec.channels, 2);
+    assert_eq!(spec.bits_per_sample, 16);
+    let read: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+    assert_eq!(read, samples);
+}